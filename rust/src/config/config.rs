@@ -1,9 +1,21 @@
 #![allow(non_camel_case_types, non_snake_case)]
-use std::net::{IpAddr, SocketAddr};
+use std::{
+    fmt, fs,
+    net::{IpAddr, SocketAddr, ToSocketAddrs},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
 
+use mediasoup::{prelude::ListenIp, rtp_parameters::RtpCodecCapability, worker::WorkerLogTag};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::{
+    handlers::codecs::media_codecs,
+    utils::{arg::Args, codec::DEFAULT_MAX_FRAME_SIZE},
+};
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Region {
     pub syncToken: String,
@@ -18,38 +30,610 @@ pub struct Prefixes {
     //pub newwork_border_group: String,
 }
 
+// how a router is picked among the routers of a room when a transport is created
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum LoadBalancing {
+    RoundRobin,
+    LeastTransports,
+    LeastCpu,
+}
+
+impl FromStr for LoadBalancing {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "round-robin" => Ok(LoadBalancing::RoundRobin),
+            "least-transports" => Ok(LoadBalancing::LeastTransports),
+            "least-cpu" => Ok(LoadBalancing::LeastCpu),
+            _ => Err(format!(
+                "unknown load balancing strategy {}, expected round-robin, least-transports or least-cpu",
+                s
+            )),
+        }
+    }
+}
+
+// how the routers of a room are spread over the workers: one router on every
+// worker up front, or a first router on one worker and another one each time
+// the routers of the room carry router_consumer_threshold consumers
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum RouterPlacement {
+    AllWorkers,
+    Lazy,
+}
+
+impl FromStr for RouterPlacement {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "all-workers" => Ok(RouterPlacement::AllWorkers),
+            "lazy" => Ok(RouterPlacement::Lazy),
+            _ => Err(format!(
+                "unknown router placement {}, expected all-workers or lazy",
+                s
+            )),
+        }
+    }
+}
+
+// which side of the media relay this node serves
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    ingress,
+    egress,
+    both,
+}
+
+impl FromStr for Mode {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ingress" => Ok(Mode::ingress),
+            "egress" => Ok(Mode::egress),
+            "both" => Ok(Mode::both),
+            _ => Err(format!(
+                "unknown mode {}, expected ingress, egress or both",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+// Settings read from the toml file given with --config, every key is optional
+// and overridden by the matching environment variable or command line flag.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    pub url: Option<String>,
+    pub mode: Option<Mode>,
+    pub traverse_nat: Option<bool>,
+    pub announce_ip: Option<IpAddr>,
+    pub listen_ip: Option<IpAddr>,
+    pub workers: Option<i32>,
+    pub region: Option<String>,
+    pub webrtc_port: Option<u16>,
+    pub load_balancing: Option<LoadBalancing>,
+    pub router_placement: Option<RouterPlacement>,
+    pub router_consumer_threshold: Option<u32>,
+    pub metrics_port: Option<u16>,
+    pub heartbeat_interval: Option<u64>,
+    pub max_frame_size: Option<usize>,
+    pub tls: Option<bool>,
+    pub tls_ca: Option<PathBuf>,
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+    pub tls_server_name: Option<String>,
+    pub auth_secret: Option<String>,
+    pub drain_timeout: Option<u64>,
+    pub room_idle_timeout: Option<u64>,
+    pub max_rooms: Option<u32>,
+    pub max_peers_per_room: Option<u32>,
+    pub max_video_producers_per_room: Option<u32>,
+    pub max_consumers_per_transport: Option<u32>,
+    pub audio_level_interval: Option<u16>,
+    pub interest_audio_distance: Option<f32>,
+    pub interest_video_distance: Option<f32>,
+    pub interest_video_low_distance: Option<f32>,
+    pub interest_interval: Option<u64>,
+    pub validate_movement: Option<bool>,
+    pub movement_snapshot: Option<bool>,
+    pub data_max_messages_per_sec: Option<u32>,
+    pub data_max_bytes_per_sec: Option<u64>,
+    pub data_max_message_size: Option<usize>,
+    pub data_throttle_seconds: Option<u32>,
+    pub data_pause_seconds: Option<u64>,
+    pub recording_dir: Option<PathBuf>,
+    pub stats_interval: Option<u64>,
+    pub initial_outgoing_bitrate: Option<u32>,
+    pub max_outgoing_bitrate: Option<u32>,
+    pub worker_log_tags: Option<Vec<String>>,
+    pub codecs: Option<Vec<RtpCodecCapability>>,
+}
+
+impl ConfigFile {
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("cannot read config file {}: {}", path.display(), e))?;
+        Self::parse(&content).map_err(|e| format!("invalid config file {}: {}", path.display(), e))
+    }
+
+    pub fn parse(content: &str) -> Result<Self, String> {
+        toml::from_str(content).map_err(|e| e.to_string())
+    }
+}
+
+// Signaling connection over TLS, certificates are PEM files.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TlsConfig {
+    // CA bundle to verify signaling with, the webpki roots when missing
+    pub ca: Option<PathBuf>,
+    // client certificate and key presented to signaling
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+    pub server_name: String,
+}
+
+// Server-side interest management from avatar positions, distances are in
+// world units between the consuming peer and the producing peer.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InterestConfig {
+    // pause audio consumers beyond this distance
+    pub audio_distance: Option<f32>,
+    // pause video consumers beyond this distance
+    pub video_distance: Option<f32>,
+    // lower video consumers to their lowest spatial layer beyond this distance
+    pub video_low_distance: Option<f32>,
+    // how often consumers are checked against the latest positions
+    pub interval: Duration,
+}
+
+// Limits on what each peer may send on one data channel, checked every second.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DataLimits {
+    pub messages_per_sec: Option<u32>,
+    pub bytes_per_sec: Option<u64>,
+    // larger messages are always dropped
+    pub max_message_size: Option<usize>,
+    // seconds in a row over the limits before the channel is paused
+    pub throttle_seconds: u32,
+    // how long a paused channel drops everything
+    pub pause: Duration,
+}
+
+// Caps past which rooms, peers, video producers and consumers are rejected
+// with limitReached, None leaves it unlimited.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RoomLimits {
+    pub max_rooms: Option<u32>,
+    pub max_peers: Option<u32>,
+    pub max_video_producers: Option<u32>,
+    pub max_consumers_per_transport: Option<u32>,
+}
+
 #[derive(Clone, Debug)]
 pub struct Config {
     pub ingress: Option<Uuid>,
     pub egress: Option<Uuid>,
+    pub mode: Mode,
     pub announceip: IpAddr,
+    pub listen_ip: IpAddr,
     pub server_address: SocketAddr,
     pub workers: i32,
     pub region: String,
-    pub traversenat: String,
+    pub traverse_nat: bool,
     pub webrtc_port: u16,
+    pub load_balancing: LoadBalancing,
+    pub router_placement: RouterPlacement,
+    // consumers on every router of a room before a lazy room gets another one
+    pub router_consumer_threshold: u32,
+    pub metrics_port: Option<u16>,
+    pub heartbeat_interval: Duration,
+    pub max_frame_size: usize,
+    pub tls: Option<TlsConfig>,
+    // shared with signaling to authenticate each other before registering
+    pub auth_secret: Option<String>,
+    // how long a draining node waits for its rooms before exiting
+    pub drain_timeout: Duration,
+    // how long a room without transports is kept, None keeps it until
+    // destroyRouterGroup
+    pub room_idle_timeout: Option<Duration>,
+    pub room_limits: RoomLimits,
+    // milliseconds between audioLevels messages of a room
+    pub audio_level_interval: u16,
+    // None unless at least one interest distance is set
+    pub interest: Option<InterestConfig>,
+    // drop malformed movement packets instead of fanning them out
+    pub validate_movement: bool,
+    // keep the last movement frame of each peer for movementSnapshot
+    pub movement_snapshot: bool,
+    // None unless one of the data channel limits is set
+    pub data_limits: Option<DataLimits>,
+    // None disables startRecording
+    pub recording_dir: Option<PathBuf>,
+    // None disables the periodic peerStats push
+    pub stats_interval: Option<Duration>,
+    pub initial_outgoing_bitrate: u32,
+    pub max_outgoing_bitrate: u32,
+    pub worker_log_tags: Vec<WorkerLogTag>,
+    pub media_codecs: Vec<RtpCodecCapability>,
 }
 
 impl Config {
-    pub fn new(
-        ingress: Option<Uuid>,
-        egress: Option<Uuid>,
-        announceip: IpAddr,
-        server_address: SocketAddr,
-        workers: i32,
-        region: String,
-        traversenat: String,
-        webrtc_port: u16,
-    ) -> Self {
-        Self {
+    // merge command line (which already carries the environment) over the file,
+    // falling back to defaults, and validate the result
+    pub fn from_sources(args: Args, file: ConfigFile) -> Result<Self, String> {
+        let url = args.url.or(file.url).ok_or(
+            "missing signaling server url, set url in the config file, FRAME_URL or --url",
+        )?;
+        let server_address = url
+            .to_socket_addrs()
+            .map_err(|e| format!("cannot resolve signaling server url {}: {}", url, e))?
+            .find(|addr| addr.is_ipv4())
+            .ok_or(format!("signaling server url {} has no ipv4 address", url))?;
+
+        let mode = match args.mode.or(file.mode) {
+            Some(mode) => mode,
+            // -i / -e flags kept for existing launch scripts
+            None => match (args.ingress, args.egress) {
+                (Some(true), Some(true)) => Mode::both,
+                (Some(true), _) => Mode::ingress,
+                (_, Some(true)) => Mode::egress,
+                _ => {
+                    return Err(
+                        "missing mode, set mode = ingress|egress|both in the config file, FRAME_MODE or --mode"
+                            .to_string(),
+                    )
+                }
+            },
+        };
+        let node_id = Uuid::new_v4();
+        let ingress = match mode {
+            Mode::ingress | Mode::both => Some(node_id),
+            Mode::egress => None,
+        };
+        let egress = match mode {
+            Mode::egress | Mode::both => Some(node_id),
+            Mode::ingress => None,
+        };
+
+        let traverse_nat = args.traverse_nat.or(file.traverse_nat).unwrap_or(false);
+        let listen_ip = match args.listen_ip.or(file.listen_ip) {
+            Some(ip) => ip,
+            None if traverse_nat => IpAddr::from([0, 0, 0, 0]),
+            None => local_ipaddress::get()
+                .and_then(|ip| ip.parse().ok())
+                .ok_or("cannot detect the local ip address, set listen_ip")?,
+        };
+        let announceip = match args.announceip.or(file.announce_ip) {
+            Some(ip) => ip,
+            None if traverse_nat => {
+                return Err("traverse_nat is set but announce_ip is missing".to_string())
+            }
+            None => listen_ip,
+        };
+
+        let workers = args.workers.or(file.workers).unwrap_or(1);
+        if workers < 1 {
+            return Err(format!("workers must be at least 1, got {}", workers));
+        }
+        let webrtc_port = args.port_transport.or(file.webrtc_port).unwrap_or(10000);
+        if webrtc_port as i32 + workers - 1 > u16::MAX as i32 {
+            return Err(format!(
+                "webrtc_port {} leaves no room for {} workers",
+                webrtc_port, workers
+            ));
+        }
+        let room_limits = RoomLimits {
+            max_rooms: args.max_rooms.or(file.max_rooms),
+            max_peers: args.max_peers_per_room.or(file.max_peers_per_room),
+            max_video_producers: args
+                .max_video_producers_per_room
+                .or(file.max_video_producers_per_room),
+            max_consumers_per_transport: args
+                .max_consumers_per_transport
+                .or(file.max_consumers_per_transport),
+        };
+        if room_limits.max_rooms == Some(0)
+            || room_limits.max_peers == Some(0)
+            || room_limits.max_video_producers == Some(0)
+            || room_limits.max_consumers_per_transport == Some(0)
+        {
+            return Err("room limits must be at least 1".to_string());
+        }
+        let router_consumer_threshold = args
+            .router_consumer_threshold
+            .or(file.router_consumer_threshold)
+            .unwrap_or(400);
+        if router_consumer_threshold == 0 {
+            return Err("router_consumer_threshold must be at least 1".to_string());
+        }
+
+        let heartbeat_interval = args
+            .heartbeat_interval
+            .or(file.heartbeat_interval)
+            .unwrap_or(2);
+        if heartbeat_interval == 0 {
+            return Err("heartbeat_interval must be at least 1 second".to_string());
+        }
+        let max_frame_size = args
+            .max_frame_size
+            .or(file.max_frame_size)
+            .unwrap_or(DEFAULT_MAX_FRAME_SIZE);
+        if max_frame_size == 0 || max_frame_size > u32::MAX as usize - 4 {
+            return Err(format!("max_frame_size {} is out of range", max_frame_size));
+        }
+        let tls_enabled = args.tls.or(file.tls).unwrap_or(false);
+        let tls = if tls_enabled {
+            let cert = args.tls_cert.or(file.tls_cert);
+            let key = args.tls_key.or(file.tls_key);
+            if cert.is_some() != key.is_some() {
+                return Err("tls_cert and tls_key must be set together".to_string());
+            }
+            let server_name = match args.tls_server_name.or(file.tls_server_name) {
+                Some(name) => name,
+                None => url
+                    .rsplit_once(':')
+                    .map(|(host, _)| host)
+                    .unwrap_or(&url)
+                    .to_string(),
+            };
+            Some(TlsConfig {
+                ca: args.tls_ca.or(file.tls_ca),
+                cert,
+                key,
+                server_name,
+            })
+        } else {
+            None
+        };
+        let auth_secret = args.auth_secret.or(file.auth_secret);
+        if auth_secret.as_ref().map(|s| s.is_empty()).unwrap_or(false) {
+            return Err("auth_secret must not be empty".to_string());
+        }
+
+        let audio_level_interval = args
+            .audio_level_interval
+            .or(file.audio_level_interval)
+            .unwrap_or(800);
+        if audio_level_interval < 250 {
+            return Err(format!(
+                "audio_level_interval must be at least 250ms, got {}",
+                audio_level_interval
+            ));
+        }
+
+        let interest = InterestConfig {
+            audio_distance: args
+                .interest_audio_distance
+                .or(file.interest_audio_distance),
+            video_distance: args
+                .interest_video_distance
+                .or(file.interest_video_distance),
+            video_low_distance: args
+                .interest_video_low_distance
+                .or(file.interest_video_low_distance),
+            interval: Duration::from_millis(
+                args.interest_interval
+                    .or(file.interest_interval)
+                    .unwrap_or(500),
+            ),
+        };
+        for distance in [
+            interest.audio_distance,
+            interest.video_distance,
+            interest.video_low_distance,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            if !distance.is_finite() || distance <= 0.0 {
+                return Err(format!(
+                    "interest distances must be positive, got {}",
+                    distance
+                ));
+            }
+        }
+        if let (Some(low), Some(video)) = (interest.video_low_distance, interest.video_distance) {
+            if low >= video {
+                return Err(format!(
+                    "interest_video_low_distance {} must be lower than interest_video_distance {}",
+                    low, video
+                ));
+            }
+        }
+        if interest.interval.is_zero() {
+            return Err("interest_interval must be at least 1ms".to_string());
+        }
+        let interest = if interest.audio_distance.is_some()
+            || interest.video_distance.is_some()
+            || interest.video_low_distance.is_some()
+        {
+            Some(interest)
+        } else {
+            None
+        };
+
+        let data_limits = DataLimits {
+            messages_per_sec: args
+                .data_max_messages_per_sec
+                .or(file.data_max_messages_per_sec),
+            bytes_per_sec: args.data_max_bytes_per_sec.or(file.data_max_bytes_per_sec),
+            max_message_size: args.data_max_message_size.or(file.data_max_message_size),
+            throttle_seconds: args
+                .data_throttle_seconds
+                .or(file.data_throttle_seconds)
+                .unwrap_or(5),
+            pause: Duration::from_secs(
+                args.data_pause_seconds
+                    .or(file.data_pause_seconds)
+                    .unwrap_or(10),
+            ),
+        };
+        if data_limits.messages_per_sec == Some(0)
+            || data_limits.bytes_per_sec == Some(0)
+            || data_limits.max_message_size == Some(0)
+        {
+            return Err("data channel limits must be at least 1".to_string());
+        }
+        if data_limits.throttle_seconds == 0 {
+            return Err("data_throttle_seconds must be at least 1".to_string());
+        }
+        let data_limits = if data_limits.messages_per_sec.is_some()
+            || data_limits.bytes_per_sec.is_some()
+            || data_limits.max_message_size.is_some()
+        {
+            Some(data_limits)
+        } else {
+            None
+        };
+
+        let stats_interval = args.stats_interval.or(file.stats_interval);
+        if stats_interval == Some(0) {
+            return Err("stats_interval must be at least 1 second".to_string());
+        }
+
+        let initial_outgoing_bitrate = args
+            .initial_outgoing_bitrate
+            .or(file.initial_outgoing_bitrate)
+            .unwrap_or(600000);
+        let max_outgoing_bitrate = args
+            .max_outgoing_bitrate
+            .or(file.max_outgoing_bitrate)
+            .unwrap_or(3500000);
+        if max_outgoing_bitrate < initial_outgoing_bitrate {
+            return Err(format!(
+                "max_outgoing_bitrate {} is lower than initial_outgoing_bitrate {}",
+                max_outgoing_bitrate, initial_outgoing_bitrate
+            ));
+        }
+
+        let worker_log_tags = match file.worker_log_tags {
+            Some(tags) => tags
+                .iter()
+                .map(|tag| parse_log_tag(tag))
+                .collect::<Result<Vec<_>, _>>()?,
+            None => default_log_tags(),
+        };
+        let media_codecs = file.codecs.unwrap_or_else(media_codecs);
+        if media_codecs.is_empty() {
+            return Err("codecs must list at least one codec".to_string());
+        }
+
+        Ok(Self {
             ingress,
             egress,
+            mode,
             announceip,
+            listen_ip,
             server_address,
             workers,
-            region,
-            traversenat,
+            region: args
+                .region
+                .or(file.region)
+                .unwrap_or_else(|| "local".to_string()),
+            traverse_nat,
             webrtc_port,
+            load_balancing: args
+                .load_balancing
+                .or(file.load_balancing)
+                .unwrap_or(LoadBalancing::RoundRobin),
+            router_placement: args
+                .router_placement
+                .or(file.router_placement)
+                .unwrap_or(RouterPlacement::AllWorkers),
+            router_consumer_threshold,
+            metrics_port: args.metrics_port.or(file.metrics_port),
+            heartbeat_interval: Duration::from_secs(heartbeat_interval),
+            max_frame_size,
+            tls,
+            auth_secret,
+            drain_timeout: Duration::from_secs(
+                args.drain_timeout.or(file.drain_timeout).unwrap_or(300),
+            ),
+            room_idle_timeout: match args
+                .room_idle_timeout
+                .or(file.room_idle_timeout)
+                .unwrap_or(0)
+            {
+                0 => None,
+                seconds => Some(Duration::from_secs(seconds)),
+            },
+            room_limits,
+            audio_level_interval,
+            interest,
+            validate_movement: args
+                .validate_movement
+                .or(file.validate_movement)
+                .unwrap_or(false),
+            movement_snapshot: args
+                .movement_snapshot
+                .or(file.movement_snapshot)
+                .unwrap_or(true),
+            data_limits,
+            recording_dir: args.recording_dir.or(file.recording_dir),
+            stats_interval: stats_interval.map(Duration::from_secs),
+            initial_outgoing_bitrate,
+            max_outgoing_bitrate,
+            worker_log_tags,
+            media_codecs,
+        })
+    }
+
+    // ip pipe transports and webrtc servers listen on
+    pub fn listen_ip(&self) -> ListenIp {
+        ListenIp {
+            ip: self.listen_ip,
+            announced_ip: if self.traverse_nat {
+                Some(self.announceip)
+            } else {
+                None
+            },
         }
     }
 }
+
+fn parse_log_tag(tag: &str) -> Result<WorkerLogTag, String> {
+    match tag {
+        "info" => Ok(WorkerLogTag::Info),
+        "ice" => Ok(WorkerLogTag::Ice),
+        "dtls" => Ok(WorkerLogTag::Dtls),
+        "rtp" => Ok(WorkerLogTag::Rtp),
+        "srtp" => Ok(WorkerLogTag::Srtp),
+        "rtcp" => Ok(WorkerLogTag::Rtcp),
+        "rtx" => Ok(WorkerLogTag::Rtx),
+        "bwe" => Ok(WorkerLogTag::Bwe),
+        "score" => Ok(WorkerLogTag::Score),
+        "simulcast" => Ok(WorkerLogTag::Simulcast),
+        "svc" => Ok(WorkerLogTag::Svc),
+        "sctp" => Ok(WorkerLogTag::Sctp),
+        "message" => Ok(WorkerLogTag::Message),
+        _ => Err(format!("unknown worker log tag {}", tag)),
+    }
+}
+
+fn default_log_tags() -> Vec<WorkerLogTag> {
+    vec![
+        WorkerLogTag::Info,
+        WorkerLogTag::Ice,
+        WorkerLogTag::Dtls,
+        WorkerLogTag::Rtp,
+        WorkerLogTag::Srtp,
+        WorkerLogTag::Rtcp,
+        WorkerLogTag::Rtx,
+        WorkerLogTag::Bwe,
+        WorkerLogTag::Score,
+        WorkerLogTag::Simulcast,
+        WorkerLogTag::Svc,
+        WorkerLogTag::Sctp,
+        WorkerLogTag::Message,
+    ]
+}
@@ -0,0 +1,145 @@
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{
+        config::config::{Config, ConfigFile, LoadBalancing, Mode, RoomLimits, RouterPlacement},
+        handlers::codecs::media_codecs,
+        utils::arg::Args,
+    };
+
+    fn example() -> ConfigFile {
+        let mut file = ConfigFile::parse(include_str!("../../config.example.toml")).unwrap();
+        file.url = Some("127.0.0.1:1188".to_string());
+        file.listen_ip = Some("10.0.0.5".parse().unwrap());
+        file
+    }
+
+    #[test]
+    fn test_example_config_matches_defaults() {
+        let config = Config::from_sources(Args::default(), example()).unwrap();
+        assert_eq!(config.mode, Mode::ingress);
+        assert!(config.ingress.is_some() && config.egress.is_none());
+        assert_eq!(config.workers, 4);
+        assert_eq!(config.load_balancing, LoadBalancing::RoundRobin);
+        assert_eq!(config.router_placement, RouterPlacement::AllWorkers);
+        assert_eq!(config.router_consumer_threshold, 400);
+        assert_eq!(config.initial_outgoing_bitrate, 600000);
+        assert_eq!(config.max_outgoing_bitrate, 3500000);
+        assert_eq!(config.media_codecs, media_codecs());
+        assert_eq!(config.listen_ip().announced_ip, None);
+        assert!(config.tls.is_none() && config.auth_secret.is_none());
+        assert!(config.interest.is_none());
+        assert!(config.data_limits.is_none());
+        assert!(config.stats_interval.is_none());
+        assert!(config.room_idle_timeout.is_none());
+        assert_eq!(config.room_limits, RoomLimits::default());
+    }
+
+    #[test]
+    fn test_router_placement_from_args() {
+        let args = Args {
+            router_placement: Some("lazy".parse().unwrap()),
+            ..Default::default()
+        };
+        let config = Config::from_sources(args, example()).unwrap();
+        assert_eq!(config.router_placement, RouterPlacement::Lazy);
+        assert!("spread".parse::<RouterPlacement>().is_err());
+    }
+
+    #[test]
+    fn test_zero_router_consumer_threshold_is_rejected() {
+        let mut file = example();
+        file.router_consumer_threshold = Some(0);
+        assert!(Config::from_sources(Args::default(), file).is_err());
+    }
+
+    #[test]
+    fn test_room_limits() {
+        let mut file = example();
+        file.max_peers_per_room = Some(50);
+        let args = Args {
+            max_consumers_per_transport: Some(100),
+            ..Default::default()
+        };
+        let config = Config::from_sources(args, file).unwrap();
+        assert_eq!(config.room_limits.max_peers, Some(50));
+        assert_eq!(config.room_limits.max_consumers_per_transport, Some(100));
+        assert!(config.room_limits.max_rooms.is_none());
+
+        let mut file = example();
+        file.max_rooms = Some(0);
+        assert!(Config::from_sources(Args::default(), file).is_err());
+    }
+
+    #[test]
+    fn test_zero_room_idle_timeout_keeps_rooms() {
+        let mut file = example();
+        file.room_idle_timeout = Some(300);
+        let config = Config::from_sources(Args::default(), file).unwrap();
+        assert_eq!(config.room_idle_timeout, Some(Duration::from_secs(300)));
+
+        let args = Args {
+            room_idle_timeout: Some(0),
+            ..Default::default()
+        };
+        let mut file = example();
+        file.room_idle_timeout = Some(300);
+        let config = Config::from_sources(args, file).unwrap();
+        assert!(config.room_idle_timeout.is_none());
+    }
+
+    #[test]
+    fn test_command_line_overrides_file() {
+        let args = Args {
+            mode: Some(Mode::both),
+            workers: Some(2),
+            ..Default::default()
+        };
+        let config = Config::from_sources(args, example()).unwrap();
+        assert_eq!(config.workers, 2);
+        assert_eq!(config.ingress, config.egress);
+        assert!(config.ingress.is_some());
+    }
+
+    #[test]
+    fn test_invalid_config_is_rejected() {
+        assert!(ConfigFile::parse("workers = \"four\"").is_err());
+        assert!(ConfigFile::parse("unknown_key = 1").is_err());
+        assert!(ConfigFile::parse("worker_log_tags = [\"nope\"]").is_ok());
+
+        let mut file = example();
+        file.worker_log_tags = Some(vec!["nope".to_string()]);
+        assert!(Config::from_sources(Args::default(), file).is_err());
+
+        let mut file = example();
+        file.max_outgoing_bitrate = Some(1000);
+        assert!(Config::from_sources(Args::default(), file).is_err());
+
+        let mut file = example();
+        file.traverse_nat = Some(true);
+        assert!(Config::from_sources(Args::default(), file).is_err());
+
+        let mut file = example();
+        file.tls = Some(true);
+        file.tls_cert = Some("node.pem".into());
+        assert!(Config::from_sources(Args::default(), file).is_err());
+
+        let mut file = example();
+        file.interest_video_distance = Some(10.0);
+        file.interest_video_low_distance = Some(20.0);
+        assert!(Config::from_sources(Args::default(), file).is_err());
+
+        let mut file = example();
+        file.data_max_messages_per_sec = Some(0);
+        assert!(Config::from_sources(Args::default(), file).is_err());
+
+        let mut file = example();
+        file.stats_interval = Some(0);
+        assert!(Config::from_sources(Args::default(), file).is_err());
+
+        let mut file = example();
+        file.mode = None;
+        assert!(Config::from_sources(Args::default(), file).is_err());
+    }
+}
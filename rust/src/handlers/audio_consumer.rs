@@ -3,24 +3,23 @@ use std::{collections::HashMap, ops::Deref, sync::Arc};
 
 use log::{error, info};
 use mediasoup::{
-    prelude::{AppData, Consumer, ConsumerId, ConsumerOptions},
-    rtp_parameters::RtpCapabilities,
+    prelude::{AppData, ConsumerOptions},
+    rtp_parameters::{MediaKind, RtpCapabilities},
     transport::Transport,
 };
-use tokio::sync::{mpsc::Sender, Mutex, RwLock};
+use tokio::sync::{Mutex, RwLock};
 use uuid::Uuid;
 
 use crate::{
+    handlers::error::HandlerError,
     models::{
         message::NewConsumerOptions,
-        sfu::{
-            AudioProducers, PeerAudioConsumed, PeerAudioConsumedData, Routers, Transport2Router,
-            Transports,
-        },
+        peer::{attach, forget_consumer, Peers},
+        sfu::{Routers, Transport2Router},
     },
     utils::{
         codec::{appData, MessageResponse, ResponseMessage},
-        utils::Mut,
+        utils::{Mut, Responder},
     },
 };
 
@@ -29,17 +28,14 @@ pub async fn consume_audio(
     consumer_peer: Uuid,
     producer_peers: Vec<Uuid>,
     rtpCaps: RtpCapabilities,
-    audio_producers: Arc<Mutex<AudioProducers>>,
-    peeraudioconsumed: Arc<Mutex<PeerAudioConsumed>>,
-    transports: Arc<Mut<Transports>>,
+    peers: Arc<Mut<Peers>>,
     transport2router: Arc<RwLock<Transport2Router>>,
     routers: Arc<Mutex<Routers>>,
-    consumers: Arc<Mutex<HashMap<ConsumerId, Consumer>>>,
-    sender: Sender<ResponseMessage>,
-) -> Result<(), String> {
+    sender: Responder,
+) -> Result<(), HandlerError> {
     let mut audio_announcement: HashMap<Uuid, Vec<NewConsumerOptions>> = HashMap::new();
     for peer in producer_peers.into_iter() {
-        let get_peer = audio_producers.lock().await.get(peer.clone());
+        let get_peer = peers.with(|p| p.get(peer).map(|p| p.producers_of(MediaKind::Audio)));
         if get_peer.is_none() {
             info!(
                 "Peer {:?} does not have video producer, skipping",
@@ -48,24 +44,29 @@ pub async fn consume_audio(
             continue;
         }
         for current_peer in get_peer.unwrap().into_iter() {
-            let peer_consumed = peeraudioconsumed
-                .lock()
-                .await
-                .find(consumer_peer, current_peer.id());
-            if !peer_consumed.is_empty() {
+            let peer_consumed = peers.with(|p| {
+                p.get(consumer_peer)
+                    .map(|c| c.consumes(current_peer.id()))
+                    .unwrap_or(false)
+            });
+            if peer_consumed {
                 println!("peer {:?} already consumed!", &peer);
                 continue;
             }
-            let get_transports = transports.with(|inner| inner.get(consumer_peer));
+            let get_transports = peers.with(|p| p.transport(consumer_peer));
             if get_transports.is_none() {
                 println!("cannot find transports");
-                return Err(String::from("cannot find transports"));
+                return Err(HandlerError::NotFound(String::from(
+                    "cannot find transports",
+                )));
             };
             let transport = get_transports.unwrap();
             let get_transport2router = transport2router.read().await.get(transport.clone().id());
             if get_transport2router.is_none() {
                 println!("cannot find transport2router");
-                return Err(String::from("cannot find transport2router"));
+                return Err(HandlerError::NotFound(String::from(
+                    "cannot find transport2router",
+                )));
             }
             let transport2router = get_transport2router.unwrap();
             let get_router = routers.lock().await.get(transport2router);
@@ -81,20 +82,20 @@ pub async fn consume_audio(
                 .clone();
             if get_router.is_none() {
                 println!("consumer peer transport not defined on this egress server");
-                return Err(format!(
+                return Err(HandlerError::NotFound(format!(
                     "Error: consumer peer transport not defined on this egress server. {:?}",
                     transport.clone()
-                ));
+                )));
             }
             let router = get_router.unwrap();
             if router.can_consume(&producer_id, &rtpCaps) {
                 let mut consumer_options = ConsumerOptions::new(producer_id, rtpCaps.clone());
                 consumer_options.paused = false;
                 consumer_options.app_data = AppData::new(app_data.clone());
-                let newMediaConsumer = transport
-                    .consume(consumer_options)
-                    .await
-                    .map_err(|error| format!("Failed consume: {:?}", error))?;
+                let newMediaConsumer =
+                    transport.consume(consumer_options).await.map_err(|error| {
+                        HandlerError::Mediasoup(format!("Failed consume: {:?}", error))
+                    })?;
 
                 newMediaConsumer.on_producer_pause(|| {}).detach();
 
@@ -122,26 +123,21 @@ pub async fn consume_audio(
                         .deref()
                         .clone(),
                 };
-                let peer_consume_data = PeerAudioConsumedData {
-                    peer_id: consumer_peer,
-                    produce_id: producer_id,
-                    consumer_id: newMediaConsumer.id(),
-                };
-                let mut peer_audio_consume_gaurd = peeraudioconsumed.lock().await;
-                peer_audio_consume_gaurd.create(peer_consume_data);
                 audio_announcement
                     .entry(peer)
                     .or_default()
                     .push(video_consumer_options);
-
-                let mut consumer_guard = consumers.lock().await;
-                consumer_guard.insert(newMediaConsumer.id(), newMediaConsumer);
+                forget_consumer(&peers, &newMediaConsumer);
+                attach(&peers, consumer_peer, newMediaConsumer, |peer, consumer| {
+                    peer.consumers.push(consumer)
+                })?;
             }
         }
     }
     if audio_announcement.len() > 0 {
         let reply_consumer = ResponseMessage::OutgoingCommunication {
             ws: Some(wsid.clone()),
+            requestId: None,
             communication: MessageResponse::audioAnnouncement {
                 data: audio_announcement.clone(),
             },
@@ -0,0 +1,256 @@
+use std::{collections::HashMap, num::NonZeroU16, sync::Arc, time::Duration};
+
+use log::{debug, error};
+use mediasoup::{
+    active_speaker_observer::ActiveSpeakerObserverOptions,
+    audio_level_observer::AudioLevelObserverOptions,
+    prelude::{RtpObserver, RtpObserverAddProducerOptions},
+    producer::{Producer, ProducerId},
+    router::{PipeToRouterOptions, Router},
+};
+use tokio::{
+    select,
+    sync::{
+        mpsc::{unbounded_channel, Sender, UnboundedReceiver},
+        Mutex,
+    },
+    time::interval,
+};
+use uuid::Uuid;
+
+use crate::{
+    handlers::error::HandlerError,
+    models::sfu::{AudioObservers, RoomAudioObserver},
+    utils::{
+        codec::{ActiveSpeakerData, AudioLevel, AudioLevelsData, MessageResponse, ResponseMessage},
+        utils::Mut,
+    },
+};
+
+// loudest producers reported in each audioLevels message
+const MAX_AUDIO_LEVELS: u16 = 8;
+
+enum AudioEvent {
+    Volumes(Vec<(ProducerId, i8)>),
+    Silence,
+    DominantSpeaker(ProducerId),
+}
+
+// Add an audio producer of `room` to the room's observers, creating them on
+// `router` for the first one. `piped` tells the producer was already piped to
+// every router of the room, as relayed producers are on egress nodes.
+pub async fn observe_audio_producer(
+    audio_observers: Arc<Mutex<AudioObservers>>,
+    signaling: Arc<Mut<Option<Sender<ResponseMessage>>>>,
+    audio_level_interval: u16,
+    room: String,
+    peer_id: Uuid,
+    producer: Producer,
+    router: Router,
+    piped: bool,
+) -> Result<(), HandlerError> {
+    let mut observers = audio_observers.lock().await;
+    let observer = match observers.get(&room) {
+        Some(observer) => observer,
+        None => {
+            let observer = create_room_observer(
+                room.clone(),
+                router.clone(),
+                signaling,
+                audio_level_interval,
+            )
+            .await?;
+            observers.create(room.clone(), observer.clone());
+            observer
+        }
+    };
+    let producer_id = producer.id();
+    observer.peers.with(|p| p.insert(producer_id, peer_id));
+
+    if !piped && observer.router.id() != router.id() {
+        let pair = router
+            .pipe_producer_to_router(
+                producer_id,
+                PipeToRouterOptions::new(observer.router.clone()),
+            )
+            .await
+            .map_err(|error| {
+                HandlerError::Mediasoup(format!(
+                    "Failed to pipe audio producer to observer router: {}",
+                    error
+                ))
+            })?;
+        observer.pipes.with(|p| p.insert(producer_id, pair));
+    }
+    observer
+        .audio_level
+        .add_producer(RtpObserverAddProducerOptions::new(producer_id))
+        .await
+        .map_err(|error| {
+            HandlerError::Mediasoup(format!("Failed to observe audio level: {}", error))
+        })?;
+    observer
+        .active_speaker
+        .add_producer(RtpObserverAddProducerOptions::new(producer_id))
+        .await
+        .map_err(|error| {
+            HandlerError::Mediasoup(format!("Failed to observe active speaker: {}", error))
+        })?;
+
+    let peers = observer.peers.clone();
+    let pipes = observer.pipes.clone();
+    producer
+        .on_close(move || {
+            peers.with(|p| p.remove(&producer_id));
+            pipes.with(|p| p.remove(&producer_id));
+        })
+        .detach();
+    Ok(())
+}
+
+async fn create_room_observer(
+    room: String,
+    router: Router,
+    signaling: Arc<Mut<Option<Sender<ResponseMessage>>>>,
+    audio_level_interval: u16,
+) -> Result<RoomAudioObserver, HandlerError> {
+    let mut options = AudioLevelObserverOptions::default();
+    options.max_entries = NonZeroU16::new(MAX_AUDIO_LEVELS).unwrap();
+    options.interval = audio_level_interval;
+    let audio_level = router
+        .create_audio_level_observer(options)
+        .await
+        .map_err(|error| {
+            HandlerError::Mediasoup(format!("Failed to create audio level observer: {}", error))
+        })?;
+    let active_speaker = router
+        .create_active_speaker_observer(ActiveSpeakerObserverOptions::default())
+        .await
+        .map_err(|error| {
+            HandlerError::Mediasoup(format!(
+                "Failed to create active speaker observer: {}",
+                error
+            ))
+        })?;
+
+    let (events_tx, events_rx) = unbounded_channel::<AudioEvent>();
+    {
+        let events_tx = events_tx.clone();
+        audio_level
+            .on_volumes(move |volumes| {
+                let volumes = volumes
+                    .iter()
+                    .map(|v| (v.producer.id(), v.volume))
+                    .collect();
+                let _ = events_tx.send(AudioEvent::Volumes(volumes));
+            })
+            .detach();
+    }
+    {
+        let events_tx = events_tx.clone();
+        audio_level
+            .on_silence(move || {
+                let _ = events_tx.send(AudioEvent::Silence);
+            })
+            .detach();
+    }
+    active_speaker
+        .on_dominant_speaker(move |speaker| {
+            let _ = events_tx.send(AudioEvent::DominantSpeaker(speaker.producer.id()));
+        })
+        .detach();
+
+    let peers = Arc::new(Mut::new(HashMap::new()));
+    tokio::spawn(forward_audio_events(
+        room,
+        events_rx,
+        peers.clone(),
+        signaling,
+        Duration::from_millis(audio_level_interval as u64),
+    ));
+    Ok(RoomAudioObserver {
+        router,
+        audio_level,
+        active_speaker,
+        peers,
+        pipes: Arc::new(Mut::new(HashMap::new())),
+    })
+}
+
+// Send the latest levels and speaker of the room at most once per interval,
+// until the observers are dropped with the room.
+async fn forward_audio_events(
+    room: String,
+    mut events: UnboundedReceiver<AudioEvent>,
+    peers: Arc<Mut<HashMap<ProducerId, Uuid>>>,
+    signaling: Arc<Mut<Option<Sender<ResponseMessage>>>>,
+    period: Duration,
+) {
+    let mut tick = interval(period);
+    let mut levels: Option<Vec<(ProducerId, i8)>> = None;
+    let mut speaker: Option<ProducerId> = None;
+    let mut sent_speaker: Option<ProducerId> = None;
+    loop {
+        select! {
+            event = events.recv() => match event {
+                Some(AudioEvent::Volumes(volumes)) => levels = Some(volumes),
+                Some(AudioEvent::Silence) => levels = Some(vec![]),
+                Some(AudioEvent::DominantSpeaker(producer_id)) => speaker = Some(producer_id),
+                None => return,
+            },
+            _ = tick.tick() => {
+                if let Some(volumes) = levels.take() {
+                    let levels = peers.with(|p| {
+                        volumes
+                            .iter()
+                            .filter_map(|(producer_id, volume)| {
+                                p.get(producer_id).map(|peer_id| AudioLevel {
+                                    peerId: *peer_id,
+                                    producerId: *producer_id,
+                                    volume: *volume,
+                                })
+                            })
+                            .collect()
+                    });
+                    send(&signaling, MessageResponse::audioLevels {
+                        data: AudioLevelsData { roomName: room.clone(), levels },
+                    })
+                    .await;
+                }
+                if speaker != sent_speaker {
+                    sent_speaker = speaker;
+                    let producer_id = speaker.unwrap();
+                    match peers.with(|p| p.get(&producer_id).copied()) {
+                        Some(peer_id) => {
+                            send(&signaling, MessageResponse::activeSpeaker {
+                                data: ActiveSpeakerData {
+                                    roomName: room.clone(),
+                                    peerId: peer_id,
+                                    producerId: producer_id,
+                                },
+                            })
+                            .await
+                        }
+                        None => debug!("active speaker {} already closed", producer_id),
+                    }
+                }
+            }
+        }
+    }
+}
+
+async fn send(signaling: &Arc<Mut<Option<Sender<ResponseMessage>>>>, message: MessageResponse) {
+    let sender = match signaling.with(|s| s.clone()) {
+        Some(sender) => sender,
+        // not connected to signaling, the next interval will carry fresh levels
+        None => return,
+    };
+    let msg = ResponseMessage::OutgoingCommunication {
+        ws: None,
+        requestId: None,
+        communication: message,
+    };
+    if let Err(e) = sender.send(msg).await {
+        error!("error sending audio event: {:?}", e);
+    }
+}
@@ -0,0 +1,410 @@
+use std::{borrow::Cow, collections::HashMap, ops::Deref, sync::Arc};
+
+use log::{debug, error, info};
+use mediasoup::{
+    data_structures::WebRtcMessage,
+    prelude::{
+        AppData, DataConsumer, DataConsumerOptions, DataProducer, DataProducerId,
+        DataProducerOptions, DirectTransport, DirectTransportOptions, Transport,
+    },
+    router::{Router, RouterId},
+};
+use tokio::sync::{mpsc::Sender, Mutex};
+use uuid::Uuid;
+
+use crate::{
+    config::config::Config,
+    handlers::error::HandlerError,
+    models::{
+        movement::{MovementFrame, MovementPacket},
+        peer::Peers,
+        room::Rooms,
+        sfu::{BotPeers, Loads, PipeTransports, Relays},
+    },
+    utils::{
+        codec::{
+            appData, BotConsumeData, BotReceivedData, BotSendData, CreateBotPeerData,
+            CreateRelayProducerMessage, CreatedBotPeerData, MessageResponse, ResponseMessage,
+        },
+        utils::{get_nodeid, Mut, Responder},
+    },
+};
+
+// labels clients give their event and movement data channels
+pub const EVENTS_LABEL: &str = "FrameEvents";
+pub const MOVEMENT_LABEL: &str = "AvatarMovement";
+
+// A participant played by the node: a direct transport on a router of the
+// room with an event and a movement data producer, relayed to egress like
+// those of a client so consumeEvents/consumeMovement reach it. It reads the
+// room's events and movement with botConsume.
+#[derive(Debug, Clone)]
+pub struct BotPeer {
+    pub peer_id: Uuid,
+    pub room: String,
+    pub transport: DirectTransport,
+    pub events: DataProducer,
+    pub movement: DataProducer,
+}
+
+impl BotPeer {
+    pub fn send_event(&self, event: String) -> Result<(), HandlerError> {
+        send(&self.events, WebRtcMessage::String(event))
+    }
+
+    pub fn send_movement(&self, frame: &MovementFrame) -> Result<(), HandlerError> {
+        send(
+            &self.movement,
+            WebRtcMessage::Binary(Cow::Owned(frame.to_bytes())),
+        )
+    }
+
+    // Read a data producer of the bot's router, e.g. the events or movement of
+    // a peer of the room.
+    pub async fn consume<F>(
+        &self,
+        producer_id: DataProducerId,
+        on_message: F,
+    ) -> Result<DataConsumer, HandlerError>
+    where
+        F: Fn(&WebRtcMessage) + Send + Sync + 'static,
+    {
+        let consumer = self
+            .transport
+            .consume_data(DataConsumerOptions::new_direct(producer_id))
+            .await
+            .map_err(|error| {
+                HandlerError::Mediasoup(format!("bot cannot consume {}: {}", producer_id, error))
+            })?;
+        consumer.on_message(on_message).detach();
+        Ok(consumer)
+    }
+}
+
+fn send(producer: &DataProducer, message: WebRtcMessage) -> Result<(), HandlerError> {
+    match producer {
+        DataProducer::Direct(producer) => producer
+            .send(message)
+            .map_err(|e| HandlerError::Mediasoup(format!("bot cannot send: {}", e))),
+        _ => Err(HandlerError::Internal(
+            "bot data producer is not direct".to_string(),
+        )),
+    }
+}
+
+pub async fn create_bot_peer(
+    wsid: String,
+    data: CreateBotPeerData,
+    room_routers: Arc<Mutex<Rooms>>,
+    relays: Arc<Mutex<Relays>>,
+    pipe_transports: Arc<Mut<PipeTransports>>,
+    bot_peers: Arc<Mut<BotPeers>>,
+    peers: Arc<Mut<Peers>>,
+    config: Config,
+    sender: Responder,
+) -> Result<(), HandlerError> {
+    if bot_peers.with(|b| b.get(data.peerId)).is_some()
+        || peers.with(|p| p.get(data.peerId).is_some())
+    {
+        return Err(HandlerError::AlreadyExists(format!(
+            "peer {} already exists",
+            data.peerId
+        )));
+    }
+    let routers = room_routers
+        .lock()
+        .await
+        .routers(&data.room)
+        .ok_or(HandlerError::NotFound(format!(
+            "cannot find room {}",
+            data.room
+        )))?;
+    // a router of the room already relayed to the egress
+    let mut router = None;
+    {
+        let relays = relays.lock().await;
+        for room_router in routers.into_iter() {
+            if !relays
+                .get_ingress_egress(room_router.id(), data.egress)
+                .is_empty()
+            {
+                router = Some(room_router);
+                break;
+            }
+        }
+    }
+    let router: Router = router.ok_or(HandlerError::NotFound(format!(
+        "room {} has no relay to egress {}",
+        data.room, data.egress
+    )))?;
+
+    let transport = router
+        .create_direct_transport(DirectTransportOptions::default())
+        .await
+        .map_err(|error| {
+            HandlerError::Mediasoup(format!("Failed to create bot transport: {}", error))
+        })?;
+    let app_data = appData(HashMap::from([("bot".to_string(), "true".to_string())]));
+    let events = produce(&transport, EVENTS_LABEL, &app_data).await?;
+    let movement = produce(&transport, MOVEMENT_LABEL, &app_data).await?;
+    let bot = BotPeer {
+        peer_id: data.peerId,
+        room: data.room.clone(),
+        transport,
+        events: events.clone(),
+        movement: movement.clone(),
+    };
+    // dropped with the transport when a relay fails, nothing is registered yet
+    let mut relay_consumers = vec![];
+    for producer in [&events, &movement] {
+        let relay_consumer = relay_data_producer(
+            router.id(),
+            data.egress,
+            data.room.clone(),
+            data.peerId,
+            producer,
+            &app_data,
+            &relays,
+            &pipe_transports,
+            &config,
+            &sender,
+        )
+        .await?;
+        relay_consumers.push(relay_consumer);
+    }
+    bot_peers.with(|b| b.create(bot.clone()));
+    peers.with(|p| {
+        let peer = p.entry(data.peerId);
+        peer.events = Some(events.clone());
+        peer.movement = Some(movement.clone());
+        peer.data_producers
+            .extend([events.clone(), movement.clone()]);
+        peer.data_consumers.extend(relay_consumers);
+    });
+
+    if let Some(position) = data.position {
+        let frame = MovementFrame {
+            base_position: Some(position),
+            ..Default::default()
+        };
+        moved(&bot, &frame, &peers, &config)?;
+    }
+    info!("bot peer {} joined room {}", data.peerId, data.room);
+    let msg = ResponseMessage::OutgoingCommunication {
+        ws: Some(wsid),
+        requestId: None,
+        communication: MessageResponse::createdBotPeer {
+            data: CreatedBotPeerData {
+                peerId: data.peerId,
+                eventsProducerId: events.id(),
+                movementProducerId: movement.id(),
+            },
+        },
+    };
+    let _ = sender.send(msg).await;
+    Ok(())
+}
+
+// Send an event and/or a movement frame as the bot.
+pub fn bot_send(
+    data: BotSendData,
+    bot_peers: Arc<Mut<BotPeers>>,
+    peers: Arc<Mut<Peers>>,
+    config: &Config,
+) -> Result<(), HandlerError> {
+    let bot = bot_peers
+        .with(|b| b.get(data.peerId))
+        .ok_or(HandlerError::NotFound(format!(
+            "cannot find bot peer {}",
+            data.peerId
+        )))?;
+    if let Some(event) = data.event {
+        bot.send_event(event)?;
+    }
+    if data.position.is_some() || data.rotation.is_some() {
+        let frame = MovementFrame {
+            base_position: data.position,
+            base_rotation: data.rotation,
+            ..Default::default()
+        };
+        moved(&bot, &frame, &peers, config)?;
+    }
+    Ok(())
+}
+
+// Consume a data producer as the bot and pass what it reads to the client
+// that asked, as botReceived. The consumer closes with the bot.
+pub async fn bot_consume(
+    wsid: String,
+    data: BotConsumeData,
+    bot_peers: Arc<Mut<BotPeers>>,
+    peers: Arc<Mut<Peers>>,
+    signaling: Arc<Mut<Option<Sender<ResponseMessage>>>>,
+) -> Result<(), HandlerError> {
+    let bot = bot_peers
+        .with(|b| b.get(data.peerId))
+        .ok_or(HandlerError::NotFound(format!(
+            "cannot find bot peer {}",
+            data.peerId
+        )))?;
+    let peer_id = bot.peer_id;
+    let producer_id = data.dataProducerId;
+    let consumer = bot
+        .consume(producer_id, move |message| {
+            let (event, position, rotation) = match message {
+                WebRtcMessage::String(event) => (Some(event.clone()), None, None),
+                WebRtcMessage::Binary(packet) => match MovementPacket::parse(packet) {
+                    Ok(movement) => (None, movement.base_position(), movement.base_rotation()),
+                    Err(e) => {
+                        debug!("bot {} read malformed movement: {}", peer_id, e);
+                        return;
+                    }
+                },
+                _ => return,
+            };
+            let sender = match signaling.with(|s| s.clone()) {
+                Some(sender) => sender,
+                None => return,
+            };
+            let msg = ResponseMessage::OutgoingCommunication {
+                ws: Some(wsid.clone()),
+                requestId: None,
+                communication: MessageResponse::botReceived {
+                    data: BotReceivedData {
+                        peerId: peer_id,
+                        dataProducerId: producer_id,
+                        event,
+                        position,
+                        rotation,
+                    },
+                },
+            };
+            // mediasoup calls this from its own thread, never block it
+            if let Err(e) = sender.try_send(msg) {
+                error!("dropping message read by bot {}: {}", peer_id, e);
+            }
+        })
+        .await?;
+    peers
+        .with(|p| {
+            p.get_mut(peer_id)
+                .map(|peer| peer.data_consumers.push(consumer))
+        })
+        .ok_or(HandlerError::NotFound(format!("bot peer {} left", peer_id)))
+}
+
+// Remove a bot and its peer, its producers and their relay consumers close
+// with the transport.
+pub async fn close_bot_peer(bot: BotPeer, peers: Arc<Mut<Peers>>, loads: Arc<Mutex<Loads>>) {
+    let peer_id = bot.peer_id;
+    let peer = peers.with(|p| p.remove(peer_id));
+    if let Some(peer) = peer {
+        peer.close(&mut *loads.lock().await);
+    }
+    info!("bot peer {} left room {}", peer_id, bot.room);
+}
+
+// Movement of a bot is not tapped, keep its frame and position here.
+fn moved(
+    bot: &BotPeer,
+    frame: &MovementFrame,
+    peers: &Arc<Mut<Peers>>,
+    config: &Config,
+) -> Result<(), HandlerError> {
+    bot.send_movement(frame)?;
+    let bytes = frame.to_bytes();
+    if config.movement_snapshot {
+        if let Ok(packet) = MovementPacket::parse(&bytes) {
+            peers.with(|p| p.entry(bot.peer_id).update_frame(packet));
+        }
+    }
+    if let (Some(position), Some(_)) = (frame.base_position, &config.interest) {
+        peers.with(|p| p.entry(bot.peer_id).position = Some(position));
+    }
+    Ok(())
+}
+
+async fn produce(
+    transport: &DirectTransport,
+    label: &str,
+    app_data: &appData,
+) -> Result<DataProducer, HandlerError> {
+    let mut options = DataProducerOptions::new_direct();
+    options.label = label.to_string();
+    options.app_data = AppData::new(app_data.clone());
+    transport.produce_data(options).await.map_err(|error| {
+        HandlerError::Mediasoup(format!(
+            "Failed to create bot {} producer: {}",
+            label, error
+        ))
+    })
+}
+
+// Consume `producer` on the relay of `ingress_router` to `egress` and ask the
+// egress to produce it, as createEventProducer/createDataProducer do.
+async fn relay_data_producer(
+    ingress_router: RouterId,
+    egress: Uuid,
+    room: String,
+    peer_id: Uuid,
+    producer: &DataProducer,
+    app_data: &appData,
+    relays: &Arc<Mutex<Relays>>,
+    pipe_transports: &Arc<Mut<PipeTransports>>,
+    config: &Config,
+    sender: &Responder,
+) -> Result<DataConsumer, HandlerError> {
+    let relay = relays
+        .lock()
+        .await
+        .get_ingress_egress(ingress_router, egress)
+        .into_iter()
+        .next()
+        .ok_or(HandlerError::NotFound(
+            "cannot find relay for bot peer".to_string(),
+        ))?;
+    let relay_transport =
+        pipe_transports
+            .with(|p| p.get(relay.transport))
+            .ok_or(HandlerError::NotFound(
+                "cannot find pipetransport".to_string(),
+            ))?;
+    let mut options = DataConsumerOptions::new_sctp_ordered(producer.id());
+    options.app_data = AppData::new(app_data.clone());
+    let relay_consumer = relay_transport
+        .pipe_transport
+        .consume_data(options)
+        .await
+        .map_err(|error| {
+            HandlerError::Mediasoup(format!("error consuming bot data producer {:?}", error))
+        })?;
+    let server_relay = ResponseMessage::OutgoingServer {
+        node: get_nodeid(config.ingress, config.egress),
+        requestId: None,
+        message: MessageResponse::createRelayProducer {
+            data: CreateRelayProducerMessage {
+                groupId: room,
+                peerId: peer_id,
+                ingressRoute: relay.router,
+                egress,
+                producerId: None,
+                mediaType: None,
+                rtpParameters: None,
+                dataProducerId: Some(relay_consumer.data_producer_id()),
+                label: Some(relay_consumer.label().to_string()),
+                sctpStreamParameters: relay_consumer.sctp_stream_parameters(),
+                appData: relay_consumer
+                    .app_data()
+                    .deref()
+                    .clone()
+                    .downcast::<appData>()
+                    .map(|app_data| app_data.deref().clone())
+                    .unwrap_or_default(),
+            },
+        },
+    };
+    if let Err(e) = sender.send(server_relay).await {
+        error!("failed to send bot relay: {:?}", e);
+    }
+    Ok(relay_consumer)
+}
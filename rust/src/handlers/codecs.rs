@@ -1,7 +1,8 @@
 use std::num::{NonZeroU32, NonZeroU8};
 
 use mediasoup::rtp_parameters::{
-    MimeTypeAudio, MimeTypeVideo, RtcpFeedback, RtpCodecCapability, RtpCodecParametersParameters,
+    MimeTypeAudio, MimeTypeVideo, RtcpFeedback, RtpCapabilities, RtpCodecCapability,
+    RtpCodecParametersParameters,
 };
 
 pub fn media_codecs() -> Vec<RtpCodecCapability> {
@@ -42,3 +43,12 @@ pub fn media_codecs() -> Vec<RtpCodecCapability> {
         },
     ]
 }
+
+// Capabilities of an endpoint receiving every codec the routers were created
+// with, for consumers the node itself creates.
+pub fn router_capabilities(media_codecs: &[RtpCodecCapability]) -> RtpCapabilities {
+    RtpCapabilities {
+        codecs: media_codecs.to_vec(),
+        header_extensions: Vec::new(),
+    }
+}
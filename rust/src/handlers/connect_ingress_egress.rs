@@ -3,14 +3,14 @@ use std::sync::Arc;
 use colored::Colorize;
 use log::error;
 use mediasoup::prelude::{DtlsParameters, WebRtcTransportRemoteParameters};
-use tokio::sync::mpsc::Sender;
 use uuid::Uuid;
 
 use crate::{
-    models::sfu::Transports,
+    handlers::error::HandlerError,
+    models::peer::Peers,
     utils::{
         codec::{MessageResponse, ResponseMessage},
-        utils::Mut,
+        utils::{Mut, Responder},
     },
 };
 
@@ -19,21 +19,22 @@ pub async fn connect_webrtc(
     transport_id: Uuid,
     dtls_parameters: DtlsParameters,
     is_ingress: bool,
-    transports: Arc<Mut<Transports>>,
-    sender: Sender<ResponseMessage>,
-) -> Result<(), String> {
-    let transport_guard = transports.with(|inner| inner.clone());
-    let get_transport = transport_guard.get(transport_id);
+    peers: Arc<Mut<Peers>>,
+    sender: Responder,
+) -> Result<(), HandlerError> {
+    let get_transport = peers.with(|p| p.transport(transport_id));
     if get_transport.is_none() {
         println!("transport not found");
-        return Err(String::from("transport not found"));
+        return Err(HandlerError::NotFound(String::from("transport not found")));
     } else {
         let transport = get_transport.unwrap();
         let remote_parameters = WebRtcTransportRemoteParameters { dtls_parameters };
         transport
             .connect(remote_parameters)
             .await
-            .map_err(|error| format!("Failed to connect ingress webrtc: {}", error))?;
+            .map_err(|error| {
+                HandlerError::Mediasoup(format!("Failed to connect ingress webrtc: {}", error))
+            })?;
         transport
             .on_sctp_state_change(move |state| {
                 println!("sctp state: {:?}", state);
@@ -42,6 +43,7 @@ pub async fn connect_webrtc(
         if is_ingress {
             let replay_message = ResponseMessage::OutgoingCommunication {
                 ws: Some(wsid),
+                requestId: None,
                 communication: MessageResponse::connectedIngressTransport {},
             };
             if let Err(e) = sender.send(replay_message).await {
@@ -51,6 +53,7 @@ pub async fn connect_webrtc(
         } else {
             let replay_message = ResponseMessage::OutgoingCommunication {
                 ws: Some(wsid),
+                requestId: None,
                 communication: MessageResponse::connectedEgressTransport {},
             };
             if let Err(e) = sender.send(replay_message).await {
@@ -0,0 +1,150 @@
+use std::sync::Arc;
+
+use log::error;
+use mediasoup::prelude::{Consumer, ConsumerId, ConsumerLayers};
+use tokio::sync::mpsc::Sender;
+use uuid::Uuid;
+
+use crate::{
+    handlers::error::HandlerError,
+    models::{peer::Peers, sfu::PlainTransports},
+    utils::{
+        codec::{
+            ConsumerLayersChangedData, ConsumerPreferredLayersData, ConsumerPriorityData,
+            ConsumerScoreData, MessageResponse, ResponseMessage,
+        },
+        utils::Mut,
+    },
+};
+
+pub async fn set_consumer_preferred_layers(
+    peers: Arc<Mut<Peers>>,
+    plain_transports: Arc<Mut<PlainTransports>>,
+    data: ConsumerPreferredLayersData,
+) -> Result<(), HandlerError> {
+    let consumer = find_consumer(&peers, &plain_transports, data.consumerId)?;
+    consumer
+        .set_preferred_layers(ConsumerLayers {
+            spatial_layer: data.spatialLayer,
+            temporal_layer: data.temporalLayer,
+        })
+        .await
+        .map_err(|error| {
+            HandlerError::Mediasoup(format!("failed to set preferred layers: {}", error))
+        })
+}
+
+pub async fn set_consumer_priority(
+    peers: Arc<Mut<Peers>>,
+    plain_transports: Arc<Mut<PlainTransports>>,
+    data: ConsumerPriorityData,
+) -> Result<(), HandlerError> {
+    if data.priority == 0 {
+        return Err(HandlerError::InvalidRequest(
+            "consumer priority must be at least 1".to_string(),
+        ));
+    }
+    let consumer = find_consumer(&peers, &plain_transports, data.consumerId)?;
+    consumer
+        .set_priority(data.priority)
+        .await
+        .map_err(|error| HandlerError::Mediasoup(format!("failed to set priority: {}", error)))
+}
+
+// A consumer of a peer or of a plain egress.
+pub fn find_consumer(
+    peers: &Arc<Mut<Peers>>,
+    plain_transports: &Arc<Mut<PlainTransports>>,
+    consumer_id: ConsumerId,
+) -> Result<Consumer, HandlerError> {
+    peers
+        .with(|p| p.consumer(consumer_id))
+        .or_else(|| plain_transports.with(|p| p.consumer(consumer_id)))
+        .ok_or(HandlerError::NotFound(format!(
+            "cannot find consumer on this server: {}",
+            consumer_id
+        )))
+}
+
+// Forward layer and score changes of a video consumer to the client owning it,
+// scores only when they differ from the last one sent.
+pub fn forward_consumer_events(
+    consumer: &Consumer,
+    wsid: String,
+    peer_id: Uuid,
+    signaling: Arc<Mut<Option<Sender<ResponseMessage>>>>,
+) {
+    let consumer_id = consumer.id();
+    {
+        let wsid = wsid.clone();
+        let signaling = signaling.clone();
+        consumer
+            .on_layers_change(move |layers| {
+                let data = ConsumerLayersChangedData {
+                    peerId: peer_id,
+                    consumerId: consumer_id,
+                    spatialLayer: layers.map(|l| l.spatial_layer),
+                    temporalLayer: layers.and_then(|l| l.temporal_layer),
+                };
+                notify(
+                    &signaling,
+                    wsid.clone(),
+                    MessageResponse::consumerLayersChanged { data },
+                );
+            })
+            .detach();
+    }
+    // the last score sent, repeated ones would only load signaling
+    let last_score = Mut::new(None);
+    consumer
+        .on_score(move |score| {
+            let current = (
+                score.score,
+                score.producer_score,
+                score.producer_scores.clone(),
+            );
+            let changed = last_score.with(|last| {
+                if last.as_ref() == Some(&current) {
+                    return false;
+                }
+                *last = Some(current);
+                true
+            });
+            if !changed {
+                return;
+            }
+            let data = ConsumerScoreData {
+                peerId: peer_id,
+                consumerId: consumer_id,
+                score: score.score,
+                producerScore: score.producer_score,
+                producerScores: score.producer_scores.clone(),
+            };
+            notify(
+                &signaling,
+                wsid.clone(),
+                MessageResponse::consumerScore { data },
+            );
+        })
+        .detach();
+}
+
+// mediasoup calls the handlers from its own thread, never block it
+fn notify(
+    signaling: &Arc<Mut<Option<Sender<ResponseMessage>>>>,
+    wsid: String,
+    message: MessageResponse,
+) {
+    let sender = match signaling.with(|s| s.clone()) {
+        Some(sender) => sender,
+        None => return,
+    };
+    let msg = ResponseMessage::OutgoingCommunication {
+        ws: Some(wsid),
+        requestId: None,
+        communication: message,
+    };
+    if let Err(e) = sender.try_send(msg) {
+        error!("dropping consumer event: {}", e);
+    }
+}
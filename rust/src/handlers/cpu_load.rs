@@ -1,19 +1,67 @@
-use std::thread;
-use std::time::Duration;
-use systemstat::{Platform, System};
+use systemstat::{CPULoad, DelayedMeasurement, Platform, System};
 
-pub fn get_media_loads() -> Result<f32, String> {
-    let sys = System::new();
-    match sys.cpu_load_aggregate() {
-        Ok(cpu) => {
-            // println!("\nMeasuring CPU load...");
-            thread::sleep(Duration::from_secs(1));
-            let cpu = cpu.done().unwrap();
+// Cpu usage of the host between two calls of sample, measured without
+// sleeping so it can run on the async runtime.
+pub struct CpuSampler {
+    system: System,
+    pending: Option<DelayedMeasurement<CPULoad>>,
+}
 
-            let usage_p = cpu.user * 100.0 + cpu.system * 100.0;
-            // println!("heghest load: {}", highest_load.clone());
-            return Ok(usage_p);
+impl CpuSampler {
+    pub fn new() -> Self {
+        CpuSampler {
+            system: System::new(),
+            pending: None,
+        }
+    }
+    // percent of all cores used since the previous call, None on the first one
+    pub fn sample(&mut self) -> Result<Option<f32>, String> {
+        let next = self
+            .system
+            .cpu_load_aggregate()
+            .map_err(|e| format!("error getting cpu: {}", e))?;
+        match self.pending.replace(next) {
+            Some(measurement) => {
+                let cpu = measurement
+                    .done()
+                    .map_err(|e| format!("error getting cpu: {}", e))?;
+                Ok(Some(cpu.user * 100.0 + cpu.system * 100.0))
+            }
+            None => Ok(None),
         }
-        Err(_) => return Err("error getting cpu".to_string()),
     }
 }
+
+// resident memory of this process, workers included, in bytes
+pub fn get_process_memory() -> Result<u64, String> {
+    let path = "/proc/self/status";
+    let status = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))
+        .and_then(|kb| kb.trim().trim_end_matches("kB").trim().parse::<u64>().ok())
+        .map(|kb| kb * 1024)
+        .ok_or_else(|| format!("malformed {}", path))
+}
+
+// kernel clock ticks per second used in /proc stat files (USER_HZ)
+pub const CLOCK_TICKS_PER_SEC: f32 = 100.0;
+
+// total user + system cpu time, in clock ticks, used so far by a thread of this process
+pub fn get_thread_cpu_ticks(thread_id: i32) -> Result<u64, String> {
+    let path = format!("/proc/self/task/{}/stat", thread_id);
+    let stat = std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path, e))?;
+    // the thread name may contain spaces, fields are counted after its closing paren
+    let fields: Vec<&str> = match stat.rfind(')') {
+        Some(i) => stat[i + 1..].split_whitespace().collect(),
+        None => return Err(format!("malformed {}", path)),
+    };
+    let ticks = |i: usize| -> Result<u64, String> {
+        fields
+            .get(i)
+            .and_then(|f| f.parse::<u64>().ok())
+            .ok_or_else(|| format!("malformed {}", path))
+    };
+    // utime and stime are fields 14 and 15 of the stat line
+    Ok(ticks(11)? + ticks(12)?)
+}
@@ -0,0 +1,303 @@
+use std::{
+    ops::Deref,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use log::error;
+use mediasoup::{
+    data_producer::{DataProducerId, DataProducerOptions},
+    data_structures::WebRtcMessage,
+    prelude::{
+        AppData, DataConsumerOptions, DataProducer, DirectTransport, DirectTransportOptions,
+        Transport,
+    },
+    router::Router,
+};
+use tokio::sync::mpsc::Sender;
+use uuid::Uuid;
+
+use crate::{
+    config::config::DataLimits,
+    handlers::error::HandlerError,
+    models::{
+        peer::{attach, Peers},
+        sfu::DataTaps,
+    },
+    utils::{
+        codec::{
+            appData, DataProducerThrottledData, MessageResponse, ResponseMessage, ThrottleState,
+        },
+        utils::Mut,
+    },
+};
+
+// data channel limits are counted over windows of this length
+const WINDOW: Duration = Duration::from_secs(1);
+
+// Rate and size limits of one data producer. Messages over the limits are
+// dropped and the producer is throttled; after `throttle_seconds` windows in a
+// row with drops it is paused and drops everything for `pause`.
+pub struct DataLimiter {
+    limits: DataLimits,
+    state: ThrottleState,
+    window_start: Instant,
+    messages: u32,
+    bytes: u64,
+    window_dropped: bool,
+    // windows in a row, before the current one, that dropped messages
+    throttled_windows: u32,
+    paused_until: Option<Instant>,
+    dropped: u64,
+}
+
+impl DataLimiter {
+    pub fn new(limits: DataLimits, now: Instant) -> Self {
+        DataLimiter {
+            limits,
+            state: ThrottleState::active,
+            window_start: now,
+            messages: 0,
+            bytes: 0,
+            window_dropped: false,
+            throttled_windows: 0,
+            paused_until: None,
+            dropped: 0,
+        }
+    }
+
+    // Whether a message of `len` bytes received at `now` may be forwarded,
+    // and the new state of the producer when it changed.
+    pub fn check(&mut self, len: usize, now: Instant) -> (bool, Option<ThrottleState>) {
+        let mut changed = None;
+        if let Some(until) = self.paused_until {
+            if now < until {
+                self.dropped += 1;
+                return (false, None);
+            }
+            self.paused_until = None;
+            self.throttled_windows = 0;
+            self.start_window(now);
+            changed = self.set_state(ThrottleState::active);
+        } else if now.duration_since(self.window_start) >= WINDOW {
+            // a silent window in between counts as a clean one
+            let clean = !self.window_dropped || now.duration_since(self.window_start) >= WINDOW * 2;
+            if clean {
+                self.throttled_windows = 0;
+                changed = self.set_state(ThrottleState::active);
+            } else {
+                self.throttled_windows += 1;
+            }
+            self.start_window(now);
+        }
+
+        let len = len as u64;
+        let oversized = self
+            .limits
+            .max_message_size
+            .map(|max| len > max as u64)
+            .unwrap_or(false);
+        let over_rate = self
+            .limits
+            .messages_per_sec
+            .map(|max| self.messages >= max)
+            .unwrap_or(false)
+            || self
+                .limits
+                .bytes_per_sec
+                .map(|max| self.bytes + len > max)
+                .unwrap_or(false);
+        if !oversized && !over_rate {
+            self.messages += 1;
+            self.bytes += len;
+            return (true, changed);
+        }
+
+        self.dropped += 1;
+        self.window_dropped = true;
+        if self.throttled_windows + 1 >= self.limits.throttle_seconds {
+            self.paused_until = Some(now + self.limits.pause);
+            return (false, self.set_state(ThrottleState::paused));
+        }
+        (false, self.set_state(ThrottleState::throttled).or(changed))
+    }
+
+    // messages dropped since the last call
+    pub fn take_dropped(&mut self) -> u64 {
+        std::mem::take(&mut self.dropped)
+    }
+
+    fn start_window(&mut self, now: Instant) {
+        self.window_start = now;
+        self.messages = 0;
+        self.bytes = 0;
+        self.window_dropped = false;
+    }
+
+    fn set_state(&mut self, state: ThrottleState) -> Option<ThrottleState> {
+        if self.state == state {
+            return None;
+        }
+        self.state = state;
+        Some(state)
+    }
+}
+
+// Size of a data channel message as counted by the limits.
+pub fn message_len(message: &WebRtcMessage) -> usize {
+    match message {
+        WebRtcMessage::String(string) => string.len(),
+        WebRtcMessage::Binary(binary) => binary.len(),
+        WebRtcMessage::EmptyString | WebRtcMessage::EmptyBinary => 0,
+    }
+}
+
+// Direct transport reading the data producers of `router` back, created the
+// first time.
+pub async fn tap_transport(
+    data_taps: &Arc<Mut<DataTaps>>,
+    router: &Router,
+) -> Result<DirectTransport, HandlerError> {
+    if let Some(transport) = data_taps.with(|t| t.get_transport(router.id())) {
+        return Ok(transport);
+    }
+    let transport = router
+        .create_direct_transport(DirectTransportOptions::default())
+        .await
+        .map_err(|error| {
+            HandlerError::Mediasoup(format!("Failed to create data tap transport: {}", error))
+        })?;
+    Ok(data_taps.with(|t| t.create_transport(router.id(), transport)))
+}
+
+// Direct data producer sending the messages of `source` that passed the
+// checks, with the same label and app data so it can be consumed in its place.
+pub async fn produce_forwarded(
+    transport: &DirectTransport,
+    source: &DataProducer,
+) -> Result<DataProducer, HandlerError> {
+    let mut options = DataProducerOptions::new_direct();
+    options.label = source.label().clone();
+    options.protocol = source.protocol().clone();
+    if let Ok(app_data) = source.app_data().deref().clone().downcast::<appData>() {
+        options.app_data = AppData::new(app_data.deref().clone());
+    }
+    transport.produce_data(options).await.map_err(|error| {
+        HandlerError::Mediasoup(format!(
+            "Failed to create forwarding data producer: {}",
+            error
+        ))
+    })
+}
+
+// Enforce `limits` on the event data producer of `peer_id`, returns the
+// producer to fan out in place of `source`.
+pub async fn limit_event_producer(
+    data_taps: Arc<Mut<DataTaps>>,
+    peers: Arc<Mut<Peers>>,
+    signaling: Arc<Mut<Option<Sender<ResponseMessage>>>>,
+    peer_id: Uuid,
+    source: &DataProducer,
+    router: Router,
+    limits: DataLimits,
+) -> Result<DataProducer, HandlerError> {
+    let transport = tap_transport(&data_taps, &router).await?;
+    let forwarded = produce_forwarded(&transport, source).await?;
+    let consumer = transport
+        .consume_data(DataConsumerOptions::new_direct(source.id()))
+        .await
+        .map_err(|error| {
+            HandlerError::Mediasoup(format!("Failed to tap event producer: {}", error))
+        })?;
+
+    let limiter = Mut::new(DataLimiter::new(limits, Instant::now()));
+    let label = source.label().clone();
+    let producer_id = source.id();
+    {
+        let forwarded = forwarded.downgrade();
+        consumer
+            .on_message(move |message| {
+                let pass = limit(
+                    &limiter,
+                    &signaling,
+                    peer_id,
+                    producer_id,
+                    &label,
+                    message_len(message),
+                );
+                if !pass {
+                    return;
+                }
+                if let Some(DataProducer::Direct(producer)) = forwarded.upgrade() {
+                    if let Err(e) = producer.send(message.clone()) {
+                        error!("cannot forward events of {}: {}", peer_id, e);
+                    }
+                }
+            })
+            .detach();
+    }
+    let forwarded_id = forwarded.id();
+    {
+        let peers = peers.clone();
+        consumer
+            .on_data_producer_close(move || {
+                let _closed = peers.with(|p| {
+                    let peer = p.get_mut(peer_id)?;
+                    match &peer.events {
+                        Some(events) if events.id() == forwarded_id => peer.events.take(),
+                        _ => None,
+                    }
+                });
+            })
+            .detach();
+    }
+    attach(&peers, peer_id, consumer, |peer, consumer| {
+        peer.taps.push(consumer)
+    })?;
+    Ok(forwarded)
+}
+
+// Count a message against `limiter`, telling signaling when the producer
+// state changes. Returns whether the message may be forwarded.
+pub fn limit(
+    limiter: &Mut<DataLimiter>,
+    signaling: &Arc<Mut<Option<Sender<ResponseMessage>>>>,
+    peer_id: Uuid,
+    producer_id: DataProducerId,
+    label: &str,
+    len: usize,
+) -> bool {
+    let (pass, changed, dropped) = limiter.with(|l| {
+        let (pass, changed) = l.check(len, Instant::now());
+        let dropped = if changed.is_some() {
+            l.take_dropped()
+        } else {
+            0
+        };
+        (pass, changed, dropped)
+    });
+    if let Some(state) = changed {
+        let sender = match signaling.with(|s| s.clone()) {
+            Some(sender) => sender,
+            None => return pass,
+        };
+        let msg = ResponseMessage::OutgoingCommunication {
+            ws: None,
+            requestId: None,
+            communication: MessageResponse::dataProducerThrottled {
+                data: DataProducerThrottledData {
+                    peerId: peer_id,
+                    dataProducerId: producer_id,
+                    label: label.to_string(),
+                    state,
+                    dropped,
+                },
+            },
+        };
+        // mediasoup calls the handlers from its own thread, never block it
+        if let Err(e) = sender.try_send(msg) {
+            error!("dropping dataProducerThrottled: {}", e);
+        }
+    }
+    pass
+}
@@ -1,9 +1,9 @@
-use std::{collections::HashMap, ops::Deref, sync::Arc};
+use std::{ops::Deref, sync::Arc};
 
 use log::{error, info};
 use mediasoup::{
     data_producer::{DataProducerId, DataProducerOptions},
-    prelude::{AppData, DataProducer, SctpStreamParameters},
+    prelude::{AppData, SctpStreamParameters},
     router::{PipeToRouterOptions, RouterId},
     transport::Transport,
 };
@@ -12,13 +12,18 @@ use uuid::Uuid;
 
 use crate::{
     config::config::Config,
-    models::sfu::{
-        EventProducers, MovementProducers, PipeTransports, RelayRouters, Relays, RoomRouters,
-        Routers,
+    handlers::{
+        error::HandlerError,
+        movement_tap::{tap_movement, taps_movement},
+    },
+    models::{
+        peer::Peers,
+        room::Rooms,
+        sfu::{DataTaps, PipeTransports, RelayRouters, Relays, Routers},
     },
     utils::{
         codec::{appData, CreatedRelayProducerData, MessageResponse, ResponseMessage},
-        utils::{get_nodeid, Mut},
+        utils::{get_nodeid, Mut, Responder},
     },
 };
 
@@ -31,28 +36,35 @@ pub async fn create_relay_datachannel_producer(
     sctp_stream_parameters: SctpStreamParameters,
     app_data: appData,
     routers: Arc<Mutex<Routers>>,
-    room_routers: Arc<Mutex<RoomRouters>>,
+    room_routers: Arc<Mutex<Rooms>>,
     relays: Arc<Mutex<Relays>>,
     pipetransports: Arc<Mut<PipeTransports>>,
-    event_producers: Arc<Mut<EventProducers>>,
-    movement_producers: Arc<Mut<MovementProducers>>,
+    peers: Arc<Mut<Peers>>,
+    data_taps: Arc<Mut<DataTaps>>,
+    signaling: Arc<Mut<Option<Sender<ResponseMessage>>>>,
     relay_routers: Arc<Mutex<RelayRouters>>,
-    data_producers: Arc<Mutex<HashMap<DataProducerId, DataProducer>>>,
     config: Config,
-    sender: Sender<ResponseMessage>,
-) -> Result<(), String> {
-    let data_producer = event_producers.with(|ep| ep.clone());
-    let peer_producer = data_producer.get(peer_id);
-    if peer_producer.as_ref().is_some() {
-        return Err(format!(
+    sender: Responder,
+) -> Result<(), HandlerError> {
+    let peer_producer = peers.with(|p| {
+        p.get(peer_id).and_then(|peer| match label.as_str() {
+            "FrameEvents" => peer.events.clone(),
+            "AvatarMovement" => peer.movement.clone(),
+            _ => None,
+        })
+    });
+    if peer_producer.is_some() {
+        return Err(HandlerError::AlreadyExists(format!(
             "peer {:?} has already produced {:?}!",
             peer_id, label
-        ));
+        )));
     }
     let get_relay_producer = relays.lock().await.get_router(ingress_route.clone());
     if get_relay_producer.is_empty() {
         println!("relay producer error:");
-        return Err(String::from("cannot find the ingress router!"));
+        return Err(HandlerError::NotFound(String::from(
+            "cannot find the ingress router!",
+        )));
     } else {
         let get_relay_transport = pipetransports.with(|p| p.get(get_relay_producer[0].transport));
         if let Some(relay_transport) = get_relay_transport {
@@ -64,14 +76,37 @@ pub async fn create_relay_datachannel_producer(
                 .pipe_transport
                 .produce_data(data_producer_options)
                 .await
-                .map_err(|error| format!("Error receiving piped network data: {:?}", error))?;
+                .map_err(|error| {
+                    HandlerError::Mediasoup(format!(
+                        "Error receiving piped network data: {:?}",
+                        error
+                    ))
+                })?;
             relay_producer
                 .on_close(Box::new(move || {
                     info!("closing relay data producer");
                 }))
                 .detach();
+            // validate movement before it is piped to the other routers of the
+            // room, the ingress node already enforced the data limits
+            let fanned_out = if label == "AvatarMovement" && taps_movement(&config) {
+                tap_movement(
+                    data_taps,
+                    peers.clone(),
+                    signaling,
+                    peer_id,
+                    &relay_producer,
+                    relay_transport.pipe_transport.router().clone(),
+                    &config,
+                    None,
+                )
+                .await?
+                .unwrap_or_else(|| relay_producer.clone())
+            } else {
+                relay_producer.clone()
+            };
 
-            let router_network = room_routers.lock().await.get(router_network);
+            let router_network = room_routers.lock().await.routers(&router_network);
             if router_network.is_some() {
                 let relay_router = relay_routers.lock().await.get(ingress_route.clone());
                 for router_info in router_network.unwrap().into_iter() {
@@ -84,12 +119,8 @@ pub async fn create_relay_datachannel_producer(
                         let router = routers.lock().await.get(relay_router.clone().unwrap());
                         if router.is_some() {
                             if router.clone().unwrap().closed() {
-                                println!("FROM CREATE RELAY PRODUCER");
-                                println!(
-                                    "Killing process, for the greater good. Source Router is closed."
-                                );
-                                std::process::exit(1);
-                                //kill process
+                                println!("source router is closed, skipping it");
+                                continue;
                             }
                         }
                         let router_clone = routers.clone();
@@ -98,38 +129,44 @@ pub async fn create_relay_datachannel_producer(
                         let pipe_router = router_guard.get(relay_router.unwrap());
                         if pipe_router.is_none() {
                             println!("couldn't find pipe router {:?}", relay_router.unwrap());
-                            return Err("couldn't find pipe router".to_string());
+                            return Err(HandlerError::NotFound(
+                                "couldn't find pipe router".to_string(),
+                            ));
                         }
                         if current_router.is_none() {
                             println!("couldn't find curent router {:?}", relay_router.unwrap());
-                            return Err("couldn't find curent router".to_string());
+                            return Err(HandlerError::NotFound(
+                                "couldn't find curent router".to_string(),
+                            ));
                         }
                         if current_router.clone().unwrap().closed() {
-                            println!("FROM CREATE EVENT RELAY PRODUCER");
-                            println!(
-                                "Killing process, for the greater good. Source Router is closed."
-                            );
-                            std::process::exit(1);
-                            //kill process
+                            println!("destination router is closed, skipping it");
+                            continue;
                         }
                         let pipe_option = PipeToRouterOptions::new(current_router.unwrap().clone());
                         pipe_router
                             .unwrap()
-                            .pipe_data_producer_to_router(relay_producer.id(), pipe_option)
+                            .pipe_data_producer_to_router(fanned_out.id(), pipe_option)
                             .await
                             .map_err(|error| {
-                                format!("Failed to Pipe to router transport: {}", error)
+                                HandlerError::Mediasoup(format!(
+                                    "Failed to Pipe to router transport: {}",
+                                    error
+                                ))
                             })?;
                     }
                 }
             }
             // prepare to send information back to the client
             if label == "FrameEvents" {
-                event_producers.with(|ep| ep.create(peer_id, relay_producer.clone()));
-                let mut data_producer_create = data_producers.lock().await;
-                data_producer_create.insert(relay_producer.id(), relay_producer.clone());
+                let _replaced = peers.with(|p| {
+                    let peer = p.entry(peer_id);
+                    peer.data_producers.push(relay_producer.clone());
+                    peer.events.replace(relay_producer.clone())
+                });
                 let reply_message = ResponseMessage::OutgoingServer {
                     node: get_nodeid(config.ingress, config.egress),
+                    requestId: None,
                     message: MessageResponse::createdRelayProducer {
                         data: CreatedRelayProducerData {
                             peerId: peer_id,
@@ -152,11 +189,14 @@ pub async fn create_relay_datachannel_producer(
                     error!("sending error: {:?}", e);
                 };
             } else if label == "AvatarMovement" {
-                movement_producers.with(|mp| mp.create(peer_id, relay_producer.clone()));
-                let mut data_producer_create = data_producers.lock().await;
-                data_producer_create.insert(relay_producer.id(), relay_producer.clone());
+                let _replaced = peers.with(|p| {
+                    let peer = p.entry(peer_id);
+                    peer.data_producers.push(relay_producer.clone());
+                    peer.movement.replace(fanned_out.clone())
+                });
                 let reply_message = ResponseMessage::OutgoingServer {
                     node: get_nodeid(config.ingress, config.egress),
+                    requestId: None,
                     message: MessageResponse::createdRelayProducer {
                         data: CreatedRelayProducerData {
                             peerId: peer_id,
@@ -180,12 +220,16 @@ pub async fn create_relay_datachannel_producer(
                 };
             } else {
                 error!("strange data channel label was passed in return here");
-                return Err(String::from("no labels match"));
+                return Err(HandlerError::InvalidRequest(String::from(
+                    "no labels match",
+                )));
             }
             // prepare to send back to client
             Ok(())
         } else {
-            return Err(String::from("error creating event data relay producer!"));
+            return Err(HandlerError::NotFound(String::from(
+                "error creating event data relay producer!",
+            )));
         }
     }
 }
@@ -4,28 +4,34 @@ use std::sync::Arc;
 use mediasoup::{
     prelude::WebRtcTransportOptions, sctp_parameters::NumSctpStreams, transport::Transport,
 };
-use tokio::sync::{mpsc::Sender, Mutex, RwLock};
+use tokio::sync::{Mutex, RwLock};
 use uuid::Uuid;
 
 use crate::{
-    models::sfu::{
-        Endpoints, Loads, RoomRouters, Routers, Routers2Worker, Transport2Router, Transports,
-        WebrtcServers,
+    config::config::Config,
+    handlers::{
+        error::HandlerError,
+        router::{admit_peer, grow_room},
+    },
+    models::{
+        peer::{release, Peers},
+        room::Rooms,
+        sfu::{Loads, Routers, Routers2Worker, Transport2Router, WebrtcServers, Workers},
     },
     utils::{
         codec::{CreatedEgressTransportData, MessageResponse, ResponseMessage, SctpOptions},
-        utils::Mut,
+        utils::{Mut, Responder},
         worker_load::get_less_loaded_router,
     },
 };
 
 pub async fn create_webrtc_egress(
     routers: Arc<Mutex<Routers>>,
-    room_routers: Arc<Mutex<RoomRouters>>,
+    room_routers: Arc<Mutex<Rooms>>,
     router2worker: Arc<Mutex<Routers2Worker>>,
+    workers: Arc<RwLock<Workers>>,
     webrtc_server: Arc<RwLock<WebrtcServers>>,
-    transports: Arc<Mut<Transports>>,
-    endpoints: Arc<Mutex<Endpoints>>,
+    peers: Arc<Mut<Peers>>,
     transport2router: Arc<RwLock<Transport2Router>>,
     loads: Arc<Mutex<Loads>>,
     sctpOptions: SctpOptions,
@@ -33,8 +39,26 @@ pub async fn create_webrtc_egress(
     wsid: String,
     peerId: Uuid,
     egress: Option<Uuid>,
-    sender: Sender<ResponseMessage>,
-) -> Result<(), String> {
+    config: Config,
+    sender: Responder,
+) -> Result<(), HandlerError> {
+    admit_peer(
+        &*room_routers.lock().await,
+        &routerNetwork,
+        peerId,
+        config.room_limits.max_peers,
+    )?;
+    grow_room(
+        &routerNetwork,
+        room_routers.clone(),
+        workers,
+        routers.clone(),
+        router2worker.clone(),
+        loads.clone(),
+        peers.clone(),
+        &config,
+    )
+    .await?;
     let lease_load = get_less_loaded_router(
         routerNetwork.clone(),
         room_routers.clone(),
@@ -43,23 +67,24 @@ pub async fn create_webrtc_egress(
     )
     .await;
     if lease_load.is_none() {
-        return Err(format!(
+        return Err(HandlerError::NotFound(format!(
             "No router available for network {}",
             &routerNetwork
-        ));
+        )));
     }
 
-    let routers = routers.lock().await;
-    let router = match routers.get(lease_load.unwrap()) {
+    let router = match routers.lock().await.get(lease_load.unwrap()) {
         Some(r) => r,
         None => {
-            return Err("cannot find router".to_string());
+            return Err(HandlerError::NotFound("cannot find router".to_string()));
         }
     };
 
     let webrtc_server = webrtc_server.read().await.get(router.worker().id());
     if webrtc_server.is_none() {
-        return Err("cannot find webrtc server".to_string());
+        return Err(HandlerError::NotFound(
+            "cannot find webrtc server".to_string(),
+        ));
     }
     let mut transport_options = WebRtcTransportOptions::new_with_server(webrtc_server.unwrap());
     transport_options.enable_udp = true;
@@ -70,23 +95,42 @@ pub async fn create_webrtc_egress(
         os: sctpOptions.OS,
         mis: sctpOptions.MIS,
     };
-    transport_options.initial_available_outgoing_bitrate = 600000;
+    transport_options.initial_available_outgoing_bitrate = config.initial_outgoing_bitrate;
     let transport_produce = router
         .create_webrtc_transport(transport_options)
         .await
-        .map_err(|error| format!("Failed to create producer transport: {}", error))?;
+        .map_err(|error| {
+            HandlerError::Mediasoup(format!("Failed to create producer transport: {}", error))
+        })?;
     transport_produce
-        .set_max_outgoing_bitrate(3500000)
+        .set_max_outgoing_bitrate(config.max_outgoing_bitrate)
         .await
-        .map_err(|error| format!("Failed to egress set max outgoing bitrate: {}", error))?;
-    transports.with(|inner| inner.create(peerId.clone(), transport_produce.clone()));
+        .map_err(|error| {
+            HandlerError::Mediasoup(format!(
+                "Failed to egress set max outgoing bitrate: {}",
+                error
+            ))
+        })?;
+    {
+        let mut rooms = room_routers.lock().await;
+        if let Err(e) = admit_peer(&rooms, &routerNetwork, peerId, config.room_limits.max_peers) {
+            // the room filled up meanwhile, the transport closes when dropped
+            release(&transport_produce, &mut *loads.lock().await);
+            return Err(e);
+        }
+        rooms.join(&routerNetwork, peerId);
+    }
+    // a transport created again replaces the previous one
+    let replaced = peers.with(|p| p.entry(peerId).transport.replace(transport_produce.clone()));
+    if let Some(replaced) = replaced {
+        release(&replaced, &mut *loads.lock().await);
+    }
 
     let mut transport2router_guard = transport2router.write().await;
     transport2router_guard.create(transport_produce.id().clone(), router.id().clone());
     // listen when transport is close then clean up
     let t_id = transport_produce.id().clone();
     let transport2router_clone = transport2router.clone();
-    let endpoints_clone = endpoints.clone();
     let handle = tokio::runtime::Handle::current();
     transport_produce
         .on_close(Box::new(move || {
@@ -94,13 +138,12 @@ pub async fn create_webrtc_egress(
                 println!("egress closed!!!!!");
                 let mut transport2Router_remove = transport2router_clone.write().await;
                 transport2Router_remove.delete(t_id);
-                let mut endpoint_remove = endpoints_clone.lock().await;
-                endpoint_remove.delete(t_id);
             });
         }))
         .detach();
     let egress_reply = ResponseMessage::OutgoingCommunication {
         ws: Some(wsid),
+        requestId: None,
         communication: MessageResponse::createdEgressTransport {
             data: CreatedEgressTransportData {
                 id: transport_produce.id(),
@@ -112,9 +155,6 @@ pub async fn create_webrtc_egress(
             },
         },
     };
-    // endpoints keeps track of the engpoints transport id and peerid
-    let mut endpoints_guard = endpoints.lock().await;
-    endpoints_guard.create(transport_produce.id(), peerId);
     // send message back to api
     let _ = sender.send(egress_reply).await;
 
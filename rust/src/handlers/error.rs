@@ -0,0 +1,86 @@
+#![allow(non_camel_case_types)]
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+// error code sent back to signaling in a requestFailed message
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    notFound,
+    alreadyExists,
+    invalidRequest,
+    mediasoupError,
+    internal,
+    draining,
+    limitReached,
+}
+
+// the limit a request was rejected by, signaling places the peer or the room
+// on another node
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    nodeRooms,
+    roomPeers,
+    roomVideoProducers,
+    transportConsumers,
+}
+
+// error returned by every request handler
+#[derive(Debug, Clone)]
+pub enum HandlerError {
+    // a transport, router, producer or relay the request refers to is not on this node
+    NotFound(String),
+    // the request would create something that already exists
+    AlreadyExists(String),
+    // the request is missing data or does not apply to this node
+    InvalidRequest(String),
+    // mediasoup rejected the operation
+    Mediasoup(String),
+    // anything else
+    Internal(String),
+    // the node is draining and does not take new rooms
+    Draining(String),
+    // the request would go over one of the configured limits
+    LimitReached(Limit, String),
+}
+
+impl HandlerError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            HandlerError::NotFound(_) => ErrorCode::notFound,
+            HandlerError::AlreadyExists(_) => ErrorCode::alreadyExists,
+            HandlerError::InvalidRequest(_) => ErrorCode::invalidRequest,
+            HandlerError::Mediasoup(_) => ErrorCode::mediasoupError,
+            HandlerError::Internal(_) => ErrorCode::internal,
+            HandlerError::Draining(_) => ErrorCode::draining,
+            HandlerError::LimitReached(..) => ErrorCode::limitReached,
+        }
+    }
+
+    pub fn limit(&self) -> Option<Limit> {
+        match self {
+            HandlerError::LimitReached(limit, _) => Some(*limit),
+            _ => None,
+        }
+    }
+
+    pub fn reason(&self) -> &str {
+        match self {
+            HandlerError::NotFound(reason)
+            | HandlerError::AlreadyExists(reason)
+            | HandlerError::InvalidRequest(reason)
+            | HandlerError::Mediasoup(reason)
+            | HandlerError::Internal(reason)
+            | HandlerError::Draining(reason)
+            | HandlerError::LimitReached(_, reason) => reason,
+        }
+    }
+}
+
+impl fmt::Display for HandlerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}: {}", self.code(), self.reason())
+    }
+}
+
+impl std::error::Error for HandlerError {}
@@ -1,20 +1,20 @@
 use log::{error, info};
 use mediasoup::{
-    prelude::{AppData, DataConsumer, DataConsumerId, DataConsumerOptions},
+    prelude::{AppData, DataConsumerOptions},
     transport::Transport,
 };
 use std::{collections::HashMap, ops::Deref, sync::Arc};
-use tokio::sync::{mpsc::Sender, Mutex};
 use uuid::Uuid;
 
 use crate::{
+    handlers::error::HandlerError,
     models::{
         message::NewDataConsumerOptions,
-        sfu::{EventProducers, PeerDataConsumed, PeerDataConsumedData, Transports},
+        peer::{attach, Peers},
     },
     utils::{
         codec::{appData, MessageResponse, ResponseMessage},
-        utils::Mut,
+        utils::{Mut, Responder},
     },
 };
 
@@ -22,16 +22,13 @@ pub async fn consume_event(
     wsid: String,
     producer_peers: Vec<Uuid>,
     consumer_peer: Uuid,
-    transports: Arc<Mut<Transports>>,
-    event_producers: Arc<Mut<EventProducers>>,
-    peerdataconsumed: Arc<Mutex<PeerDataConsumed>>,
-    data_consumers: Arc<Mutex<HashMap<DataConsumerId, DataConsumer>>>,
-    sender: Sender<ResponseMessage>,
-) -> Result<(), String> {
+    peers: Arc<Mut<Peers>>,
+    sender: Responder,
+) -> Result<(), HandlerError> {
     let mut event_announcement: HashMap<Uuid, NewDataConsumerOptions> = HashMap::new();
     for peer in producer_peers.into_iter() {
-        let get_transport = transports.with(|inner| inner.get(consumer_peer));
-        let get_producers = event_producers.with(|event| event.get(peer).clone());
+        let get_transport = peers.with(|p| p.transport(consumer_peer));
+        let get_producers = peers.with(|p| p.get(peer).and_then(|p| p.events.clone()));
         if get_producers.is_none() {
             info!(
                 "Warning: consumer peer events producer is not defined on this egress server. event producer {}",
@@ -63,7 +60,9 @@ pub async fn consume_event(
         let new_data_consumer = transport
             .consume_data(data_consumer_options)
             .await
-            .map_err(|_error| format!("Error consuming data event consumer!"))?;
+            .map_err(|_error| {
+                HandlerError::Mediasoup(format!("Error consuming data event consumer!"))
+            })?;
 
         new_data_consumer
             .on_close(move || {
@@ -86,20 +85,18 @@ pub async fn consume_event(
                 .deref()
                 .clone(),
         };
-        let peer_consume_data = PeerDataConsumedData {
-            peer_id: consumer_peer,
-            produce_id: new_data_consumer.data_producer_id(),
-            consumer_id: new_data_consumer.id(),
-        };
-        let mut peerdataconsumed = peerdataconsumed.lock().await;
-        peerdataconsumed.create(peer_consume_data);
         event_announcement.insert(peer, event_consumer_options);
-        let mut data_consumers = data_consumers.lock().await;
-        data_consumers.insert(new_data_consumer.id(), new_data_consumer);
+        attach(
+            &peers,
+            consumer_peer,
+            new_data_consumer,
+            |peer, consumer| peer.data_consumers.push(consumer),
+        )?;
     }
     if event_announcement.len() > 0 {
         let reply_consumer = ResponseMessage::OutgoingCommunication {
             ws: Some(wsid.clone()),
+            requestId: None,
             communication: MessageResponse::eventAnnouncement {
                 data: event_announcement,
             },
@@ -6,18 +6,26 @@ use mediasoup::{
     prelude::WebRtcTransportOptions, sctp_parameters::NumSctpStreams, transport::Transport,
 };
 
-use tokio::sync::{mpsc::Sender, Mutex, RwLock};
+use tokio::sync::{Mutex, RwLock};
 use uuid::Uuid;
 
 use crate::{
     config::config::Config,
-    models::sfu::{
-        Endpoints, Loads, PipeTransports, Relays, RoomRouters, Routers, Routers2Worker,
-        Transport2Router, Transports, WebrtcServers,
+    handlers::{
+        error::HandlerError,
+        router::{admit_peer, grow_room},
+    },
+    models::{
+        peer::{release, Peers},
+        room::Rooms,
+        sfu::{
+            Loads, PipeTransports, Relays, Routers, Routers2Worker, Transport2Router,
+            WebrtcServers, Workers,
+        },
     },
     utils::{
         codec::{CreatedIngressTransportData, MessageResponse, ResponseMessage, SctpOptions},
-        utils::Mut,
+        utils::{Mut, Responder},
         worker_load::get_less_loaded_router,
     },
 };
@@ -26,15 +34,15 @@ use super::media_relay::create_ingress_relay;
 
 pub async fn create_webrtc_ingress(
     ingress: Option<Uuid>,
-    endpoints: Arc<Mutex<Endpoints>>,
-    transports: Arc<Mut<Transports>>,
+    peers: Arc<Mut<Peers>>,
     pipetransports: Arc<Mut<PipeTransports>>,
     relays: Arc<Mutex<Relays>>,
     transport2router: Arc<RwLock<Transport2Router>>,
     webrtc_server: Arc<RwLock<WebrtcServers>>,
     routers: Arc<Mutex<Routers>>,
-    room_routers: Arc<Mutex<RoomRouters>>,
+    room_routers: Arc<Mutex<Rooms>>,
     router2worker: Arc<Mutex<Routers2Worker>>,
+    workers: Arc<RwLock<Workers>>,
     loads: Arc<Mutex<Loads>>,
     sctpOptions: SctpOptions,
     routerNetwork: String,
@@ -42,8 +50,25 @@ pub async fn create_webrtc_ingress(
     routerPips: Vec<Option<Uuid>>,
     peerId: Uuid,
     config: Config,
-    sender: Sender<ResponseMessage>,
-) -> Result<(), String> {
+    sender: Responder,
+) -> Result<(), HandlerError> {
+    admit_peer(
+        &*room_routers.lock().await,
+        &routerNetwork,
+        peerId,
+        config.room_limits.max_peers,
+    )?;
+    grow_room(
+        &routerNetwork,
+        room_routers.clone(),
+        workers,
+        routers.clone(),
+        router2worker.clone(),
+        loads.clone(),
+        peers.clone(),
+        &config,
+    )
+    .await?;
     let lease_load = get_less_loaded_router(
         routerNetwork.clone(),
         room_routers.clone(),
@@ -52,18 +77,24 @@ pub async fn create_webrtc_ingress(
     )
     .await;
     if lease_load.is_none() {
-        return Err(format!(
+        return Err(HandlerError::NotFound(format!(
             "No router available for network {}",
             &routerNetwork
-        ));
+        )));
     }
     let router = match routers.lock().await.get(lease_load.unwrap()) {
         Some(r) => r,
-        None => return Err("cannot find the router ingress".to_string()),
+        None => {
+            return Err(HandlerError::NotFound(
+                "cannot find the router ingress".to_string(),
+            ))
+        }
     };
     let webrtc_server = webrtc_server.read().await.get(router.worker().id());
     if webrtc_server.is_none() {
-        return Err("cannot find webrtc server".to_string());
+        return Err(HandlerError::NotFound(
+            "cannot find webrtc server".to_string(),
+        ));
     }
     let mut transport_options = WebRtcTransportOptions::new_with_server(webrtc_server.unwrap());
     transport_options.enable_udp = true;
@@ -74,17 +105,36 @@ pub async fn create_webrtc_ingress(
         os: sctpOptions.OS,
         mis: sctpOptions.MIS,
     };
-    transport_options.initial_available_outgoing_bitrate = 600000;
+    transport_options.initial_available_outgoing_bitrate = config.initial_outgoing_bitrate;
     let transport_produce = router
         .create_webrtc_transport(transport_options)
         .await
-        .map_err(|error| format!("Failed to create producer transport: {}", error))?;
+        .map_err(|error| {
+            HandlerError::Mediasoup(format!("Failed to create producer transport: {}", error))
+        })?;
     transport_produce
-        .set_max_outgoing_bitrate(3500000)
+        .set_max_outgoing_bitrate(config.max_outgoing_bitrate)
         .await
-        .map_err(|error| format!("Failed to set ingress max outgoing bitrate: {}", error))?;
-    // tranports keeps all the ingress relays
-    transports.with(|inner| inner.create(peerId.clone(), transport_produce.clone()));
+        .map_err(|error| {
+            HandlerError::Mediasoup(format!(
+                "Failed to set ingress max outgoing bitrate: {}",
+                error
+            ))
+        })?;
+    {
+        let mut rooms = room_routers.lock().await;
+        if let Err(e) = admit_peer(&rooms, &routerNetwork, peerId, config.room_limits.max_peers) {
+            // the room filled up meanwhile, the transport closes when dropped
+            release(&transport_produce, &mut *loads.lock().await);
+            return Err(e);
+        }
+        rooms.join(&routerNetwork, peerId);
+    }
+    // a transport created again replaces the previous one
+    let replaced = peers.with(|p| p.entry(peerId).transport.replace(transport_produce.clone()));
+    if let Some(replaced) = replaced {
+        release(&replaced, &mut *loads.lock().await);
+    }
     let transport_producer_id = transport_produce.id();
     let router_id_clone = router.id().clone();
     {
@@ -96,15 +146,12 @@ pub async fn create_webrtc_ingress(
     let t_id = transport_produce.id().clone();
     let r_id = router.id().clone();
     let transport2router_clone = transport2router.clone();
-    let endpoints_clone = endpoints.clone();
     let handle = tokio::runtime::Handle::current();
     transport_produce
         .on_close(Box::new(move || {
             handle.spawn(async move {
                 let mut transport2Router_remove = transport2router_clone.write().await;
                 transport2Router_remove.delete(t_id);
-                let mut endpoint_remove = endpoints_clone.lock().await;
-                endpoint_remove.delete(t_id);
             });
         }))
         .detach();
@@ -115,6 +162,7 @@ pub async fn create_webrtc_ingress(
         relays.clone(),
         pipetransports.clone(),
         routers.clone(),
+        room_routers.clone(),
         routerNetwork.clone(),
         routerPips.clone(),
         config.clone(),
@@ -123,6 +171,7 @@ pub async fn create_webrtc_ingress(
     .await?;
     let ingress_reply = ResponseMessage::OutgoingCommunication {
         ws: Some(wsid.clone()),
+        requestId: None,
         communication: MessageResponse::createdIngressTransport {
             data: CreatedIngressTransportData {
                 id: transport_produce.id().clone(),
@@ -134,11 +183,6 @@ pub async fn create_webrtc_ingress(
             },
         },
     };
-    // endpoints keeps track of the engpoints transport id and peerid
-    {
-        let mut endpoints_guard = endpoints.lock().await;
-        endpoints_guard.create(transport_produce.id(), peerId);
-    }
     if let Err(e) = sender.send(ingress_reply).await {
         error!("failed sending ingres reply");
     };
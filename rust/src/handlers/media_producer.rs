@@ -1,9 +1,10 @@
 #![allow(non_camel_case_types, non_snake_case)]
-use std::{collections::HashMap, ops::Deref, sync::Arc};
+use std::{ops::Deref, sync::Arc};
 
+use log::error;
 use mediasoup::{
-    prelude::{AppData, Consumer, ConsumerId},
-    producer::{Producer, ProducerId, ProducerOptions},
+    prelude::AppData,
+    producer::{ProducerId, ProducerOptions},
     rtp_parameters::{MediaKind, RtpCapabilities, RtpParameters},
     transport::Transport,
 };
@@ -12,16 +13,19 @@ use uuid::Uuid;
 
 use crate::{
     config::config::Config,
-    models::sfu::{
-        AudioProducers, PipeTransports, Relays, Transport2Router, Transports, VideoProducers,
+    handlers::error::{HandlerError, Limit},
+    models::{
+        peer::{attach, forget_producer, Peers},
+        room::Rooms,
+        sfu::{AudioObservers, PipeTransports, Relays, Transport2Router},
     },
     utils::{
         codec::{appData, ProductionOptionData, ResponseMessage},
-        utils::Mut,
+        utils::{Mut, Responder},
     },
 };
 
-use super::relay_consumer::create_consumer_relay;
+use super::{audio_observer::observe_audio_producer, relay_consumer::create_consumer_relay};
 
 #[derive(Clone)]
 pub struct ProducerReply {
@@ -35,15 +39,47 @@ pub struct ProducerReply {
     pub appData: appData,
 }
 
+// Whether `room` takes one more video producer under
+// max_video_producers_per_room. Checked before producing, to fail fast, and
+// again with the producer created while the rooms stay locked, so concurrent
+// producers cannot all take the last place.
+fn admit_video(
+    rooms: &Rooms,
+    peers: &Mut<Peers>,
+    room: &str,
+    max: Option<u32>,
+) -> Result<(), HandlerError> {
+    let max = match max {
+        Some(max) => max,
+        None => return Ok(()),
+    };
+    let videos: usize = match rooms.get(room) {
+        Some(room) => peers.with(|p| {
+            room.peers
+                .iter()
+                .filter_map(|peer_id| p.get(*peer_id))
+                .map(|peer| peer.producers_of(MediaKind::Video).len())
+                .sum()
+        }),
+        None => 0,
+    };
+    if videos >= max as usize {
+        return Err(HandlerError::LimitReached(
+            Limit::roomVideoProducers,
+            format!("room {} already has {} video producers", room, max),
+        ));
+    }
+    Ok(())
+}
+
 pub async fn create_media_producer(
-    transports: Arc<Mut<Transports>>,
+    peers: Arc<Mut<Peers>>,
     transport2router: Arc<RwLock<Transport2Router>>,
-    audioProducers: Arc<Mutex<AudioProducers>>,
-    videoProducers: Arc<Mutex<VideoProducers>>,
+    audioObservers: Arc<Mutex<AudioObservers>>,
+    signaling: Arc<Mut<Option<Sender<ResponseMessage>>>>,
     pipeTransports: Arc<Mut<PipeTransports>>,
     relays: Arc<Mutex<Relays>>,
-    producers: Arc<Mutex<HashMap<ProducerId, Producer>>>,
-    consumers: Arc<Mutex<HashMap<ConsumerId, Consumer>>>,
+    rooms: Arc<Mutex<Rooms>>,
     configs: Config,
     egress: Uuid,
     produceroptions: ProductionOptionData,
@@ -51,9 +87,17 @@ pub async fn create_media_producer(
     wsid: String,
     routerNetwork: String,
     rtpCapabilities: RtpCapabilities,
-    sender: Sender<ResponseMessage>,
-) -> Result<(), String> {
-    let get_media_producer = transports.with(|innger| innger.get(peerid));
+    sender: Responder,
+) -> Result<(), HandlerError> {
+    if produceroptions.kind == MediaKind::Video {
+        admit_video(
+            &*rooms.lock().await,
+            &peers,
+            &routerNetwork,
+            configs.room_limits.max_video_producers,
+        )?;
+    }
+    let get_media_producer = peers.with(|p| p.transport(peerid));
     let mut producer_options =
         ProducerOptions::new(produceroptions.kind, produceroptions.rtpParameters.clone());
     let app_data = appData(produceroptions.appData.0);
@@ -62,12 +106,26 @@ pub async fn create_media_producer(
         match media_producer.produce(producer_options).await {
             Ok(producer) => match producer.kind() {
                 MediaKind::Audio => {
-                    let mut audio_producers = audioProducers.lock().await;
-                    audio_producers.create(peerid, producer.clone());
-                    let mut producer_guard = producers.lock().await;
-                    producer_guard.insert(producer.id(), producer.clone());
+                    attach(&peers, peerid, producer.clone(), |peer, producer| {
+                        peer.producers.push(producer)
+                    })?;
+                    forget_producer(&peers, &producer);
+                    if let Err(e) = observe_audio_producer(
+                        audioObservers,
+                        signaling,
+                        configs.audio_level_interval,
+                        routerNetwork.clone(),
+                        peerid,
+                        producer.clone(),
+                        media_producer.router().clone(),
+                        false,
+                    )
+                    .await
+                    {
+                        error!("cannot observe audio producer {}: {}", producer.id(), e);
+                    }
                     create_consumer_relay(
-                        transports.clone(),
+                        peers.clone(),
                         transport2router.clone(),
                         relays.clone(),
                         pipeTransports.clone(),
@@ -89,34 +147,30 @@ pub async fn create_media_producer(
                             .clone(),
                         produceroptions.rtpParameters.clone(),
                         rtpCapabilities.clone(),
-                        consumers.clone(),
                         sender.clone(),
                     )
                     .await?;
-                    let producers_clone = producers.clone();
-                    let producer_id = producer.clone().id();
-                    let consumers_clone = consumers.clone();
-                    let handle = tokio::runtime::Handle::current();
                     producer
-                        .on_close(move || {
-                            handle.spawn(async move {
-                                let mut producer_close = producers_clone.lock().await;
-                                let mut consumer_close = consumers_clone.lock().await;
-                                for consumer in consumer_close.clone().into_iter() {
-                                    if consumer.1.producer_id() == producer_id {
-                                        consumer_close.remove(&consumer.0);
-                                    }
-                                }
-                                producer_close.remove(&producer_id);
-                                println!("closing this producer");
-                            });
-                        })
+                        .on_close(Box::new(move || println!("closing this producer")))
                         .detach();
                     return Ok(());
                 }
                 MediaKind::Video => {
-                    let mut videoProducers = videoProducers.lock().await;
-                    videoProducers.create(peerid, producer.clone());
+                    {
+                        // counted again under the rooms lock, the producer
+                        // closes when the room got its last video meanwhile
+                        let rooms = rooms.lock().await;
+                        admit_video(
+                            &rooms,
+                            &peers,
+                            &routerNetwork,
+                            configs.room_limits.max_video_producers,
+                        )?;
+                        attach(&peers, peerid, producer.clone(), |peer, producer| {
+                            peer.producers.push(producer)
+                        })?;
+                    }
+                    forget_producer(&peers, &producer);
                     producer
                         .on_trace(move |trace| {
                             println!("trace {:?}", trace);
@@ -126,7 +180,7 @@ pub async fn create_media_producer(
                         .on_close(Box::new(move || println!("closing this producer video")))
                         .detach();
                     create_consumer_relay(
-                        transports.clone(),
+                        peers.clone(),
                         transport2router.clone(),
                         relays.clone(),
                         pipeTransports.clone(),
@@ -148,22 +202,23 @@ pub async fn create_media_producer(
                             .clone(),
                         produceroptions.rtpParameters.clone(),
                         rtpCapabilities.clone(),
-                        consumers.clone(),
                         sender.clone(),
                     )
                     .await?;
-                    let mut producer_guard = producers.lock().await;
-                    producer_guard.insert(producer.id(), producer);
                     return Ok(());
                 }
             },
             Err(_) => {
                 println!("failed to create producer");
-                Err("failed to create producer".to_string())
+                Err(HandlerError::Mediasoup(
+                    "failed to create producer".to_string(),
+                ))
             }
         }
     } else {
         println!("can't find the peer router");
-        Err("error creating producer".to_string())
+        Err(HandlerError::NotFound(
+            "error creating producer".to_string(),
+        ))
     }
 }
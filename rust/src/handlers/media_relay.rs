@@ -1,20 +1,22 @@
 use std::sync::Arc;
 
 use mediasoup::{
-    data_structures::SctpState,
-    prelude::{ListenIp, PipeTransportOptions},
-    router::RouterId,
+    data_structures::SctpState, prelude::PipeTransportOptions, router::RouterId,
     transport::Transport,
 };
-use tokio::sync::{mpsc::Sender, Mutex};
+use tokio::sync::Mutex;
 use uuid::Uuid;
 
 use crate::{
     config::config::Config,
-    models::sfu::{PipeTransports, Relays, Routers},
+    handlers::error::HandlerError,
+    models::{
+        room::Rooms,
+        sfu::{PipeTransports, Relays, Routers},
+    },
     utils::{
         codec::{MessageResponse, ResponseMessage, StorePipRelayData},
-        utils::{get_nodeid, Mut},
+        utils::{get_nodeid, Mut, Responder},
     },
 };
 
@@ -23,11 +25,12 @@ pub async fn create_ingress_relay(
     relays: Arc<Mutex<Relays>>,
     pipetransports: Arc<Mut<PipeTransports>>,
     routers: Arc<Mutex<Routers>>,
+    rooms: Arc<Mutex<Rooms>>,
     room_name: String,
     router_pips: Vec<Option<Uuid>>,
     config: Config,
-    sender: Sender<ResponseMessage>,
-) -> Result<(), String> {
+    sender: Responder,
+) -> Result<(), HandlerError> {
     let piptransports_clone = pipetransports.clone();
     let relay_clone = relays.clone();
     let routers_clone = routers.clone();
@@ -42,20 +45,7 @@ pub async fn create_ingress_relay(
             let routers_guard = routers_clone.lock().await;
             let get_routers = routers_guard.get(router_id);
             if let Some(router) = get_routers {
-                let listen_ip;
-                if config.traversenat == "true" {
-                    listen_ip = ListenIp {
-                        //ip: "127.0.0.1".parse().unwrap(),
-                        ip: "0.0.0.0".parse().unwrap(),
-                        announced_ip: Some(config.announceip),
-                    };
-                } else {
-                    listen_ip = ListenIp {
-                        //ip: "127.0.0.1".parse().unwrap(),
-                        ip: local_ipaddress::get().unwrap().parse().unwrap(),
-                        announced_ip: None,
-                    };
-                };
+                let listen_ip = config.listen_ip();
                 let mut pipe_options = PipeTransportOptions::new(listen_ip);
                 pipe_options.enable_sctp = true;
                 pipe_options.enable_rtx = false;
@@ -65,7 +55,10 @@ pub async fn create_ingress_relay(
                         .create_pipe_transport(pipe_options)
                         .await
                         .map_err(|error| {
-                            format!("Failed to create ingress Pipe transport: {:?}", error)
+                            HandlerError::Mediasoup(format!(
+                                "Failed to create ingress Pipe transport: {:?}",
+                                error
+                            ))
                         })?;
                 new_pipe_transport
                     .on_sctp_state_change(move |sctp_state| match sctp_state {
@@ -97,9 +90,14 @@ pub async fn create_ingress_relay(
                         new_pipe_transport.clone(),
                     )
                 });
+                rooms
+                    .lock()
+                    .await
+                    .add_relay(&room_name, new_pipe_transport.id());
                 let pipe_replay = ResponseMessage::OutgoingServer {
                     //wsid: Some(wsid.clone()),
                     node: get_nodeid(config.ingress, config.egress),
+                    requestId: None,
                     message: MessageResponse::storePipeRelay {
                         data: StorePipRelayData {
                             ingressRoute: router_id.clone(),
@@ -1,24 +1,36 @@
 pub mod media_producer;
 // // pub mod comsumer;
 pub mod audio_consumer;
+pub mod audio_observer;
+pub mod bot_peer;
 pub mod codecs;
 pub mod connect_ingress_egress;
+pub mod consumer_layers;
 pub mod cpu_load;
+pub mod data_gate;
 pub mod data_relay_producer;
 pub mod egress;
+pub mod error;
 pub mod event_consumer;
 pub mod event_producer;
 pub mod ingress;
 pub mod media_relay;
 pub mod movement_consumer;
 pub mod movement_producer;
+pub mod movement_tap;
 pub mod pendingrelays;
+pub mod plain_transport;
+pub mod recording;
 pub mod relay_connect;
 pub mod relay_consumer;
 pub mod relay_egress;
 pub mod relay_producer;
 pub mod router;
+pub mod stats;
 pub mod test_cpu_load;
+pub mod test_data_gate;
+pub mod test_recording;
+pub mod test_stats;
 // pub mod test_create_router_group_new_room;
 pub mod video_consumer;
 pub mod worker;
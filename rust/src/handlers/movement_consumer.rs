@@ -1,20 +1,20 @@
 use log::{error, info};
 use mediasoup::{
-    prelude::{AppData, DataConsumer, DataConsumerId, DataConsumerOptions},
+    prelude::{AppData, DataConsumerOptions},
     transport::Transport,
 };
 use std::{collections::HashMap, ops::Deref, sync::Arc};
-use tokio::sync::{mpsc::Sender, Mutex};
 use uuid::Uuid;
 
 use crate::{
+    handlers::error::HandlerError,
     models::{
         message::NewDataConsumerOptions,
-        sfu::{MovementProducers, PeerMovementConsumed, PeerMovementConsumedData, Transports},
+        peer::{attach, Peers},
     },
     utils::{
         codec::{appData, MessageResponse, ResponseMessage},
-        utils::Mut,
+        utils::{Mut, Responder},
     },
 };
 
@@ -22,16 +22,13 @@ pub async fn consume_movement(
     wsid: String,
     producer_peers: Vec<Uuid>,
     consumer_peer: Uuid,
-    transports: Arc<Mut<Transports>>,
-    movment_producers: Arc<Mut<MovementProducers>>,
-    peermovementconsumed: Arc<Mutex<PeerMovementConsumed>>,
-    data_consumers: Arc<Mutex<HashMap<DataConsumerId, DataConsumer>>>,
-    sender: Sender<ResponseMessage>,
-) -> Result<(), String> {
+    peers: Arc<Mut<Peers>>,
+    sender: Responder,
+) -> Result<(), HandlerError> {
     let mut movement_announcement: HashMap<Uuid, NewDataConsumerOptions> = HashMap::new();
     for peer in producer_peers.into_iter() {
-        let get_transport = transports.with(|inner| inner.get(consumer_peer));
-        let get_producers = movment_producers.with(|mp| mp.get(peer).clone());
+        let get_transport = peers.with(|p| p.transport(consumer_peer));
+        let get_producers = peers.with(|p| p.get(peer).and_then(|p| p.movement.clone()));
         if get_producers.is_none() {
             info!(
                 "Error: consumer peer movement producer is not defined on this egress server. movement producer {}",
@@ -63,7 +60,9 @@ pub async fn consume_movement(
         let new_data_consumer = transport
             .consume_data(data_consumer_options)
             .await
-            .map_err(|_error| format!("Error consuming data movement consumer!"))?;
+            .map_err(|_error| {
+                HandlerError::Mediasoup(format!("Error consuming data movement consumer!"))
+            })?;
 
         new_data_consumer
             .on_close(move || {
@@ -87,20 +86,27 @@ pub async fn consume_movement(
                 .deref()
                 .clone(),
         };
-        let peer_consume_data = PeerMovementConsumedData {
-            peer_id: consumer_peer,
-            produce_id: new_data_consumer.data_producer_id(),
-            consumer_id: new_data_consumer.id(),
-        };
-        let mut peerdataconsumed = peermovementconsumed.lock().await;
-        peerdataconsumed.create(peer_consume_data);
         movement_announcement.insert(peer, movement_consumer_options);
-        let mut data_consumers = data_consumers.lock().await;
-        data_consumers.insert(new_data_consumer.id(), new_data_consumer);
+        attach(
+            &peers,
+            consumer_peer,
+            new_data_consumer,
+            |peer, consumer| peer.data_consumers.push(consumer),
+        )?;
     }
     if movement_announcement.len() > 0 {
+        let snapshot: HashMap<Uuid, Vec<u8>> = peers.with(|p| {
+            movement_announcement
+                .keys()
+                .filter_map(|peer| {
+                    let frame = p.get(*peer)?.movement_frame.as_ref()?;
+                    Some((*peer, frame.to_bytes()))
+                })
+                .collect()
+        });
         let reply_consumer = ResponseMessage::OutgoingCommunication {
             ws: Some(wsid.clone()),
+            requestId: None,
             communication: MessageResponse::movementAnnouncement {
                 data: movement_announcement,
             },
@@ -108,6 +114,17 @@ pub async fn consume_movement(
         if let Err(e) = sender.send(reply_consumer).await {
             error!("error ending message: {:?}", e);
         };
+        // peers that do not move would stay invisible until their next packet
+        if !snapshot.is_empty() {
+            let reply_snapshot = ResponseMessage::OutgoingCommunication {
+                ws: Some(wsid.clone()),
+                requestId: None,
+                communication: MessageResponse::movementSnapshot { data: snapshot },
+            };
+            if let Err(e) = sender.send(reply_snapshot).await {
+                error!("error sending movement snapshot: {:?}", e);
+            };
+        }
     }
     Ok(())
 }
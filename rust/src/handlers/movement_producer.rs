@@ -1,12 +1,9 @@
-use std::{collections::HashMap, ops::Deref, sync::Arc};
+use std::{ops::Deref, sync::Arc};
 
 use log::info;
 use mediasoup::{
     data_producer::DataProducerOptions,
-    prelude::{
-        AppData, DataConsumer, DataConsumerId, DataConsumerOptions, DataProducer, DataProducerId,
-        SctpStreamParameters,
-    },
+    prelude::{AppData, DataConsumerOptions, SctpStreamParameters},
     transport::Transport,
 };
 use tokio::sync::{mpsc::Sender, Mutex, RwLock};
@@ -14,16 +11,20 @@ use uuid::Uuid;
 
 use crate::{
     config::config::Config,
-    models::sfu::{
-        Endpoints, MovementProducers, PeerMovementConsumed, PeerMovementConsumedData,
-        PipeTransports, Relays, Transport2Router, Transports,
+    handlers::{
+        error::HandlerError,
+        movement_tap::{tap_movement, taps_movement},
+    },
+    models::{
+        peer::{attach, Peers},
+        sfu::{DataTaps, PipeTransports, Relays, Transport2Router},
     },
     utils::{
         codec::{
             appData, CreateRelayProducerMessage, DataProduceOptionsData, MessageResponse,
             ProduceData, ResponseMessage,
         },
-        utils::{get_nodeid, Mut},
+        utils::{get_nodeid, Mut, Responder},
     },
 };
 
@@ -33,23 +34,21 @@ pub async fn create_movement_data_producer(
     peer_id: Uuid,
     egress: Uuid,
     produce_options: DataProduceOptionsData,
-    transports: Arc<Mut<Transports>>,
-    movement_producers: Arc<Mut<MovementProducers>>,
+    peers: Arc<Mut<Peers>>,
+    data_taps: Arc<Mut<DataTaps>>,
+    signaling: Arc<Mut<Option<Sender<ResponseMessage>>>>,
     transport_2_router: Arc<RwLock<Transport2Router>>,
     pipe_transports: Arc<Mut<PipeTransports>>,
     relays: Arc<Mutex<Relays>>,
-    endpoints: Arc<Mutex<Endpoints>>,
-    peer_movement_consumed: Arc<Mutex<PeerMovementConsumed>>,
-    data_producers: Arc<Mutex<HashMap<DataProducerId, DataProducer>>>,
-    data_consumers: Arc<Mutex<HashMap<DataConsumerId, DataConsumer>>>,
     config: Config,
-    sender: Sender<ResponseMessage>,
-) -> Result<(), String> {
-    let transports = transports.with(|inner| inner.clone());
-    let get_transport = transports.get(peer_id);
+    sender: Responder,
+) -> Result<(), HandlerError> {
+    let get_transport = peers.with(|p| p.transport(peer_id));
     if get_transport.is_none() {
         println!("cannot find transport for peer: {}", &peer_id);
-        return Err("cannot find transport for peer".to_string());
+        return Err(HandlerError::NotFound(
+            "cannot find transport for peer".to_string(),
+        ));
     } else {
         let transport = get_transport.unwrap();
         let sctp_stream_parameters =
@@ -62,7 +61,9 @@ pub async fn create_movement_data_producer(
         let data_producer = transport
             .produce_data(data_producer_options)
             .await
-            .map_err(|error| format!("error creating data producer: {:?}", error))?;
+            .map_err(|error| {
+                HandlerError::Mediasoup(format!("error creating data producer: {:?}", error))
+            })?;
         // let producers_clone = data_producers.clone();
         // let producer_id = data_producer.clone().id();
         // let consumers_clone = data_consumers.clone();
@@ -71,51 +72,79 @@ pub async fn create_movement_data_producer(
                 info!("data channel closing this producer");
             }))
             .detach();
-        movement_producers.with(|mp| mp.create(peer_id, data_producer.clone()));
+        // with validate_movement or data limits only the checked copy is
+        // relayed and consumed
+        let fanned_out = if taps_movement(&config) || config.data_limits.is_some() {
+            tap_movement(
+                data_taps,
+                peers.clone(),
+                signaling,
+                peer_id,
+                &data_producer,
+                transport.router().clone(),
+                &config,
+                config.data_limits.clone(),
+            )
+            .await?
+            .unwrap_or_else(|| data_producer.clone())
+        } else {
+            data_producer.clone()
+        };
+        let _replaced = attach(
+            &peers,
+            peer_id,
+            (data_producer.clone(), fanned_out.clone()),
+            |peer, (data_producer, fanned_out)| {
+                peer.data_producers.push(data_producer);
+                peer.movement.replace(fanned_out)
+            },
+        )?;
         //Prepare the media to be sent over the network to a egress server, and send signal
         let get_transport2_router = transport_2_router.read().await.get(transport.id());
         if get_transport2_router.is_none() {
-            return Err("cannot find transport to router".to_string());
+            return Err(HandlerError::NotFound(
+                "cannot find transport to router".to_string(),
+            ));
         }
         let get_relay_data_consumer = relays
             .lock()
             .await
             .get_ingress_egress(get_transport2_router.unwrap(), egress);
         if get_relay_data_consumer.is_empty() {
-            return Err("cannot find relay in movement producer".to_string());
+            return Err(HandlerError::NotFound(
+                "cannot find relay in movement producer".to_string(),
+            ));
         }
 
         let get_relay_transport =
             pipe_transports.with(|p| p.get(get_relay_data_consumer[0].transport));
         if get_relay_transport.is_none() {
-            return Err("cannot find pipetransport".to_string());
+            return Err(HandlerError::NotFound(
+                "cannot find pipetransport".to_string(),
+            ));
         }
         let relay_data_tranport = get_relay_transport.unwrap();
-        let rtp_capabilities = endpoints.lock().await.get(transport.id());
-        if rtp_capabilities.is_none() {
-            return Err("cannot find endpoint:".to_string());
-        }
-        let mut consumer_options =
-            DataConsumerOptions::new_sctp_ordered(data_producer.clone().id());
+        let mut consumer_options = DataConsumerOptions::new_sctp_ordered(fanned_out.id());
         consumer_options.app_data = AppData::new(produce_options.appData.clone());
         let relay_data_consumer = relay_data_tranport
             .pipe_transport
             .consume_data(consumer_options)
             .await
-            .map_err(|error| format!("error consuming data movement producer {:?}", error))?;
-        let peer_consume_data = PeerMovementConsumedData {
+            .map_err(|error| {
+                HandlerError::Mediasoup(format!(
+                    "error consuming data movement producer {:?}",
+                    error
+                ))
+            })?;
+        attach(
+            &peers,
             peer_id,
-            produce_id: relay_data_consumer.data_producer_id(),
-            consumer_id: relay_data_consumer.id(),
-        };
-        let mut peer_data_consumed = peer_movement_consumed.lock().await;
-        peer_data_consumed.create(peer_consume_data);
-        let mut producers = data_producers.lock().await;
-        producers.insert(data_producer.id(), data_producer.clone());
-        let mut consumers = data_consumers.lock().await;
-        consumers.insert(relay_data_consumer.id(), relay_data_consumer.clone());
+            relay_data_consumer.clone(),
+            |peer, consumer| peer.data_consumers.push(consumer),
+        )?;
         let server_relay = ResponseMessage::OutgoingServer {
             node: get_nodeid(config.ingress, config.egress),
+            requestId: None,
             message: MessageResponse::createRelayProducer {
                 data: CreateRelayProducerMessage {
                     groupId: router_network,
@@ -142,6 +171,7 @@ pub async fn create_movement_data_producer(
         let _ = sender.send(server_relay).await;
         let produced_message = ResponseMessage::OutgoingCommunication {
             ws: Some(wsid.clone()),
+            requestId: None,
             communication: MessageResponse::producedData {
                 data: ProduceData {
                     dataProducerId: data_producer.id(),
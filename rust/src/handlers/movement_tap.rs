@@ -0,0 +1,148 @@
+use std::{borrow::Cow, sync::Arc, time::Instant};
+
+use log::{debug, error};
+use mediasoup::{
+    data_structures::WebRtcMessage,
+    prelude::{DataConsumerOptions, DataProducer, Transport},
+    router::Router,
+};
+use tokio::sync::mpsc::Sender;
+use uuid::Uuid;
+
+use crate::{
+    config::config::{Config, DataLimits},
+    handlers::{
+        data_gate::{limit, message_len, produce_forwarded, tap_transport, DataLimiter},
+        error::HandlerError,
+    },
+    models::{
+        movement::MovementPacket,
+        peer::{attach, Peers},
+        sfu::DataTaps,
+    },
+    utils::{codec::ResponseMessage, utils::Mut},
+};
+
+// Whether movement has to be read back on this node at all.
+pub fn taps_movement(config: &Config) -> bool {
+    config.movement_snapshot || config.interest.is_some() || config.validate_movement
+}
+
+// Consume the movement of `peer_id` on a direct transport of `router`, the
+// router of `source`. The merged frame is kept for movementSnapshot, the base
+// position for the interest manager and, with validate_movement or `limits`,
+// the messages within the limits, and with validate_movement well formed, are
+// sent again on a direct data producer which is returned to be fanned out in
+// place of `source`.
+pub async fn tap_movement(
+    data_taps: Arc<Mut<DataTaps>>,
+    peers: Arc<Mut<Peers>>,
+    signaling: Arc<Mut<Option<Sender<ResponseMessage>>>>,
+    peer_id: Uuid,
+    source: &DataProducer,
+    router: Router,
+    config: &Config,
+    limits: Option<DataLimits>,
+) -> Result<Option<DataProducer>, HandlerError> {
+    let transport = tap_transport(&data_taps, &router).await?;
+    let validated = if config.validate_movement || limits.is_some() {
+        Some(produce_forwarded(&transport, source).await?)
+    } else {
+        None
+    };
+    let consumer = transport
+        .consume_data(DataConsumerOptions::new_direct(source.id()))
+        .await
+        .map_err(|error| {
+            HandlerError::Mediasoup(format!("Failed to tap movement producer: {}", error))
+        })?;
+
+    let track_positions = config.interest.is_some();
+    let keep_frames = config.movement_snapshot;
+    let validate = config.validate_movement;
+    {
+        let label = source.label().clone();
+        let producer_id = source.id();
+        let limiter = limits.map(|limits| Mut::new(DataLimiter::new(limits, Instant::now())));
+        let peers = peers.clone();
+        let validated = validated.as_ref().map(|p| p.downgrade());
+        consumer
+            .on_message(move |message| {
+                if let Some(limiter) = &limiter {
+                    let len = message_len(message);
+                    if !limit(limiter, &signaling, peer_id, producer_id, &label, len) {
+                        return;
+                    }
+                }
+                let forward = |message: WebRtcMessage| {
+                    if let Some(DataProducer::Direct(producer)) =
+                        validated.as_ref().and_then(|p| p.upgrade())
+                    {
+                        if let Err(e) = producer.send(message) {
+                            error!("cannot forward movement of {}: {}", peer_id, e);
+                        }
+                    }
+                };
+                // only limited, what clients sent goes on as is
+                if !validate {
+                    forward(message.clone());
+                }
+                let packet = match message {
+                    WebRtcMessage::Binary(packet) => packet.as_ref(),
+                    _ => {
+                        debug!("non binary movement of {}", peer_id);
+                        return;
+                    }
+                };
+                let movement = match MovementPacket::parse(packet) {
+                    Ok(movement) => movement,
+                    Err(e) => {
+                        debug!("malformed movement of {}: {}", peer_id, e);
+                        return;
+                    }
+                };
+                if keep_frames || track_positions {
+                    peers.with(|p| {
+                        if let Some(peer) = p.get_mut(peer_id) {
+                            if keep_frames {
+                                peer.update_frame(movement);
+                            }
+                            if track_positions {
+                                if let Some(position) = movement.base_position() {
+                                    peer.position = Some(position);
+                                }
+                            }
+                        }
+                    });
+                }
+                if validate {
+                    forward(WebRtcMessage::Binary(Cow::Borrowed(movement.as_bytes())));
+                }
+            })
+            .detach();
+    }
+    // the position goes with the movement, the validated producer closes with
+    // the one it stands for
+    let validated_id = validated.as_ref().map(|p| p.id());
+    {
+        let peers = peers.clone();
+        consumer
+            .on_data_producer_close(move || {
+                let _closed = peers.with(|p| {
+                    let peer = p.get_mut(peer_id)?;
+                    peer.position = None;
+                    match &peer.movement {
+                        Some(movement) if Some(movement.id()) == validated_id => {
+                            peer.movement.take()
+                        }
+                        _ => None,
+                    }
+                });
+            })
+            .detach();
+    }
+    attach(&peers, peer_id, consumer, |peer, consumer| {
+        peer.taps.push(consumer)
+    })?;
+    Ok(validated)
+}
@@ -3,7 +3,7 @@ use std::{net::IpAddr, sync::Arc};
 use mediasoup::{router::RouterId, srtp_parameters::SrtpParameters};
 use tokio::sync::Mutex;
 
-use crate::models::sfu::PendingRelays;
+use crate::{handlers::error::HandlerError, models::sfu::PendingRelays};
 
 pub async fn store_pipe_relay(
     pending_relays: Arc<Mutex<PendingRelays>>,
@@ -11,7 +11,7 @@ pub async fn store_pipe_relay(
     ip: IpAddr,
     port: u16,
     srtp: SrtpParameters,
-) -> Result<(), String> {
+) -> Result<(), HandlerError> {
     pending_relays
         .lock()
         .await
@@ -0,0 +1,318 @@
+use std::{net::IpAddr, sync::Arc};
+
+use log::{error, info};
+use mediasoup::{
+    prelude::{
+        AppData, ConsumerId, ConsumerOptions, PlainTransport, PlainTransportOptions,
+        PlainTransportRemoteParameters, Transport,
+    },
+    producer::{ProducerId, ProducerOptions},
+    router::Router,
+    rtp_parameters::{MediaKind, RtpParameters},
+};
+use tokio::sync::{mpsc::Sender, Mutex};
+
+use crate::{
+    config::config::Config,
+    handlers::{
+        audio_observer::observe_audio_producer, codecs::router_capabilities, error::HandlerError,
+        relay_consumer::relay_to_egress,
+    },
+    models::{
+        peer::{forget_producer, Peers},
+        room::Rooms,
+        sfu::{AudioObservers, PipeTransports, PlainTransportData, PlainTransports, Relays},
+    },
+    utils::{
+        codec::{
+            CreatePlainEgressData, CreatePlainIngressData, CreatedPlainTransportData,
+            MessageResponse, ResponseMessage,
+        },
+        utils::{Mut, Responder},
+    },
+};
+
+// Consume a producer of the room on a plain transport of its router, sending
+// RTP to a local process. consumerPause/consumerResume find the consumer with
+// the plain transport.
+pub async fn create_plain_egress(
+    wsid: String,
+    data: CreatePlainEgressData,
+    room_routers: Arc<Mutex<Rooms>>,
+    peers: Arc<Mut<Peers>>,
+    plain_transports: Arc<Mut<PlainTransports>>,
+    config: Config,
+    sender: Responder,
+) -> Result<(), HandlerError> {
+    let producer = peers
+        .with(|p| p.producer(data.producerId))
+        .map(|(_, producer)| producer)
+        .ok_or(HandlerError::NotFound(format!(
+            "cannot find producer on this server: {}",
+            data.producerId
+        )))?;
+    let router = producer.transport().router().clone();
+    if !room_has_router(&room_routers, &data.room, &router).await {
+        return Err(HandlerError::NotFound(format!(
+            "producer {} is not in room {}",
+            data.producerId, data.room
+        )));
+    }
+
+    let transport = create_plain_transport(
+        &router,
+        &data.ip,
+        data.port,
+        data.rtcpMux,
+        data.comedia,
+        &config,
+    )
+    .await?;
+    let mut options =
+        ConsumerOptions::new(producer.id(), router_capabilities(&config.media_codecs));
+    options.paused = false;
+    let consumer = transport.consume(options).await.map_err(|error| {
+        HandlerError::Mediasoup(format!(
+            "Failed to consume producer on plain egress: {}",
+            error
+        ))
+    })?;
+
+    // the egress goes away with the producer it forwards
+    let transport_id = transport.id();
+    let consumer_id = consumer.id();
+    {
+        let plain_transports = plain_transports.clone();
+        consumer
+            .on_producer_close(move || {
+                let _closed = plain_transports.with(|p| p.remove(transport_id));
+            })
+            .detach();
+    }
+    let created = created_data(
+        &transport,
+        producer.id(),
+        Some(consumer_id),
+        consumer.rtp_parameters().clone(),
+    );
+    plain_transports.with(|p| {
+        p.create(PlainTransportData {
+            room: data.room,
+            transport,
+            consumer: Some(consumer),
+            producer: None,
+        })
+    });
+    info!(
+        "plain egress {} of producer {}",
+        transport_id,
+        producer.id()
+    );
+    let msg = ResponseMessage::OutgoingCommunication {
+        ws: Some(wsid),
+        requestId: None,
+        communication: MessageResponse::createdPlainEgress { data: created },
+    };
+    let _ = sender.send(msg).await;
+    Ok(())
+}
+
+// Produce what a local process sends on a plain transport of the room, as
+// peerId. The producer is registered, observed and relayed to egress exactly
+// like one of a WebRTC transport so consumeAudio/consumeVideo find it.
+pub async fn create_plain_ingress(
+    wsid: String,
+    data: CreatePlainIngressData,
+    room_routers: Arc<Mutex<Rooms>>,
+    relays: Arc<Mutex<Relays>>,
+    pipe_transports: Arc<Mut<PipeTransports>>,
+    peers: Arc<Mut<Peers>>,
+    audio_observers: Arc<Mutex<AudioObservers>>,
+    signaling: Arc<Mut<Option<Sender<ResponseMessage>>>>,
+    plain_transports: Arc<Mut<PlainTransports>>,
+    config: Config,
+    sender: Responder,
+) -> Result<(), HandlerError> {
+    let routers = room_routers
+        .lock()
+        .await
+        .routers(&data.room)
+        .ok_or(HandlerError::NotFound(format!(
+            "cannot find room {}",
+            data.room
+        )))?;
+    // any router of the room already relayed to the egress
+    let mut router = None;
+    {
+        let relays = relays.lock().await;
+        for room_router in routers.into_iter() {
+            if !relays
+                .get_ingress_egress(room_router.id(), data.egress)
+                .is_empty()
+            {
+                router = Some(room_router);
+                break;
+            }
+        }
+    }
+    let router = router.ok_or(HandlerError::NotFound(format!(
+        "room {} has no relay to egress {}",
+        data.room, data.egress
+    )))?;
+
+    let transport = create_plain_transport(
+        &router,
+        &data.ip,
+        data.port,
+        data.rtcpMux,
+        data.comedia,
+        &config,
+    )
+    .await?;
+    let mut options = ProducerOptions::new(data.kind, data.rtpParameters);
+    options.app_data = AppData::new(data.appData.clone());
+    let producer = transport.produce(options).await.map_err(|error| {
+        HandlerError::Mediasoup(format!("Failed to produce on plain ingress: {}", error))
+    })?;
+
+    peers.with(|p| p.entry(data.peerId).producers.push(producer.clone()));
+    forget_producer(&peers, &producer);
+    if producer.kind() == MediaKind::Audio {
+        if let Err(e) = observe_audio_producer(
+            audio_observers,
+            signaling,
+            config.audio_level_interval,
+            data.room.clone(),
+            data.peerId,
+            producer.clone(),
+            router.clone(),
+            false,
+        )
+        .await
+        {
+            error!("cannot observe audio producer {}: {}", producer.id(), e);
+        }
+    }
+    let created = created_data(
+        &transport,
+        producer.id(),
+        None,
+        producer.rtp_parameters().clone(),
+    );
+    plain_transports.with(|p| {
+        p.create(PlainTransportData {
+            room: data.room.clone(),
+            transport,
+            consumer: None,
+            producer: Some((data.peerId, producer.clone())),
+        })
+    });
+    let msg = ResponseMessage::OutgoingCommunication {
+        ws: Some(wsid.clone()),
+        requestId: None,
+        communication: MessageResponse::createdPlainIngress { data: created },
+    };
+    let _ = sender.send(msg).await;
+
+    relay_to_egress(
+        router.id(),
+        relays,
+        pipe_transports,
+        data.room,
+        config.clone(),
+        wsid,
+        data.peerId,
+        data.egress,
+        producer.id(),
+        data.appData,
+        router_capabilities(&config.media_codecs),
+        peers,
+        sender,
+    )
+    .await
+}
+
+// Close a plain egress or ingress, an ingress producer is unregistered with it.
+pub async fn close_plain_transport(data: PlainTransportData, peers: Arc<Mut<Peers>>) {
+    if let Some((_, producer)) = &data.producer {
+        let _closed = peers.with(|p| p.remove_producer(producer.id()));
+    }
+    info!("closing plain transport {}", data.transport.id());
+}
+
+async fn room_has_router(room_routers: &Arc<Mutex<Rooms>>, room: &str, router: &Router) -> bool {
+    room_routers
+        .lock()
+        .await
+        .get(room)
+        .map(|room| room.routers.iter().any(|r| r.id() == router.id()))
+        .unwrap_or(false)
+}
+
+// Plain transport on the listen ip, connected to ip:port unless comedia.
+async fn create_plain_transport(
+    router: &Router,
+    ip: &Option<IpAddr>,
+    port: Option<u16>,
+    rtcp_mux: bool,
+    comedia: bool,
+    config: &Config,
+) -> Result<PlainTransport, HandlerError> {
+    let mut options = PlainTransportOptions::new(config.listen_ip());
+    options.rtcp_mux = rtcp_mux;
+    options.comedia = comedia;
+    let transport = router
+        .create_plain_transport(options)
+        .await
+        .map_err(|error| {
+            HandlerError::Mediasoup(format!("Failed to create plain transport: {}", error))
+        })?;
+    if comedia {
+        return Ok(transport);
+    }
+    let (ip, port) = match (ip, port) {
+        (Some(ip), Some(port)) => (*ip, port),
+        _ => {
+            return Err(HandlerError::InvalidRequest(
+                "ip and port are required without comedia".to_string(),
+            ))
+        }
+    };
+    let rtcp_port = if rtcp_mux {
+        None
+    } else {
+        Some(port.checked_add(1).ok_or(HandlerError::InvalidRequest(
+            "no port left for RTCP without rtcpMux".to_string(),
+        ))?)
+    };
+    transport
+        .connect(PlainTransportRemoteParameters {
+            ip: Some(ip),
+            port: Some(port),
+            rtcp_port,
+            srtp_parameters: None,
+        })
+        .await
+        .map_err(|error| {
+            HandlerError::Mediasoup(format!("Failed to connect plain transport: {}", error))
+        })?;
+    Ok(transport)
+}
+
+fn created_data(
+    transport: &PlainTransport,
+    producer_id: ProducerId,
+    consumer_id: Option<ConsumerId>,
+    rtp_parameters: RtpParameters,
+) -> CreatedPlainTransportData {
+    let tuple = transport.tuple();
+    CreatedPlainTransportData {
+        transportId: transport.id(),
+        ip: tuple.local_ip(),
+        port: tuple.local_port(),
+        rtcpPort: transport.rtcp_tuple().map(|t| t.local_port()),
+        producerId: producer_id,
+        consumerId: consumer_id,
+        rtpParameters: rtp_parameters,
+    }
+}
@@ -0,0 +1,365 @@
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use log::{error, info};
+use mediasoup::{
+    prelude::{
+        ConsumerOptions, ListenIp, MediaKind, PlainTransportOptions,
+        PlainTransportRemoteParameters, Transport,
+    },
+    producer::Producer,
+    rtp_parameters::RtpCodecCapability,
+};
+use tokio::{
+    fs::{self, File},
+    io::{AsyncWriteExt, BufWriter},
+    net::UdpSocket,
+    sync::{oneshot, Mutex},
+};
+use uuid::Uuid;
+
+use crate::{
+    config::config::Config,
+    handlers::{codecs::router_capabilities, error::HandlerError},
+    models::{
+        peer::Peers,
+        room::Rooms,
+        sfu::{Recording, RecordingTrack, Recordings},
+    },
+    utils::{
+        codec::{
+            MessageResponse, RecordingData, RecordingFile, ResponseMessage, StartRecordingData,
+            StopRecordingData,
+        },
+        utils::{Mut, Responder},
+    },
+};
+
+// largest datagram mediasoup sends on the plain transport
+const MAX_PACKET: usize = 65536;
+
+// Record the producers of a room present on this node into one rtpdump file
+// each under recording_dir/<room>. Producers created later are not recorded.
+pub async fn start_recording(
+    wsid: String,
+    data: StartRecordingData,
+    room_routers: Arc<Mutex<Rooms>>,
+    peers: Arc<Mut<Peers>>,
+    recordings: Arc<Mutex<Recordings>>,
+    config: Config,
+    sender: Responder,
+) -> Result<(), HandlerError> {
+    let recording_dir = config
+        .recording_dir
+        .clone()
+        .ok_or(HandlerError::InvalidRequest(
+            "recording is disabled on this node, set recording_dir".to_string(),
+        ))?;
+    if data.kinds.is_empty() {
+        return Err(HandlerError::InvalidRequest(
+            "startRecording needs at least one kind".to_string(),
+        ));
+    }
+    let router_ids: Vec<_> = room_routers
+        .lock()
+        .await
+        .routers(&data.room)
+        .ok_or(HandlerError::NotFound(format!(
+            "cannot find room {}",
+            data.room
+        )))?
+        .iter()
+        .map(|router| router.id())
+        .collect();
+
+    // held until the recording is stored so the room is only recorded once
+    let mut recordings = recordings.lock().await;
+    if recordings.contains(&data.room) {
+        return Err(HandlerError::AlreadyExists(format!(
+            "room {} is already being recorded",
+            data.room
+        )));
+    }
+    let mut producers = Vec::new();
+    for kind in [MediaKind::Audio, MediaKind::Video] {
+        if data.kinds.contains(&kind) {
+            producers.extend(peers.with(|p| p.producers(kind)));
+        }
+    }
+    producers.retain(|(peer_id, producer)| {
+        !producer.closed()
+            && router_ids.contains(&producer.transport().router().id())
+            && data
+                .peers
+                .as_ref()
+                .map(|ids| ids.contains(peer_id))
+                .unwrap_or(true)
+    });
+    if producers.is_empty() {
+        return Err(HandlerError::NotFound(format!(
+            "nothing to record in room {}",
+            data.room
+        )));
+    }
+
+    let dir = recording_dir.join(file_safe(&data.room));
+    fs::create_dir_all(&dir)
+        .await
+        .map_err(|e| HandlerError::Internal(format!("cannot create {}: {}", dir.display(), e)))?;
+    let mut recording = Recording {
+        id: Uuid::new_v4(),
+        tracks: Vec::new(),
+    };
+    for (peer_id, producer) in producers {
+        let path = dir.join(format!(
+            "{}-{}-{}-{}.rtpdump",
+            recording.id,
+            peer_id,
+            kind_name(producer.kind()),
+            producer.id()
+        ));
+        // tracks already started end with `recording` on error
+        let track = record_producer(peer_id, &producer, path, &config.media_codecs).await?;
+        recording.tracks.push(track);
+    }
+
+    let started = recording_data(&data.room, &recording);
+    info!(
+        "recording {} files of room {}",
+        recording.tracks.len(),
+        data.room
+    );
+    recordings.create(data.room, recording);
+    let msg = ResponseMessage::OutgoingCommunication {
+        ws: Some(wsid),
+        requestId: None,
+        communication: MessageResponse::recordingStarted { data: started },
+    };
+    let _ = sender.send(msg).await;
+    Ok(())
+}
+
+pub async fn stop_recording(
+    wsid: String,
+    data: StopRecordingData,
+    recordings: Arc<Mutex<Recordings>>,
+    sender: Responder,
+) -> Result<(), HandlerError> {
+    let recording = recordings
+        .lock()
+        .await
+        .remove(&data.room)
+        .ok_or(HandlerError::NotFound(format!(
+            "room {} is not being recorded",
+            data.room
+        )))?;
+    let stopped = finish_recording(&data.room, recording).await;
+    let msg = ResponseMessage::OutgoingCommunication {
+        ws: Some(wsid),
+        requestId: None,
+        communication: MessageResponse::recordingStopped { data: stopped },
+    };
+    let _ = sender.send(msg).await;
+    Ok(())
+}
+
+// Stop every writer and wait for its file to be flushed, the plain transports
+// close with the tracks.
+pub async fn finish_recording(room: &str, recording: Recording) -> RecordingData {
+    let mut data = recording_data(room, &recording);
+    let mut writers = Vec::new();
+    for track in recording.tracks {
+        let _ = track.stop.send(());
+        writers.push((track.path, track.writer));
+    }
+    for (file, (path, writer)) in data.files.iter_mut().zip(writers) {
+        let packets = match writer.await {
+            Ok(Ok(packets)) => packets,
+            Ok(Err(e)) => {
+                error!("error writing {}: {}", path.display(), e);
+                0
+            }
+            Err(e) => {
+                error!("recording writer of {} failed: {}", path.display(), e);
+                0
+            }
+        };
+        file.packets = Some(packets);
+    }
+    data
+}
+
+async fn record_producer(
+    peer_id: Uuid,
+    producer: &Producer,
+    path: PathBuf,
+    media_codecs: &[RtpCodecCapability],
+) -> Result<RecordingTrack, HandlerError> {
+    let router = producer.transport().router().clone();
+    let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))
+        .await
+        .map_err(|e| HandlerError::Internal(format!("cannot bind recording socket: {}", e)))?;
+    let local = socket
+        .local_addr()
+        .map_err(|e| HandlerError::Internal(format!("recording socket has no address: {}", e)))?;
+
+    let transport = router
+        .create_plain_transport(PlainTransportOptions::new(ListenIp {
+            ip: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            announced_ip: None,
+        }))
+        .await
+        .map_err(|error| {
+            HandlerError::Mediasoup(format!("Failed to create recording transport: {}", error))
+        })?;
+    transport
+        .connect(PlainTransportRemoteParameters {
+            ip: Some(local.ip()),
+            port: Some(local.port()),
+            rtcp_port: None,
+            srtp_parameters: None,
+        })
+        .await
+        .map_err(|error| {
+            HandlerError::Mediasoup(format!("Failed to connect recording transport: {}", error))
+        })?;
+    let remote = SocketAddr::new(local.ip(), transport.tuple().local_port());
+    socket
+        .connect(remote)
+        .await
+        .map_err(|e| HandlerError::Internal(format!("cannot connect recording socket: {}", e)))?;
+
+    let mut options = ConsumerOptions::new(producer.id(), router_capabilities(media_codecs));
+    options.paused = true;
+    let consumer = transport.consume(options).await.map_err(|error| {
+        HandlerError::Mediasoup(format!("Failed to consume producer to record: {}", error))
+    })?;
+
+    let file = File::create(&path)
+        .await
+        .map_err(|e| HandlerError::Internal(format!("cannot create {}: {}", path.display(), e)))?;
+    let (stop, stopped) = oneshot::channel();
+    let writer = tokio::spawn(write_rtpdump(socket, file, remote, stopped));
+    // resuming a video consumer asks the producer for a key frame
+    consumer.resume().await.map_err(|error| {
+        HandlerError::Mediasoup(format!("Failed to resume recording consumer: {}", error))
+    })?;
+    Ok(RecordingTrack {
+        peer_id,
+        kind: producer.kind(),
+        path,
+        transport,
+        consumer,
+        stop,
+        writer,
+    })
+}
+
+// Write what the plain transport sends until `stop` fires or is dropped,
+// returns the number of packets written.
+async fn write_rtpdump(
+    socket: UdpSocket,
+    file: File,
+    source: SocketAddr,
+    mut stop: oneshot::Receiver<()>,
+) -> io::Result<u64> {
+    let mut out = BufWriter::new(file);
+    let start = Instant::now();
+    out.write_all(&rtpdump_header(SystemTime::now(), source))
+        .await?;
+    let mut buffer = vec![0u8; MAX_PACKET];
+    let mut packets = 0;
+    loop {
+        tokio::select! {
+            _ = &mut stop => break,
+            received = socket.recv(&mut buffer) => {
+                let len = received?;
+                out.write_all(&rtpdump_packet(start.elapsed(), &buffer[..len]))
+                    .await?;
+                packets += 1;
+            }
+        }
+    }
+    out.flush().await?;
+    Ok(packets)
+}
+
+// rtpdump file header as written by rtptools: the text line, then the start
+// time and the address the packets came from, all big endian.
+pub fn rtpdump_header(start: SystemTime, source: SocketAddr) -> Vec<u8> {
+    let mut header = format!("#!rtpplay1.0 {}/{}\n", source.ip(), source.port()).into_bytes();
+    let since_epoch = start.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let address = match source.ip() {
+        IpAddr::V4(ip) => u32::from(ip),
+        IpAddr::V6(_) => 0,
+    };
+    header.extend_from_slice(&(since_epoch.as_secs() as u32).to_be_bytes());
+    header.extend_from_slice(&since_epoch.subsec_micros().to_be_bytes());
+    header.extend_from_slice(&address.to_be_bytes());
+    header.extend_from_slice(&source.port().to_be_bytes());
+    header.extend_from_slice(&[0, 0]);
+    header
+}
+
+// One rtpdump record: record length, RTP length (0 for RTCP), milliseconds
+// since the start, then the packet.
+pub fn rtpdump_packet(offset: Duration, packet: &[u8]) -> Vec<u8> {
+    // rtcp-mux puts RTCP on the same port, told apart by its packet type
+    let rtcp = packet.len() >= 2 && (192..=223).contains(&packet[1]);
+    let len = packet.len().min(u16::MAX as usize - 8);
+    let mut record = Vec::with_capacity(8 + len);
+    record.extend_from_slice(&((8 + len) as u16).to_be_bytes());
+    record.extend_from_slice(&(if rtcp { 0 } else { len as u16 }).to_be_bytes());
+    record.extend_from_slice(&(offset.as_millis() as u32).to_be_bytes());
+    record.extend_from_slice(&packet[..len]);
+    record
+}
+
+fn recording_data(room: &str, recording: &Recording) -> RecordingData {
+    RecordingData {
+        room: room.to_string(),
+        recordingId: recording.id,
+        files: recording
+            .tracks
+            .iter()
+            .map(|track| RecordingFile {
+                peerId: track.peer_id,
+                producerId: track.consumer.producer_id(),
+                kind: track.kind,
+                path: track.path.display().to_string(),
+                packets: None,
+            })
+            .collect(),
+    }
+}
+
+fn kind_name(kind: MediaKind) -> &'static str {
+    match kind {
+        MediaKind::Audio => "audio",
+        MediaKind::Video => "video",
+    }
+}
+
+// room names come from clients, keep them from escaping recording_dir
+pub fn file_safe(room: &str) -> String {
+    let name: String = room
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if name.is_empty() {
+        "_".to_string()
+    } else {
+        name
+    }
+}
@@ -6,6 +6,7 @@ use tokio::sync::Mutex;
 use uuid::Uuid;
 
 use crate::{
+    handlers::error::HandlerError,
     models::sfu::{PipeTransports, Relays},
     utils::utils::Mut,
 };
@@ -18,7 +19,7 @@ pub async fn connect_pipe_relay(
     srtp: SrtpParameters,
     pipetransports: Arc<Mut<PipeTransports>>,
     relays: Arc<Mutex<Relays>>,
-) -> Result<(), String> {
+) -> Result<(), HandlerError> {
     let get_pipe_relay = relays
         .lock()
         .await
@@ -28,9 +29,9 @@ pub async fn connect_pipe_relay(
             "Pipe relay can not connect. None exists for route:",
             // ingress_route
         );
-        return Err(String::from(
+        return Err(HandlerError::NotFound(String::from(
             "Pipe relay can not connect. None exists for route",
-        ));
+        )));
     } else {
         let get_pipe_relay = pipetransports.with(|p| p.get(get_pipe_relay[0].transport));
         if let Some(pipe_relay) = get_pipe_relay.clone() {
@@ -38,9 +39,9 @@ pub async fn connect_pipe_relay(
             println!("@@ is pipe connected {:?}", pipe_relay.is_connected);
             println!("pipe_relay transport {:?}", pipe_relay.transport_id);
             if pipe_relay.is_connected {
-                return Err(String::from(
+                return Err(HandlerError::AlreadyExists(String::from(
                     "Pipe relay has already connected return, this is not a error",
-                ));
+                )));
             }
             let remote_parameters = PipeTransportRemoteParameters {
                 ip,
@@ -52,7 +53,9 @@ pub async fn connect_pipe_relay(
                 .pipe_transport
                 .connect(remote_parameters)
                 .await
-                .map_err(|error| format!("Failed to connect ingress relay: {:?}", error))?;
+                .map_err(|error| {
+                    HandlerError::Mediasoup(format!("Failed to connect ingress relay: {:?}", error))
+                })?;
             pipe_relay
                 .pipe_transport
                 .on_sctp_state_change(|state| {
@@ -64,7 +67,9 @@ pub async fn connect_pipe_relay(
             println!("after pipe update");
             return Ok(());
         } else {
-            Err(String::from("error connect egress relay"))
+            Err(HandlerError::NotFound(String::from(
+                "error connect egress relay",
+            )))
         }
     }
 }
@@ -1,27 +1,32 @@
 #![allow(non_camel_case_types, non_snake_case)]
 use mediasoup::{
-    prelude::{AppData, Consumer, ConsumerId, ConsumerOptions},
+    prelude::{AppData, ConsumerOptions},
     producer::ProducerId,
+    router::RouterId,
     rtp_parameters::{MediaKind, RtpCapabilities, RtpParameters},
     transport::Transport,
 };
-use std::{collections::HashMap, ops::Deref, sync::Arc};
-use tokio::sync::{mpsc::Sender, Mutex, RwLock};
+use std::{ops::Deref, sync::Arc};
+use tokio::sync::{Mutex, RwLock};
 use uuid::Uuid;
 
 use crate::{
     config::config::Config,
-    models::sfu::{PipeTransports, Relays, Transport2Router, Transports},
+    handlers::error::HandlerError,
+    models::{
+        peer::{forget_consumer, Peers},
+        sfu::{PipeTransports, Relays, Transport2Router},
+    },
     utils::{
         codec::{
             appData, CreateRelayProducerMessage, MessageResponse, ProduceMediaData, ResponseMessage,
         },
-        utils::{get_nodeid, Mut},
+        utils::{get_nodeid, Mut, Responder},
     },
 };
 
 pub async fn create_consumer_relay(
-    transports: Arc<Mut<Transports>>,
+    peers: Arc<Mut<Peers>>,
     transport2router: Arc<RwLock<Transport2Router>>,
     relays: Arc<Mutex<Relays>>,
     pipetransports: Arc<Mut<PipeTransports>>,
@@ -35,26 +40,67 @@ pub async fn create_consumer_relay(
     app_data: appData,
     _rtpParameters: RtpParameters,
     rtp_capabilities: RtpCapabilities,
-    consumers: Arc<Mutex<HashMap<ConsumerId, Consumer>>>,
-    sender: Sender<ResponseMessage>,
-) -> Result<(), String> {
-    let peer_router = transports.with(|inner| inner.get(peer_id));
+    sender: Responder,
+) -> Result<(), HandlerError> {
+    let peer_router = peers.with(|p| p.transport(peer_id));
     if peer_router.is_none() {
         println!("{}", String::from("cannot find peer router"));
-        return Err(String::from("cannot find peer router"));
+        return Err(HandlerError::NotFound(String::from(
+            "cannot find peer router",
+        )));
     }
     let ingress_router = transport2router.read().await.get(peer_router.unwrap().id());
     if ingress_router.is_none() {
         println!("{}", String::from("cannot find transport2router"));
-        return Err(String::from("cannot find peer transport2router"));
+        return Err(HandlerError::NotFound(String::from(
+            "cannot find peer transport2router",
+        )));
     }
+    relay_to_egress(
+        ingress_router.unwrap(),
+        relays,
+        pipetransports,
+        routerNetwork,
+        config,
+        wsid,
+        peer_id,
+        egress,
+        producer_id,
+        app_data,
+        rtp_capabilities,
+        peers,
+        sender,
+    )
+    .await
+}
+
+// Consume `producer_id` of the ingress router on its relay to `egress` and
+// announce it, to the egress node as a relay producer and to the client. The
+// relay consumer is kept with the peer.
+pub async fn relay_to_egress(
+    ingress_router: RouterId,
+    relays: Arc<Mutex<Relays>>,
+    pipetransports: Arc<Mut<PipeTransports>>,
+    routerNetwork: String,
+    config: Config,
+    wsid: String,
+    peer_id: Uuid,
+    egress: Uuid,
+    producer_id: ProducerId,
+    app_data: appData,
+    rtp_capabilities: RtpCapabilities,
+    peers: Arc<Mut<Peers>>,
+    sender: Responder,
+) -> Result<(), HandlerError> {
     let get_transport = relays
         .lock()
         .await
-        .get_ingress_egress(ingress_router.unwrap(), egress);
+        .get_ingress_egress(ingress_router, egress);
     if get_transport.is_empty() == true {
         println!("cannot find relay in relay consumer transport");
-        return Err("cannot find relay in relay consumer transport".to_string());
+        return Err(HandlerError::NotFound(
+            "cannot find relay in relay consumer transport".to_string(),
+        ));
     } else {
         let relay_consumer = pipetransports.with(|p| p.get(get_transport[0].transport));
         if let Some(consumer) = relay_consumer {
@@ -66,21 +112,22 @@ pub async fn create_consumer_relay(
                 .consume(consumer_options)
                 .await
                 .map_err(|error| {
-                    format!(
+                    HandlerError::Mediasoup(format!(
                         "Failed to create relay consumer Pipe transport: {:?}",
                         error
-                    )
+                    ))
                 })?;
             // reply message to server
             let node = get_nodeid(config.ingress, config.egress);
             let relay_consumer_reply = ResponseMessage::OutgoingServer {
                 // wsid: Some(wsid.clone()),
                 node,
+                requestId: None,
                 message: MessageResponse::createRelayProducer {
                     data: CreateRelayProducerMessage {
                         groupId: routerNetwork,
                         peerId: peer_id,
-                        ingressRoute: ingress_router.unwrap(),
+                        ingressRoute: ingress_router,
                         egress: egress,
                         producerId: Some(new_consumer.producer_id()),
                         mediaType: Some(new_consumer.kind()),
@@ -102,6 +149,7 @@ pub async fn create_consumer_relay(
             let _ = sender.send(relay_consumer_reply).await;
             let reply_message = ResponseMessage::OutgoingCommunication {
                 ws: Some(wsid.clone()),
+                requestId: None,
                 communication: MessageResponse::producedMedia {
                     data: ProduceMediaData {
                         id: new_consumer.producer_id(),
@@ -118,12 +166,14 @@ pub async fn create_consumer_relay(
                 },
             };
             let _ = sender.send(reply_message).await;
-            let mut consumers = consumers.lock().await;
-            consumers.insert(new_consumer.id(), new_consumer);
+            forget_consumer(&peers, &new_consumer);
+            peers.with(|p| p.entry(peer_id).relay_consumers.push(new_consumer));
             return Ok(());
         } else {
-            Err("error creating consumer relay".to_string())
+            Err(HandlerError::NotFound(
+                "error creating consumer relay".to_string(),
+            ))
         }
     }
-    //Err("error creating consumer relay".to_string())
+    //Err(HandlerError::NotFound("error creating consumer relay".to_string()))
 }
@@ -2,38 +2,41 @@ use std::sync::Arc;
 
 use crate::{
     config::config::Config,
-    models::sfu::{
-        Loads, PendingRelays, PipeTransports, RelayRouters, Relays, RoomRouters, Routers,
-        Routers2Worker,
+    handlers::error::HandlerError,
+    models::{
+        room::Rooms,
+        sfu::{
+            Loads, PendingRelays, PipeTransports, RelayRouters, Relays, Routers, Routers2Worker,
+        },
     },
     utils::{
         codec::{ConnectPipeRelayData, MessageResponse, ResponseMessage},
-        utils::{get_nodeid, Mut},
+        utils::{get_nodeid, Mut, Responder},
         worker_load::get_less_loaded_router,
     },
 };
 use log::error;
 use mediasoup::{
-    prelude::{ListenIp, PipeTransportOptions, PipeTransportRemoteParameters},
+    prelude::{PipeTransportOptions, PipeTransportRemoteParameters},
     router::RouterId,
     transport::Transport,
 };
-use tokio::sync::{mpsc::Sender, Mutex};
+use tokio::sync::Mutex;
 
 pub async fn create_egress_relay(
     group_id: String,
     ingress: RouterId,
     relays: Arc<Mutex<Relays>>,
     routers: Arc<Mutex<Routers>>,
-    room_routers: Arc<Mutex<RoomRouters>>,
+    room_routers: Arc<Mutex<Rooms>>,
     router2worker: Arc<Mutex<Routers2Worker>>,
     relay_routers: Arc<Mutex<RelayRouters>>,
     pipetransports: Arc<Mut<PipeTransports>>,
     pending_relays: Arc<Mutex<PendingRelays>>,
     loads: Arc<Mutex<Loads>>,
     config: Config,
-    sender: Sender<ResponseMessage>,
-) -> Result<(), String> {
+    sender: Responder,
+) -> Result<(), HandlerError> {
     let get_relays = relays.lock().await.get_router(ingress);
     if get_relays.is_empty() {
         let get_lease_load = get_less_loaded_router(
@@ -47,20 +50,7 @@ pub async fn create_egress_relay(
         if let Some(lease_load) = get_lease_load {
             let get_pipe_transport = routers.lock().await.get(lease_load);
             if let Some(router) = get_pipe_transport {
-                let listen_ip;
-                if config.traversenat == "true" {
-                    listen_ip = ListenIp {
-                        //ip: "127.0.0.1".parse().unwrap(),
-                        ip: "0.0.0.0".parse().unwrap(),
-                        announced_ip: Some(config.announceip),
-                    };
-                } else {
-                    listen_ip = ListenIp {
-                        //ip: "127.0.0.1".parse().unwrap(),
-                        ip: local_ipaddress::get().unwrap().parse().unwrap(),
-                        announced_ip: None,
-                    };
-                }
+                let listen_ip = config.listen_ip();
                 // let router_id = router.id();
                 // let group_router_id = group_router.clone();
                 let mut pipe_options = PipeTransportOptions::new(listen_ip);
@@ -72,7 +62,10 @@ pub async fn create_egress_relay(
                     .create_pipe_transport(pipe_options)
                     .await
                     .map_err(|error| {
-                    format!("Failed to create egress Pipe transport: {:?}", error)
+                    HandlerError::Mediasoup(format!(
+                        "Failed to create egress Pipe transport: {:?}",
+                        error
+                    ))
                 })?;
                 let rt = router.clone();
                 new_pipe_transport
@@ -106,7 +99,9 @@ pub async fn create_egress_relay(
                 // router can be routerId
                 let egress = get_nodeid(config.ingress, config.egress);
                 if egress.is_none() {
-                    return Err("no egress found in the nodeId".to_string());
+                    return Err(HandlerError::NotFound(
+                        "no egress found in the nodeId".to_string(),
+                    ));
                 }
                 let mut create_relay = relays.lock().await;
                 create_relay.create(
@@ -115,6 +110,10 @@ pub async fn create_egress_relay(
                     new_pipe_transport.id().clone(),
                     group_id.clone(),
                 );
+                room_routers
+                    .lock()
+                    .await
+                    .add_relay(&group_id, new_pipe_transport.id());
                 pipetransports.with(|p| {
                     p.create(
                         group_id,
@@ -139,13 +138,17 @@ pub async fn create_egress_relay(
                         .connect(remote_parameters)
                         .await
                         .map_err(|error| {
-                            format!("Failed to connect Pipe transport: {:?}", error)
+                            HandlerError::Mediasoup(format!(
+                                "Failed to connect Pipe transport: {:?}",
+                                error
+                            ))
                         })?;
                 }
 
                 if let Some(node_id) = get_nodeid(config.ingress, config.egress) {
                     let relay_reply = ResponseMessage::OutgoingServer {
                         node: Some(node_id),
+                        requestId: None,
                         message: MessageResponse::connectPipeRelay {
                             data: ConnectPipeRelayData {
                                 ingressRoute: ingress.clone(),
@@ -162,13 +165,17 @@ pub async fn create_egress_relay(
                     };
                     return Ok(());
                 } else {
-                    return Err("no node id found".to_string());
+                    return Err(HandlerError::NotFound("no node id found".to_string()));
                 }
             } else {
-                return Err(String::from("Cannot find Router from the GroupID"));
+                return Err(HandlerError::NotFound(String::from(
+                    "Cannot find Router from the GroupID",
+                )));
             }
         } else {
-            return Err(String::from("Cannot find group router!"));
+            return Err(HandlerError::NotFound(String::from(
+                "Cannot find group router!",
+            )));
         }
     } else {
         println!("Egress pipe is not valid or already exists");
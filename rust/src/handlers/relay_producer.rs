@@ -1,8 +1,8 @@
-use std::{collections::HashMap, ops::Deref, sync::Arc};
+use std::{ops::Deref, sync::Arc};
 
 use mediasoup::{
     prelude::AppData,
-    producer::{Producer, ProducerId, ProducerOptions},
+    producer::{ProducerId, ProducerOptions},
     router::{PipeToRouterOptions, RouterId},
     rtp_parameters::{MediaKind, RtpParameters},
     transport::Transport,
@@ -12,12 +12,15 @@ use uuid::Uuid;
 
 use crate::{
     config::config::Config,
-    models::sfu::{
-        AudioProducers, PipeTransports, RelayRouters, Relays, RoomRouters, Routers, VideoProducers,
+    handlers::{audio_observer::observe_audio_producer, error::HandlerError},
+    models::{
+        peer::{forget_producer, Peers},
+        room::Rooms,
+        sfu::{AudioObservers, PipeTransports, RelayRouters, Relays, Routers},
     },
     utils::{
         codec::{appData, CreatedRelayProducerData, MessageResponse, ResponseMessage},
-        utils::{get_nodeid, Mut},
+        utils::{get_nodeid, Mut, Responder},
     },
 };
 
@@ -31,20 +34,22 @@ pub async fn create_relay_producer(
     app_data: appData,
     relays: Arc<Mutex<Relays>>,
     pipetransports: Arc<Mut<PipeTransports>>,
-    room_routers: Arc<Mutex<RoomRouters>>,
+    room_routers: Arc<Mutex<Rooms>>,
     relay_routers: Arc<Mutex<RelayRouters>>,
     routers: Arc<Mutex<Routers>>,
-    audio_producers: Arc<Mutex<AudioProducers>>,
-    video_producers: Arc<Mutex<VideoProducers>>,
+    peers: Arc<Mut<Peers>>,
+    audio_observers: Arc<Mutex<AudioObservers>>,
+    signaling: Arc<Mut<Option<Sender<ResponseMessage>>>>,
     config: Config,
-    producers: Arc<Mutex<HashMap<ProducerId, Producer>>>,
-    sender: Sender<ResponseMessage>,
-) -> Result<(), String> {
+    sender: Responder,
+) -> Result<(), HandlerError> {
     //let ingress = ingress_route.clone().into();
     let get_relay_producer = relays.lock().await.get_router(ingress_route.clone());
     if get_relay_producer.is_empty() {
         println!("relay producer error:");
-        return Err(String::from("cannot find the ingress router!"));
+        return Err(HandlerError::NotFound(String::from(
+            "cannot find the ingress router!",
+        )));
     } else {
         let ingress_id = ingress_route.clone();
         let get_relay_transport = pipetransports.with(|p| p.get(get_relay_producer[0].transport));
@@ -57,8 +62,13 @@ pub async fn create_relay_producer(
                 .pipe_transport
                 .produce(producer_options)
                 .await
-                .map_err(|error| format!("Failed to relay produce Pipe transport: {:?}", error))?;
-            let router_network = room_routers.lock().await.get(group_id);
+                .map_err(|error| {
+                    HandlerError::Mediasoup(format!(
+                        "Failed to relay produce Pipe transport: {:?}",
+                        error
+                    ))
+                })?;
+            let router_network = room_routers.lock().await.routers(&group_id);
             let relay_router = relay_routers.lock().await.get(ingress_id);
             if router_network.is_some() && relay_router.is_some() {
                 for router_info in router_network.unwrap().into_iter() {
@@ -71,26 +81,20 @@ pub async fn create_relay_producer(
                         let get_routers = routers.lock().await.get(relay_router.clone().unwrap());
                         if get_routers.is_some() {
                             if get_routers.clone().unwrap().closed() {
-                                println!("FROM CREATE RELAY PRODUCER");
-                                println!(
-                                    "Killing process, for the greater good. Source Router is closed."
-                                );
-                                std::process::exit(1);
-                                //kill process
+                                println!("source router is closed, skipping it");
+                                continue;
                             }
                         }
                         let current_router = routers.lock().await.get(router_info.id());
                         if current_router.is_none() {
                             println!("couldn't find curent router: {:?}", &router_info.id());
-                            return Err("couldn't find curent router".to_string());
+                            return Err(HandlerError::NotFound(
+                                "couldn't find curent router".to_string(),
+                            ));
                         }
                         if current_router.clone().unwrap().closed() {
-                            println!("FROM CREATE RELAY PRODUCER");
-                            println!(
-                                "Killing process, for the greater good. Dest Router is closed."
-                            );
-                            std::process::exit(1);
-                            //kill process
+                            println!("destination router is closed, skipping it");
+                            continue;
                         }
                         let pipe_router = routers.lock().await.get(relay_router.unwrap());
                         if pipe_router.is_none() {
@@ -98,7 +102,9 @@ pub async fn create_relay_producer(
                                 "couldn't find curent pipe router {:?}",
                                 &relay_router.unwrap()
                             );
-                            return Err("couldn't find curent pipe router".to_string());
+                            return Err(HandlerError::NotFound(
+                                "couldn't find curent pipe router".to_string(),
+                            ));
                         }
                         let pipe_option = PipeToRouterOptions::new(current_router.unwrap().clone());
                         pipe_router
@@ -106,17 +112,36 @@ pub async fn create_relay_producer(
                             .pipe_producer_to_router(relay_producer.id(), pipe_option)
                             .await
                             .map_err(|error| {
-                                format!("Failed to Pipe to router transport: {}", error)
+                                HandlerError::Mediasoup(format!(
+                                    "Failed to Pipe to router transport: {}",
+                                    error
+                                ))
                             })?;
                     }
                 }
             }
+            peers.with(|p| p.entry(peer_id).producers.push(relay_producer.clone()));
+            forget_producer(&peers, &relay_producer);
             // prepare to send information back to client
             if relay_producer.kind() == MediaKind::Audio {
-                let mut audio_producers = audio_producers.lock().await;
-                audio_producers.create(peer_id, relay_producer.clone());
+                // already piped to every router of the room above
+                if let Err(e) = observe_audio_producer(
+                    audio_observers,
+                    signaling,
+                    config.audio_level_interval,
+                    group_id,
+                    peer_id,
+                    relay_producer.clone(),
+                    relay_transport.pipe_transport.router().clone(),
+                    true,
+                )
+                .await
+                {
+                    println!("cannot observe relayed audio producer: {}", e);
+                }
                 let reply_message = ResponseMessage::OutgoingServer {
                     node: get_nodeid(config.ingress, config.egress),
+                    requestId: None,
                     message: MessageResponse::createdRelayProducer {
                         data: CreatedRelayProducerData {
                             peerId: peer_id,
@@ -136,13 +161,10 @@ pub async fn create_relay_producer(
                     },
                 };
                 let _ = sender.send(reply_message).await;
-                let mut save_producer = producers.lock().await;
-                save_producer.insert(relay_producer.id(), relay_producer);
             } else if relay_producer.kind() == MediaKind::Video {
-                let mut video_producers = video_producers.lock().await;
-                video_producers.create(peer_id, relay_producer.clone());
                 let reply_message = ResponseMessage::OutgoingServer {
                     node: get_nodeid(config.ingress, config.egress),
+                    requestId: None,
                     message: MessageResponse::createdRelayProducer {
                         data: CreatedRelayProducerData {
                             peerId: peer_id,
@@ -163,16 +185,16 @@ pub async fn create_relay_producer(
                 };
 
                 let _ = sender.send(reply_message).await;
-                let mut save_producer = producers.lock().await;
-                save_producer.insert(relay_producer.id(), relay_producer);
             } else {
-                return Err(String::from(
+                return Err(HandlerError::InvalidRequest(String::from(
                     "error relay producer for audio/video there is not a matching media type",
-                ));
+                )));
             }
             return Ok(());
         } else {
-            return Err(String::from("error creating relay producer!"));
+            return Err(HandlerError::NotFound(String::from(
+                "error creating relay producer!",
+            )));
         }
     }
 }
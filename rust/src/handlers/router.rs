@@ -1,12 +1,25 @@
 use std::{collections::hash_map::Entry, sync::Arc};
 
 use crate::{
-    models::sfu::{RoomRouters, Routers, Routers2Worker, Workers},
-    utils::codec::{JoinRoomData, MessageResponse, ResponseMessage},
+    config::config::{Config, RouterPlacement},
+    handlers::error::{HandlerError, Limit},
+    models::{
+        peer::Peers,
+        room::Rooms,
+        sfu::{Loads, Routers, Routers2Worker, Workers},
+    },
+    utils::{
+        codec::{JoinRoomData, MessageResponse, ResponseMessage, RoomCreatedData},
+        utils::{Mut, Responder},
+    },
 };
 use colored::Colorize;
-use log::error;
-use mediasoup::router::RouterOptions;
+use log::{error, info};
+use mediasoup::{
+    router::{PipeToRouterOptions, Router, RouterOptions},
+    rtp_parameters::RtpCodecCapability,
+    worker::{Worker, WorkerId},
+};
 use tokio::sync::{mpsc::Sender, Mutex, RwLock};
 use uuid::Uuid;
 
@@ -15,19 +28,24 @@ pub async fn create_router_group(
     wsid: String,
     ingress: Option<Uuid>,
     egress: Option<Uuid>,
-    room_routers: Arc<Mutex<RoomRouters>>,
+    room_routers: Arc<Mutex<Rooms>>,
     workers: Arc<RwLock<Workers>>,
     routers: Arc<Mutex<Routers>>,
     routers2workers: Arc<Mutex<Routers2Worker>>,
-    sender: Sender<ResponseMessage>,
-) -> Result<(), String> {
+    loads: Arc<Mutex<Loads>>,
+    placement: RouterPlacement,
+    max_rooms: Option<u32>,
+    media_codecs: Vec<RtpCodecCapability>,
+    signaling: Arc<Mut<Option<Sender<ResponseMessage>>>>,
+    sender: Responder,
+) -> Result<(), HandlerError> {
     let mut rm_routers = room_routers.lock().await;
     match rm_routers.0.entry(room.clone()) {
         Entry::Occupied(entry) => {
-            let r = entry.get();
-            let room_router = r[0].clone();
+            let room_router = entry.get().routers[0].clone();
             let resp = ResponseMessage::OutgoingCommunication {
                 ws: Some(wsid.clone()),
+                requestId: None,
                 communication: MessageResponse::joinedRoom {
                     data: JoinRoomData {
                         roomRTPCapabilities: room_router.rtp_capabilities().clone(),
@@ -41,28 +59,56 @@ pub async fn create_router_group(
             //  }
         }
         Entry::Vacant(_) => {
-            let wks = workers.read().await;
-            for (_n, worker) in wks.0.clone().iter().enumerate() {
-                let router = worker
-                    .create_router(RouterOptions::new(crate::handlers::codecs::media_codecs()))
-                    .await
-                    .map_err(|error| format!("Failed to create router: {}", error))?;
-                // save the router router
-                let mut rt = routers.lock().await;
-                rt.create(router.id(), router.clone());
-                // save the room
-                rm_routers.create(room.clone(), router.clone());
-                // save routers2workers
-                let mut r_t_r = routers2workers.lock().await;
-                r_t_r.create(router.id(), worker.id());
+            if let Some(max) = max_rooms {
+                if rm_routers.0.len() >= max as usize {
+                    return Err(HandlerError::LimitReached(
+                        Limit::nodeRooms,
+                        format!("node already hosts {} rooms, not creating {}", max, room),
+                    ));
+                }
+            }
+            let wks: Vec<Worker> = workers.read().await.0.clone();
+            let wks = match placement {
+                RouterPlacement::AllWorkers => wks,
+                // start on one worker, grow_room adds the others when needed
+                RouterPlacement::Lazy => {
+                    let ids: Vec<WorkerId> = wks.iter().map(|w| w.id()).collect();
+                    let selected = loads.lock().await.select_worker(&ids);
+                    wks.into_iter()
+                        .filter(|w| Some(w.id()) == selected)
+                        .collect()
+                }
+            };
+            for worker in wks.iter() {
+                let router = create_router(worker, media_codecs.clone()).await?;
+                register_router(&mut rm_routers, &room, &router, &routers, &routers2workers).await;
             }
-            let router = rm_routers.get(room.clone());
+            let router = rm_routers.routers(&room);
             if router.is_none() {
                 println!("{}", "No router found".red());
-                return Err("No router found".to_string());
+                return Err(HandlerError::NotFound("No router found".to_string()));
+            }
+            let created = ResponseMessage::OutgoingServer {
+                node: ingress.or(egress),
+                requestId: None,
+                message: MessageResponse::roomCreated {
+                    data: RoomCreatedData {
+                        room: room.clone(),
+                        routers: rm_routers
+                            .get(&room)
+                            .map(|r| r.router_ids())
+                            .unwrap_or_default(),
+                    },
+                },
+            };
+            if let Some(signaling) = signaling.with(|s| s.clone()) {
+                if let Err(e) = signaling.send(created).await {
+                    error!("error sending roomCreated: {:?}", e);
+                }
             }
             let resp = ResponseMessage::OutgoingCommunication {
                 ws: Some(wsid),
+                requestId: None,
                 communication: MessageResponse::joinedRoom {
                     data: JoinRoomData {
                         roomRTPCapabilities: router.unwrap()[0].rtp_capabilities().clone(),
@@ -78,3 +124,142 @@ pub async fn create_router_group(
         }
     }
 }
+
+async fn create_router(
+    worker: &Worker,
+    media_codecs: Vec<RtpCodecCapability>,
+) -> Result<Router, HandlerError> {
+    worker
+        .create_router(RouterOptions::new(media_codecs))
+        .await
+        .map_err(|error| HandlerError::Mediasoup(format!("Failed to create router: {}", error)))
+}
+
+// save the router with its room and worker
+async fn register_router(
+    rooms: &mut Rooms,
+    room: &str,
+    router: &Router,
+    routers: &Arc<Mutex<Routers>>,
+    routers2workers: &Arc<Mutex<Routers2Worker>>,
+) {
+    routers.lock().await.create(router.id(), router.clone());
+    rooms.create(room.to_string(), router.clone());
+    routers2workers
+        .lock()
+        .await
+        .create(router.id(), router.worker().id());
+}
+
+// With lazy placement, give `room` a router on another worker once each of its
+// routers carries `threshold` consumers. The producers of the room are piped
+// to the new router before it is handed out, so its consumers see all of them.
+// Whether `room` takes the peer under max_peers_per_room. Checked once before
+// the transport is created, to fail fast, and again under the same lock as
+// join, so concurrent requests cannot all take the last place.
+pub fn admit_peer(
+    rooms: &Rooms,
+    room: &str,
+    peer_id: Uuid,
+    max_peers: Option<u32>,
+) -> Result<(), HandlerError> {
+    match max_peers {
+        Some(max) if !rooms.admits(room, peer_id, max) => Err(HandlerError::LimitReached(
+            Limit::roomPeers,
+            format!("room {} already has {} peers", room, max),
+        )),
+        _ => Ok(()),
+    }
+}
+
+pub async fn grow_room(
+    room: &str,
+    rooms: Arc<Mutex<Rooms>>,
+    workers: Arc<RwLock<Workers>>,
+    routers: Arc<Mutex<Routers>>,
+    routers2workers: Arc<Mutex<Routers2Worker>>,
+    loads: Arc<Mutex<Loads>>,
+    peers: Arc<Mut<Peers>>,
+    config: &Config,
+) -> Result<(), HandlerError> {
+    if config.router_placement != RouterPlacement::Lazy {
+        return Ok(());
+    }
+    let mut rooms_guard = rooms.lock().await;
+    let router_ids = match rooms_guard.get(room) {
+        Some(r) => r.router_ids(),
+        None => return Ok(()),
+    };
+    let threshold = config.router_consumer_threshold as usize;
+    let full = peers.with(|p| {
+        router_ids
+            .iter()
+            .all(|router_id| p.consumers_on(*router_id) >= threshold)
+    });
+    if !full {
+        return Ok(());
+    }
+    let used: Vec<WorkerId> = {
+        let routers2workers = routers2workers.lock().await;
+        router_ids
+            .iter()
+            .filter_map(|r| routers2workers.get(*r))
+            .collect()
+    };
+    let free: Vec<Worker> = workers
+        .read()
+        .await
+        .0
+        .iter()
+        .filter(|w| !used.contains(&w.id()))
+        .cloned()
+        .collect();
+    let ids: Vec<WorkerId> = free.iter().map(|w| w.id()).collect();
+    let selected = loads.lock().await.select_worker(&ids);
+    let worker = match free.into_iter().find(|w| Some(w.id()) == selected) {
+        Some(worker) => worker,
+        // already on every worker
+        None => return Ok(()),
+    };
+    let router = create_router(&worker, config.media_codecs.clone()).await?;
+
+    let (producers, data_producers) = peers.with(|p| p.producers_on(&router_ids));
+    for producer in producers.iter() {
+        producer
+            .transport()
+            .router()
+            .pipe_producer_to_router(producer.id(), PipeToRouterOptions::new(router.clone()))
+            .await
+            .map_err(|error| {
+                HandlerError::Mediasoup(format!(
+                    "Failed to pipe producer to new room router: {}",
+                    error
+                ))
+            })?;
+    }
+    for data_producer in data_producers.iter() {
+        data_producer
+            .transport()
+            .router()
+            .pipe_data_producer_to_router(
+                data_producer.id(),
+                PipeToRouterOptions::new(router.clone()),
+            )
+            .await
+            .map_err(|error| {
+                HandlerError::Mediasoup(format!(
+                    "Failed to pipe data producer to new room router: {}",
+                    error
+                ))
+            })?;
+    }
+    register_router(&mut rooms_guard, room, &router, &routers, &routers2workers).await;
+    info!(
+        "room {} grew to {} routers, added {} on worker {}",
+        room,
+        router_ids.len() + 1,
+        router.id(),
+        worker.id()
+    );
+    Ok(())
+}
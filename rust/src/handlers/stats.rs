@@ -0,0 +1,130 @@
+use std::sync::Arc;
+
+use mediasoup::{
+    prelude::{Transport, TransportGeneric},
+    webrtc_transport::WebRtcTransportStat,
+};
+use uuid::Uuid;
+
+use crate::{
+    handlers::{consumer_layers::find_consumer, error::HandlerError},
+    models::{peer::Peers, sfu::PlainTransports},
+    utils::{
+        codec::{
+            ConsumerStatsData, ConsumerStatsRequestData, MessageResponse, PeerStat,
+            ProducerStatsData, ProducerStatsRequestData, ResponseMessage, TransportStatsData,
+            TransportStatsRequestData,
+        },
+        utils::{Mut, Responder},
+    },
+};
+
+pub async fn get_transport_stats(
+    wsid: String,
+    data: TransportStatsRequestData,
+    peers: Arc<Mut<Peers>>,
+    sender: Responder,
+) -> Result<(), HandlerError> {
+    let transport = peers
+        .with(|p| p.transport(data.peerId))
+        .ok_or(HandlerError::NotFound(format!(
+            "cannot find transport of peer {}",
+            data.peerId
+        )))?;
+    let stats = transport.get_stats().await.map_err(|error| {
+        HandlerError::Mediasoup(format!("failed to get transport stats: {}", error))
+    })?;
+    reply(
+        wsid,
+        MessageResponse::transportStats {
+            data: TransportStatsData {
+                peerId: data.peerId,
+                transportId: transport.id(),
+                stats,
+            },
+        },
+        sender,
+    )
+    .await;
+    Ok(())
+}
+
+pub async fn get_producer_stats(
+    wsid: String,
+    data: ProducerStatsRequestData,
+    peers: Arc<Mut<Peers>>,
+    sender: Responder,
+) -> Result<(), HandlerError> {
+    let (_, producer) =
+        peers
+            .with(|p| p.producer(data.producerId))
+            .ok_or(HandlerError::NotFound(format!(
+                "cannot find producer on this server: {}",
+                data.producerId
+            )))?;
+    let stats = producer.get_stats().await.map_err(|error| {
+        HandlerError::Mediasoup(format!("failed to get producer stats: {}", error))
+    })?;
+    reply(
+        wsid,
+        MessageResponse::producerStats {
+            data: ProducerStatsData {
+                producerId: data.producerId,
+                stats,
+            },
+        },
+        sender,
+    )
+    .await;
+    Ok(())
+}
+
+pub async fn get_consumer_stats(
+    wsid: String,
+    data: ConsumerStatsRequestData,
+    peers: Arc<Mut<Peers>>,
+    plain_transports: Arc<Mut<PlainTransports>>,
+    sender: Responder,
+) -> Result<(), HandlerError> {
+    let consumer = find_consumer(&peers, &plain_transports, data.consumerId)?;
+    let stats = consumer.get_stats().await.map_err(|error| {
+        HandlerError::Mediasoup(format!("failed to get consumer stats: {}", error))
+    })?;
+    reply(
+        wsid,
+        MessageResponse::consumerStats {
+            data: ConsumerStatsData {
+                consumerId: data.consumerId,
+                stats,
+            },
+        },
+        sender,
+    )
+    .await;
+    Ok(())
+}
+
+// Summary pushed to signaling every stats_interval, None when mediasoup
+// returned no report for the transport.
+pub fn peer_stat(peer_id: Uuid, stats: &[WebRtcTransportStat]) -> Option<PeerStat> {
+    let stat = stats.first()?;
+    Some(PeerStat {
+        peerId: peer_id,
+        transportId: stat.transport_id,
+        iceState: stat.ice_state,
+        recvBitrate: stat.recv_bitrate,
+        sendBitrate: stat.send_bitrate,
+        availableOutgoingBitrate: stat.available_outgoing_bitrate,
+        packetLossReceived: stat.rtp_packet_loss_received,
+        packetLossSent: stat.rtp_packet_loss_sent,
+    })
+}
+
+async fn reply(wsid: String, communication: MessageResponse, sender: Responder) {
+    let msg = ResponseMessage::OutgoingCommunication {
+        ws: Some(wsid),
+        requestId: None,
+        communication,
+    };
+    let _ = sender.send(msg).await;
+}
@@ -1,19 +1,29 @@
 #[cfg(test)]
 mod tests {
-    use crate::handlers::cpu_load::get_media_loads;
+    use std::time::Duration;
+
+    use crate::handlers::cpu_load::{get_process_memory, CpuSampler};
 
     #[tokio::test]
     async fn test_cpu_load() {
-        match get_media_loads() {
-            Ok(percentage) => {
+        let mut sampler = CpuSampler::new();
+        assert_eq!(sampler.sample(), Ok(None));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        match sampler.sample() {
+            Ok(Some(percentage)) => {
                 assert!(
-                    percentage >= 0.0 && percentage <= 100.0,
+                    (0.0..=100.0).contains(&percentage),
                     "CPU usage percentage should be between 0 and 100"
                 );
             }
-            Err(e) => {
-                panic!("Unexpected error: {}", e);
+            other => {
+                panic!("Unexpected sample: {:?}", other);
             }
         }
     }
+
+    #[test]
+    fn test_process_memory() {
+        assert!(get_process_memory().unwrap() > 0);
+    }
 }
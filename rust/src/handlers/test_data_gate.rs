@@ -0,0 +1,91 @@
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::{
+        config::config::DataLimits, handlers::data_gate::DataLimiter, utils::codec::ThrottleState,
+    };
+
+    fn limits() -> DataLimits {
+        DataLimits {
+            messages_per_sec: Some(3),
+            bytes_per_sec: Some(1000),
+            max_message_size: Some(500),
+            throttle_seconds: 2,
+            pause: Duration::from_secs(10),
+        }
+    }
+
+    fn at(start: Instant, millis: u64) -> Instant {
+        start + Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn test_messages_within_limits_pass() {
+        let start = Instant::now();
+        let mut limiter = DataLimiter::new(limits(), start);
+        for second in 0..5 {
+            for i in 0..3 {
+                let now = at(start, second * 1000 + i * 10);
+                assert_eq!(limiter.check(300, now), (true, None));
+            }
+        }
+        assert_eq!(limiter.take_dropped(), 0);
+    }
+
+    #[test]
+    fn test_over_limit_throttles_then_pauses() {
+        let start = Instant::now();
+        let mut limiter = DataLimiter::new(limits(), start);
+        // oversized messages are dropped whatever the rate
+        assert_eq!(
+            limiter.check(501, start),
+            (false, Some(ThrottleState::throttled))
+        );
+        assert_eq!(limiter.check(100, at(start, 1)), (true, None));
+        assert_eq!(limiter.check(100, at(start, 2)), (true, None));
+        assert_eq!(limiter.check(100, at(start, 3)), (true, None));
+        assert_eq!(limiter.check(100, at(start, 4)), (false, None));
+        assert_eq!(limiter.take_dropped(), 2);
+
+        // a second window in a row over the limits pauses the producer
+        assert_eq!(limiter.check(450, at(start, 1000)), (true, None));
+        assert_eq!(limiter.check(450, at(start, 1000)), (true, None));
+        assert_eq!(
+            limiter.check(200, at(start, 1001)),
+            (false, Some(ThrottleState::paused))
+        );
+        assert_eq!(limiter.check(1, at(start, 5000)), (false, None));
+        assert_eq!(limiter.take_dropped(), 2);
+
+        assert_eq!(
+            limiter.check(1, at(start, 11001)),
+            (true, Some(ThrottleState::active))
+        );
+    }
+
+    #[test]
+    fn test_clean_window_resets_throttling() {
+        let start = Instant::now();
+        let mut limiter = DataLimiter::new(limits(), start);
+        assert_eq!(
+            limiter.check(501, start),
+            (false, Some(ThrottleState::throttled))
+        );
+        // nothing dropped in the next window, the producer is active again
+        assert_eq!(limiter.check(10, at(start, 1000)), (true, None));
+        assert_eq!(
+            limiter.check(10, at(start, 2000)),
+            (true, Some(ThrottleState::active))
+        );
+        // a silent window counts as a clean one
+        assert_eq!(
+            limiter.check(501, at(start, 3000)),
+            (false, Some(ThrottleState::throttled))
+        );
+        assert_eq!(
+            limiter.check(10, at(start, 5500)),
+            (true, Some(ThrottleState::active))
+        );
+    }
+}
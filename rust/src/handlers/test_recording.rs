@@ -0,0 +1,48 @@
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use crate::handlers::recording::{file_safe, rtpdump_header, rtpdump_packet};
+
+    #[test]
+    fn test_rtpdump_header() {
+        let start = UNIX_EPOCH + Duration::new(1_700_000_000, 250_000_000);
+        let header = rtpdump_header(start, "127.0.0.1:40000".parse().unwrap());
+        let text = b"#!rtpplay1.0 127.0.0.1/40000\n";
+        assert_eq!(&header[..text.len()], text);
+        let binary = &header[text.len()..];
+        assert_eq!(binary.len(), 16);
+        assert_eq!(binary[0..4], 1_700_000_000u32.to_be_bytes());
+        assert_eq!(binary[4..8], 250_000u32.to_be_bytes());
+        assert_eq!(binary[8..12], [127, 0, 0, 1]);
+        assert_eq!(binary[12..14], 40000u16.to_be_bytes());
+        assert!(rtpdump_header(SystemTime::now(), "[::1]:5000".parse().unwrap()).len() > 16);
+    }
+
+    #[test]
+    fn test_rtpdump_packet_records() {
+        // opus payload type 100 with the marker bit
+        let mut rtp = vec![0x80, 0xe4, 0, 1];
+        rtp.extend_from_slice(&[0; 16]);
+        let record = rtpdump_packet(Duration::from_millis(1500), &rtp);
+        assert_eq!(record[0..2], 28u16.to_be_bytes());
+        assert_eq!(record[2..4], 20u16.to_be_bytes());
+        assert_eq!(record[4..8], 1500u32.to_be_bytes());
+        assert_eq!(&record[8..], &rtp[..]);
+
+        // sender report, muxed on the same port
+        let rtcp = [0x80, 200, 0, 6, 1, 2, 3, 4];
+        let record = rtpdump_packet(Duration::from_millis(20), &rtcp);
+        assert_eq!(record[0..2], 16u16.to_be_bytes());
+        assert_eq!(record[2..4], 0u16.to_be_bytes());
+        assert_eq!(&record[8..], &rtcp[..]);
+    }
+
+    #[test]
+    fn test_room_names_stay_in_recording_dir() {
+        assert_eq!(file_safe("standup-room_1"), "standup-room_1");
+        assert_eq!(file_safe("../../etc"), "______etc");
+        assert_eq!(file_safe("a/b"), "a_b");
+        assert_eq!(file_safe(""), "_");
+    }
+}
@@ -0,0 +1,43 @@
+#[cfg(test)]
+mod tests {
+    use mediasoup::{data_structures::IceState, webrtc_transport::WebRtcTransportStat};
+    use uuid::Uuid;
+
+    use crate::handlers::stats::peer_stat;
+
+    // report of a connected transport as the worker sends it
+    const TRANSPORT_STATS: &str = r#"[{
+        "type": "webrtc-transport",
+        "transportId": "0b2a3c4d-5e6f-4a1b-8c2d-3e4f5a6b7c8d",
+        "timestamp": 123456,
+        "sctpState": "connected",
+        "bytesReceived": 1000, "recvBitrate": 640000,
+        "bytesSent": 2000, "sendBitrate": 1200000,
+        "rtpBytesReceived": 900, "rtpRecvBitrate": 600000,
+        "rtpBytesSent": 1800, "rtpSendBitrate": 1100000,
+        "rtxBytesReceived": 0, "rtxRecvBitrate": 0,
+        "rtxBytesSent": 0, "rtxSendBitrate": 0,
+        "probationBytesSent": 0, "probationSendBitrate": 0,
+        "availableOutgoingBitrate": 2500000,
+        "rtpPacketLossReceived": 0.02,
+        "iceRole": "controlled",
+        "iceState": "completed",
+        "dtlsState": "connected"
+    }]"#;
+
+    #[test]
+    fn test_peer_stat_summarises_transport() {
+        let stats: Vec<WebRtcTransportStat> = serde_json::from_str(TRANSPORT_STATS).unwrap();
+        let peer_id = Uuid::new_v4();
+        let stat = peer_stat(peer_id, &stats).unwrap();
+        assert_eq!(stat.peerId, peer_id);
+        assert_eq!(stat.transportId, stats[0].transport_id);
+        assert_eq!(stat.iceState, IceState::Completed);
+        assert_eq!((stat.recvBitrate, stat.sendBitrate), (640000, 1200000));
+        assert_eq!(stat.availableOutgoingBitrate, Some(2500000));
+        assert_eq!(stat.packetLossReceived, Some(0.02));
+        assert_eq!(stat.packetLossSent, None);
+
+        assert!(peer_stat(peer_id, &[]).is_none());
+    }
+}
@@ -3,23 +3,26 @@ use std::{collections::HashMap, ops::Deref, sync::Arc};
 
 use log::{error, info};
 use mediasoup::{
-    prelude::{AppData, Consumer, ConsumerId, ConsumerOptions},
-    rtp_parameters::RtpCapabilities,
+    prelude::{AppData, ConsumerOptions},
+    rtp_parameters::{MediaKind, RtpCapabilities},
     transport::Transport,
 };
 use tokio::sync::{mpsc::Sender, Mutex, RwLock};
 use uuid::Uuid;
 
 use crate::{
+    handlers::{
+        consumer_layers::forward_consumer_events,
+        error::{HandlerError, Limit},
+    },
     models::{
         message::NewConsumerOptions,
-        sfu::{
-            PeerConsumed, PeerConsumedData, Routers, Transport2Router, Transports, VideoProducers,
-        },
+        peer::{attach, forget_consumer, Peers},
+        sfu::{Routers, Transport2Router},
     },
     utils::{
         codec::{appData, MessageResponse, ResponseMessage},
-        utils::Mut,
+        utils::{Mut, Responder},
     },
 };
 
@@ -28,17 +31,18 @@ pub async fn consume_video(
     consumer_peer: Uuid,
     producer_peers: Vec<Uuid>,
     rtpCaps: RtpCapabilities,
-    videoProducers: Arc<Mutex<VideoProducers>>,
-    peerconsumed: Arc<Mutex<PeerConsumed>>,
-    transports: Arc<Mut<Transports>>,
+    peers: Arc<Mut<Peers>>,
     transport2router: Arc<RwLock<Transport2Router>>,
     routers: Arc<Mutex<Routers>>,
-    consumers: Arc<Mutex<HashMap<ConsumerId, Consumer>>>,
-    sender: Sender<ResponseMessage>,
-) -> Result<(), String> {
+    signaling: Arc<Mut<Option<Sender<ResponseMessage>>>>,
+    max_consumers: Option<u32>,
+    sender: Responder,
+) -> Result<(), HandlerError> {
     let mut video_announcement: HashMap<Uuid, Vec<NewConsumerOptions>> = HashMap::new();
-    for peer in producer_peers.into_iter() {
-        let get_peer = videoProducers.lock().await.get(peer.clone());
+    // consumers created before the limit are still announced
+    let mut rejected = None;
+    'peers: for peer in producer_peers.into_iter() {
+        let get_peer = peers.with(|p| p.get(peer).map(|p| p.producers_of(MediaKind::Video)));
         if get_peer.is_none() {
             info!(
                 "Peer {:?} does not have video producer, skipping",
@@ -47,24 +51,40 @@ pub async fn consume_video(
             continue;
         }
         for current_peer in get_peer.unwrap().into_iter() {
-            let peer_consumed = peerconsumed
-                .lock()
-                .await
-                .find(consumer_peer, current_peer.id());
-            if !peer_consumed.is_empty() {
+            let peer_consumed = peers.with(|p| {
+                p.get(consumer_peer)
+                    .map(|c| c.consumes(current_peer.id()))
+                    .unwrap_or(false)
+            });
+            if peer_consumed {
                 println!("peer {:?} already consumed!", &peer);
                 continue;
             }
-            let get_transports = transports.with(|inner| inner.get(consumer_peer));
+            if let Some(max) = max_consumers {
+                let consumers =
+                    peers.with(|p| p.get(consumer_peer).map(|c| c.consumers.len()).unwrap_or(0));
+                if consumers >= max as usize {
+                    rejected = Some(HandlerError::LimitReached(
+                        Limit::transportConsumers,
+                        format!("peer {} already has {} consumers", consumer_peer, max),
+                    ));
+                    break 'peers;
+                }
+            }
+            let get_transports = peers.with(|p| p.transport(consumer_peer));
             if get_transports.is_none() {
                 println!("cannot find transports");
-                return Err(String::from("cannot find transports"));
+                return Err(HandlerError::NotFound(String::from(
+                    "cannot find transports",
+                )));
             };
             let transport = get_transports.unwrap();
             let get_transport2router = transport2router.read().await.get(transport.clone().id());
             if get_transport2router.is_none() {
                 println!("cannot find transport2router");
-                return Err(String::from("cannot find transport2router"));
+                return Err(HandlerError::NotFound(String::from(
+                    "cannot find transport2router",
+                )));
             }
             let transport2router = get_transport2router.unwrap();
             let get_router = routers.lock().await.get(transport2router);
@@ -80,20 +100,20 @@ pub async fn consume_video(
                 .clone();
             if get_router.is_none() {
                 println!("consumer peer transport not defined on this egress server");
-                return Err(format!(
+                return Err(HandlerError::NotFound(format!(
                     "Error: consumer peer transport not defined on this egress server. {:?}",
                     transport.clone()
-                ));
+                )));
             }
             let router = get_router.unwrap();
             if router.can_consume(&producer_id, &rtpCaps) {
                 let mut consumer_options = ConsumerOptions::new(producer_id, rtpCaps.clone());
                 consumer_options.paused = false;
                 consumer_options.app_data = AppData::new(app_data.clone());
-                let newMediaConsumer = transport
-                    .consume(consumer_options)
-                    .await
-                    .map_err(|error| format!("Failed consume: {:?}", error))?;
+                let newMediaConsumer =
+                    transport.consume(consumer_options).await.map_err(|error| {
+                        HandlerError::Mediasoup(format!("Failed consume: {:?}", error))
+                    })?;
                 newMediaConsumer.on_producer_pause(|| {}).detach();
                 newMediaConsumer.on_producer_resume(|| {}).detach();
                 newMediaConsumer
@@ -101,6 +121,12 @@ pub async fn consume_video(
                         println!("producer stopped");
                     }))
                     .detach();
+                forward_consumer_events(
+                    &newMediaConsumer,
+                    wsid.clone(),
+                    consumer_peer,
+                    signaling.clone(),
+                );
                 // preper relay message
                 let video_consumer_options = NewConsumerOptions {
                     id: newMediaConsumer.clone().id(),
@@ -121,21 +147,17 @@ pub async fn consume_video(
                     .entry(peer)
                     .or_default()
                     .push(video_consumer_options);
-                let peer_consumer_data = PeerConsumedData {
-                    peer_id: consumer_peer,
-                    produce_id: current_peer.id(),
-                    consumer_id: newMediaConsumer.id(),
-                };
-                let mut peer_consumed = peerconsumed.lock().await;
-                peer_consumed.create(peer_consumer_data);
-                let mut consumers = consumers.lock().await;
-                consumers.insert(newMediaConsumer.id(), newMediaConsumer);
+                forget_consumer(&peers, &newMediaConsumer);
+                attach(&peers, consumer_peer, newMediaConsumer, |peer, consumer| {
+                    peer.consumers.push(consumer)
+                })?;
             }
         }
     }
     if video_announcement.len() > 0 {
         let reply_consumer = ResponseMessage::OutgoingCommunication {
             ws: Some(wsid.clone()),
+            requestId: None,
             communication: MessageResponse::videoAnnouncement {
                 data: video_announcement.clone(),
             },
@@ -145,5 +167,8 @@ pub async fn consume_video(
             error!("error sending message: {:?}", e);
         };
     }
-    Ok(())
+    match rejected {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
 }
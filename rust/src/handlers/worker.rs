@@ -1,18 +1,16 @@
-use log::info;
+use log::{error, info};
 use mediasoup::{
     data_structures::Protocol,
-    prelude::ListenIp,
     webrtc_server::{WebRtcServerListenInfo, WebRtcServerListenInfos, WebRtcServerOptions},
-    worker::{WorkerLogLevel, WorkerLogTag, WorkerSettings},
+    worker::{Worker, WorkerLogLevel, WorkerSettings},
     worker_manager::WorkerManager,
 };
 use std::sync::{mpsc, Arc};
-use tokio::sync::{Mutex, RwLock};
+use tokio::sync::{mpsc::UnboundedSender, Mutex, RwLock};
 
 use crate::{
     config::config::Config,
-    models::sfu::{TempWorker, WebrtcServers, WorkerLoad, Workers},
-    utils::utils::get_now_ms,
+    models::sfu::{DeadWorker, Loads, WebrtcServers, Workers},
 };
 
 pub async fn create_worker(
@@ -20,110 +18,84 @@ pub async fn create_worker(
     webrtc_server: Arc<RwLock<WebrtcServers>>,
     num_workers: Option<i32>,
     config: Config,
+    loads: Arc<Mutex<Loads>>,
+    dead_workers: UnboundedSender<DeadWorker>,
 ) -> Result<(), String> {
-    let _temp_load = Arc::new(Mutex::new(TempWorker::new()));
-    let (tx, rx) = mpsc::sync_channel::<u32>(128);
-    let callback = Arc::new(move || {
-        let _ = tx.send(00);
-    });
     if num_workers.is_none() {
         return Err("cannot find number of workers set in the server".to_string());
     }
     for n in 0..num_workers.unwrap() {
-        let port = Some(config.webrtc_port + n as u16);
-        let worker_manager = WorkerManager::new();
-        let worker = worker_manager
-            .create_worker({
-                let mut settings = WorkerSettings::default();
-                settings.log_level = WorkerLogLevel::default();
-                settings.log_tags = vec![
-                    WorkerLogTag::Info,
-                    WorkerLogTag::Ice,
-                    WorkerLogTag::Dtls,
-                    WorkerLogTag::Rtp,
-                    WorkerLogTag::Srtp,
-                    WorkerLogTag::Rtcp,
-                    WorkerLogTag::Rtx,
-                    WorkerLogTag::Bwe,
-                    WorkerLogTag::Score,
-                    WorkerLogTag::Simulcast,
-                    WorkerLogTag::Svc,
-                    WorkerLogTag::Sctp,
-                    WorkerLogTag::Message,
-                ];
-                settings.thread_initializer = Some(callback.clone());
-                settings
-            })
-            .await
-            .map_err(|error| format!("Failed to create worker: {}", error))?;
-        worker
-            .on_dead(|e| {
-                println!("worker had dead here is the error: {:?}", e.unwrap_err());
-                std::process::exit(1);
-            })
-            .detach();
-        match rx.try_recv() {
-            Ok(thread_id) => {
-                let pid = std::process::id();
-                let worker_load = WorkerLoad {
-                    worker_id: worker.id(),
-                    pid,
-                    thread_id,
-                    wall: get_now_ms(),
-                    cpu: 0.0,
-                };
-                info!("workload: {:?}", worker_load);
-            }
-            Err(_) => {}
-        }
-        let listen_udp = if config.traversenat == "true" {
-            WebRtcServerListenInfo {
-                protocol: Protocol::Udp,
-                listen_ip: ListenIp {
-                    ip: "0.0.0.0".parse().unwrap(),
-                    announced_ip: Some(config.announceip),
-                },
-                port,
-            }
-        } else {
-            WebRtcServerListenInfo {
-                protocol: Protocol::Udp,
-                listen_ip: ListenIp {
-                    ip: local_ipaddress::get().unwrap().parse().unwrap(),
-                    announced_ip: None,
-                },
-                port,
-            }
-        };
-        let listen_tcp = if config.traversenat == "true" {
-            WebRtcServerListenInfo {
-                protocol: Protocol::Tcp,
-                listen_ip: ListenIp {
-                    ip: "0.0.0.0".parse().unwrap(),
-                    announced_ip: Some(config.announceip),
-                },
-                port,
-            }
-        } else {
-            WebRtcServerListenInfo {
-                protocol: Protocol::Tcp,
-                listen_ip: ListenIp {
-                    ip: local_ipaddress::get().unwrap().parse().unwrap(),
-                    announced_ip: None,
-                },
-                port,
-            }
-        };
-        let listen_infos = WebRtcServerListenInfos::new(listen_udp);
-        let listen_infos = listen_infos.insert(listen_tcp);
-        let webrtc_server_options = WebRtcServerOptions::new(listen_infos);
-        let mut webrtc_server = webrtc_server.write().await;
-        match worker.create_webrtc_server(webrtc_server_options).await {
-            Ok(w) => webrtc_server.create(worker.id(), w),
-            Err(_) => println!("error creating worker"),
-        }
-        let mut workers = workers.write().await;
-        workers.create(worker);
+        let port = config.webrtc_port + n as u16;
+        let worker = start_worker(
+            workers.clone(),
+            webrtc_server.clone(),
+            port,
+            config.clone(),
+            loads.clone(),
+            dead_workers.clone(),
+        )
+        .await?;
+        info!("started worker {} on port {}", worker.id(), port);
     }
     Ok(())
 }
+
+// create a single worker and its webrtc server listening on the given port,
+// used at startup and by the supervisor to replace a dead worker
+pub async fn start_worker(
+    workers: Arc<RwLock<Workers>>,
+    webrtc_server: Arc<RwLock<WebrtcServers>>,
+    port: u16,
+    config: Config,
+    loads: Arc<Mutex<Loads>>,
+    dead_workers: UnboundedSender<DeadWorker>,
+) -> Result<Worker, String> {
+    let (tx, rx) = mpsc::sync_channel::<i32>(1);
+    let callback = Arc::new(move || {
+        let _ = tx.send(os_id::thread::get_raw_id());
+    });
+    let worker_manager = WorkerManager::new();
+    let worker = worker_manager
+        .create_worker({
+            let mut settings = WorkerSettings::default();
+            settings.log_level = WorkerLogLevel::default();
+            settings.log_tags = config.worker_log_tags.clone();
+            settings.thread_initializer = Some(callback.clone());
+            settings
+        })
+        .await
+        .map_err(|error| format!("Failed to create worker: {}", error))?;
+    let worker_id = worker.id();
+    worker
+        .on_dead(move |e| {
+            error!("worker {} had dead here is the error: {:?}", worker_id, e);
+            let _ = dead_workers.send(DeadWorker { worker_id, port });
+        })
+        .detach();
+    // the initializer ran on the worker thread before create_worker returned
+    let thread_id = rx.try_recv().ok();
+    info!("worker {} runs on thread {:?}", worker_id, thread_id);
+    loads.lock().await.register(worker_id, thread_id);
+    let port = Some(port);
+    let listen_udp = WebRtcServerListenInfo {
+        protocol: Protocol::Udp,
+        listen_ip: config.listen_ip(),
+        port,
+    };
+    let listen_tcp = WebRtcServerListenInfo {
+        protocol: Protocol::Tcp,
+        listen_ip: config.listen_ip(),
+        port,
+    };
+    let listen_infos = WebRtcServerListenInfos::new(listen_udp);
+    let listen_infos = listen_infos.insert(listen_tcp);
+    let webrtc_server_options = WebRtcServerOptions::new(listen_infos);
+    let mut webrtc_server = webrtc_server.write().await;
+    match worker.create_webrtc_server(webrtc_server_options).await {
+        Ok(w) => webrtc_server.create(worker.id(), w),
+        Err(_) => println!("error creating worker"),
+    }
+    let mut workers = workers.write().await;
+    workers.create(worker.clone());
+    Ok(worker)
+}
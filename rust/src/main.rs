@@ -1,35 +1,109 @@
+use crate::handlers::worker::create_worker;
+use crate::models::sfu::DeadWorker;
+use crate::server::auth::AuthError;
+use crate::server::capacity::sample_capacity;
+use crate::server::drain::start_drain;
+use crate::server::interest::manage_interest;
+use crate::server::metrics::serve_metrics;
 use crate::server::models::MediaServer;
+use crate::server::rooms::close_idle_rooms;
+use crate::server::stats::push_peer_stats;
 use crate::server::stream::handle_stream;
+use crate::server::supervisor::supervise_workers;
+use crate::server::tls::SignalingTls;
 use crate::utils::utils::init;
+use crate::utils::worker_load::sample_worker_loads;
 use log::{error, info};
-use std::net::{SocketAddr, ToSocketAddrs};
-use std::str::FromStr;
+use std::time::Duration;
 use tokio::net::TcpStream;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::mpsc::unbounded_channel;
+use tokio::time::{sleep, Instant};
 mod config;
 mod handlers;
 mod models;
 mod server;
 mod utils;
-static mut IDX_COUNT: usize = 0;
+// delay between attempts to reach signaling, doubled after every failure
+const MIN_RECONNECT_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_RECONNECT_BACKOFF: Duration = Duration::from_secs(30);
 
 #[tokio::main]
 async fn main() {
     env_logger::init();
-    let config = init();
+    let config = match init() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("invalid configuration: {}", e);
+            std::process::exit(2);
+        }
+    };
+    let tls = match config.tls.as_ref().map(SignalingTls::new).transpose() {
+        Ok(tls) => tls,
+        Err(e) => {
+            eprintln!("invalid tls configuration: {}", e);
+            std::process::exit(2);
+        }
+    };
     let media_server = MediaServer::new(config.clone());
-    let addr = config
-        .server_address
-        .to_socket_addrs()
-        .unwrap()
-        .find(|addr| addr.is_ipv4())
-        .unwrap_or_else(|| {
-            let default = "0.0.0.0:1188";
-            println!(
-                "Can not resolve ipv4 address from given url. Fall back to default value: {}",
-                default
-            );
-            SocketAddr::from_str(default).unwrap()
+    // create workers, they are kept alive across reconnects to signaling
+    let (dead_workers_tx, dead_workers_rx) = unbounded_channel::<DeadWorker>();
+    if let Err(e) = create_worker(
+        media_server.workers.clone(),
+        media_server.webrtc_server.clone(),
+        media_server.num_workers,
+        config.clone(),
+        media_server.loads.clone(),
+        dead_workers_tx.clone(),
+    )
+    .await
+    {
+        error!("error creating workers: {}", e);
+    }
+    tokio::spawn(supervise_workers(
+        media_server.clone(),
+        config.clone(),
+        dead_workers_tx,
+        dead_workers_rx,
+    ));
+    {
+        // SIGTERM drains the node like a drainNode request using drain_timeout
+        let media_server = media_server.clone();
+        let drain_timeout = config.drain_timeout;
+        tokio::spawn(async move {
+            let mut sigterm = match signal(SignalKind::terminate()) {
+                Ok(sigterm) => sigterm,
+                Err(e) => {
+                    error!("cannot listen for SIGTERM: {:?}", e);
+                    return;
+                }
+            };
+            while sigterm.recv().await.is_some() {
+                if start_drain(&media_server, drain_timeout) {
+                    info!("SIGTERM received, draining within {:?}", drain_timeout);
+                }
+            }
         });
+    }
+    if let Some(interest) = config.interest.clone() {
+        tokio::spawn(manage_interest(media_server.clone(), interest));
+    }
+    if let Some(every) = config.stats_interval {
+        tokio::spawn(push_peer_stats(media_server.clone(), every));
+    }
+    if let Some(timeout) = config.room_idle_timeout {
+        tokio::spawn(close_idle_rooms(media_server.clone(), timeout));
+    }
+    if let Some(port) = config.metrics_port {
+        tokio::spawn(serve_metrics(port, media_server.clone()));
+    }
+    tokio::spawn(sample_capacity(media_server.clone()));
+    tokio::spawn(sample_worker_loads(
+        media_server.workers.clone(),
+        media_server.peers.clone(),
+        media_server.loads.clone(),
+    ));
+    let addr = config.server_address;
     println!("server address: {:?}", &config.announceip);
     println!(
         "[@] Node ID: ingress: {:?} or egress: {:?}",
@@ -37,27 +111,68 @@ async fn main() {
     );
     println!("[+] Successfully started");
     tokio::spawn(async move {
+        let mut backoff = MIN_RECONNECT_BACKOFF;
         loop {
             info!("Connecting to: {}", &addr);
 
             match TcpStream::connect(addr).await {
                 Ok(stream) => {
-                    match handle_stream(stream, media_server.clone(), config.clone()).await {
+                    let started = Instant::now();
+                    let mut registered = false;
+                    if let Err(e) = stream.set_nodelay(true) {
+                        error!("cannot set nodelay: {:?}", e);
+                    }
+                    let res = match &tls {
+                        Some(tls) => match tls.connect(stream).await {
+                            Ok(stream) => {
+                                handle_stream(
+                                    stream,
+                                    media_server.clone(),
+                                    config.clone(),
+                                    &mut registered,
+                                )
+                                .await
+                            }
+                            Err(e) => Err(AuthError(format!("tls handshake: {}", e)).into()),
+                        },
+                        None => {
+                            handle_stream(
+                                stream,
+                                media_server.clone(),
+                                config.clone(),
+                                &mut registered,
+                            )
+                            .await
+                        }
+                    };
+                    let rejected = match res {
                         Ok(_) => {
-                            error!("TCP stream disconnect Shutting down application");
-                            std::process::exit(1);
+                            error!("TCP stream disconnect, reconnecting");
+                            false
+                        }
+                        Err(e) if e.is::<AuthError>() => {
+                            error!("signaling auth failed: {}", e);
+                            true
                         }
                         Err(e) => {
                             error!("Tcp handle error: {:?}", e);
-                            std::process::exit(1);
+                            false
                         }
+                    };
+                    // a session signaling took part in starts the backoff
+                    // over, one dropped right after connecting or failing the
+                    // handshake keeps growing it
+                    if !rejected && (registered || started.elapsed() > MAX_RECONNECT_BACKOFF) {
+                        backoff = MIN_RECONNECT_BACKOFF;
                     }
                 }
                 Err(e) => {
                     error!("Tcp connect error: {:?}", e);
-                    std::process::exit(1);
                 }
             }
+            info!("Reconnecting in {:?}", backoff);
+            sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_RECONNECT_BACKOFF);
         }
     })
     .await
@@ -3,10 +3,12 @@ use mediasoup::{
     data_producer::DataProducerId,
     prelude::{ConsumerId, DataConsumerId, SctpStreamParameters},
     producer::ProducerId,
+    router::RouterId,
     rtp_parameters::{MediaKind, RtpParameters},
     transport::TransportId,
 };
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
 use crate::utils::codec::appData;
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -29,3 +31,34 @@ pub struct NewDataConsumerOptions {
     pub protocol: String,
     pub appData: appData,
 }
+
+// state of a room pushed to signaling after a reconnect so it can reconcile
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct RoomSnapshot {
+    pub roomName: String,
+    pub routers: Vec<RouterId>,
+    pub peers: Vec<PeerSnapshot>,
+    pub relays: Vec<RelaySnapshot>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct PeerSnapshot {
+    pub peerId: Uuid,
+    pub transportId: TransportId,
+    pub producers: Vec<ProducerSnapshot>,
+    pub dataProducers: Vec<DataProducerId>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ProducerSnapshot {
+    pub id: ProducerId,
+    pub kind: MediaKind,
+    pub paused: bool,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct RelaySnapshot {
+    pub egress: Uuid,
+    pub transportId: TransportId,
+    pub connected: bool,
+}
@@ -1,2 +1,8 @@
 pub mod message;
+pub mod movement;
+pub mod peer;
+pub mod room;
 pub mod sfu;
+pub mod test_movement;
+pub mod test_peer;
+pub mod test_room;
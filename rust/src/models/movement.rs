@@ -0,0 +1,418 @@
+use std::fmt;
+
+use byteorder::{ByteOrder, LittleEndian};
+
+// Avatar movement packets of the AvatarMovement data channel, laid out as in
+// webapp/core/movementProtocol.ts: an int16 header telling which fields are
+// present, the base position as three float32 and every other field as
+// float16, all little endian. Clients send the first 36 bytes when only the
+// base and head fields are set and the whole 78 bytes otherwise.
+pub const SMALL_PACKET_SIZE: usize = 36;
+pub const FULL_PACKET_SIZE: usize = 78;
+
+const BASE_POSITION: usize = 2;
+const BASE_ROTATION: usize = 14;
+const HEAD_POSITION: usize = 22;
+const HEAD_ROTATION: usize = 28;
+const LEFT_HAND_POSITION: usize = 36;
+const LEFT_HAND_ROTATION: usize = 42;
+const RIGHT_HAND_POSITION: usize = 50;
+const RIGHT_HAND_ROTATION: usize = 56;
+const GAME_PIECE_POSITION: usize = 64;
+const GAME_PIECE_ROTATION: usize = 70;
+
+// Bits of the packet header, PacketHeader in movementProtocol.ts.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PacketHeader(pub u16);
+
+impl PacketHeader {
+    pub const BASE_POSITION: u16 = 1;
+    pub const BASE_ROTATION: u16 = 2;
+    pub const HEAD_POSITION: u16 = 4;
+    pub const HEAD_ROTATION: u16 = 8;
+    pub const LEFT_HAND_POSITION: u16 = 16;
+    pub const LEFT_HAND_ROTATION: u16 = 32;
+    pub const RIGHT_HAND_POSITION: u16 = 64;
+    pub const RIGHT_HAND_ROTATION: u16 = 128;
+    pub const GAME_PIECE_POSITION: u16 = 256;
+    pub const GAME_PIECE_ROTATION: u16 = 512;
+    pub const FULL_PACKET: u16 = 1024;
+    pub const FREQUENT: u16 = 2048;
+
+    // fields a small packet is long enough to carry
+    const SMALL_FIELDS: u16 =
+        Self::BASE_POSITION | Self::BASE_ROTATION | Self::HEAD_POSITION | Self::HEAD_ROTATION;
+    // fields whose presence makes clients set FULL_PACKET
+    const FULL_FIELDS: u16 =
+        Self::LEFT_HAND_POSITION | Self::RIGHT_HAND_POSITION | Self::GAME_PIECE_POSITION;
+    // the 4 high bits are left to external use and carry no field
+    const RESERVED: u16 = 0xf000;
+
+    pub fn contains(self, bit: u16) -> bool {
+        self.0 & bit == bit
+    }
+
+    // shortest packet holding every field of the header, flags take no room
+    pub fn packet_size(self) -> usize {
+        if self.0 & !(Self::SMALL_FIELDS | Self::FREQUENT | Self::RESERVED) == 0 {
+            SMALL_PACKET_SIZE
+        } else {
+            FULL_PACKET_SIZE
+        }
+    }
+}
+
+// Why a packet was rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MovementError {
+    // neither a small nor a full packet
+    Length(usize),
+    // longer than a full packet
+    Oversized(usize),
+    // the header announces fields past the end of the packet
+    Truncated { header: u16, len: usize },
+    // a present field is NaN or infinite
+    NotFinite { header: u16 },
+}
+
+impl fmt::Display for MovementError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MovementError::Length(len) => write!(f, "invalid movement packet length {}", len),
+            MovementError::Oversized(len) => write!(f, "movement packet of {} bytes", len),
+            MovementError::Truncated { header, len } => write!(
+                f,
+                "movement header {:#06x} needs {} bytes, got {}",
+                header,
+                PacketHeader(*header).packet_size(),
+                len
+            ),
+            MovementError::NotFinite { header } => {
+                write!(f, "movement {:#06x} has a non finite value", header)
+            }
+        }
+    }
+}
+
+// A validated movement packet read in place, fields the header does not set
+// read as None.
+#[derive(Clone, Copy, Debug)]
+pub struct MovementPacket<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> MovementPacket<'a> {
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, MovementError> {
+        let len = bytes.len();
+        if len > FULL_PACKET_SIZE {
+            return Err(MovementError::Oversized(len));
+        }
+        if len != SMALL_PACKET_SIZE && len != FULL_PACKET_SIZE {
+            return Err(MovementError::Length(len));
+        }
+        let packet = MovementPacket { bytes };
+        let header = packet.header();
+        if header.packet_size() > len {
+            return Err(MovementError::Truncated {
+                header: header.0,
+                len,
+            });
+        }
+        if !packet.to_frame().is_finite() {
+            return Err(MovementError::NotFinite { header: header.0 });
+        }
+        Ok(packet)
+    }
+
+    pub fn header(&self) -> PacketHeader {
+        PacketHeader(LittleEndian::read_u16(self.bytes))
+    }
+
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    pub fn base_position(&self) -> Option<[f32; 3]> {
+        if !self.header().contains(PacketHeader::BASE_POSITION) {
+            return None;
+        }
+        let mut position = [0f32; 3];
+        LittleEndian::read_f32_into(
+            &self.bytes[BASE_POSITION..BASE_POSITION + 12],
+            &mut position,
+        );
+        Some(position)
+    }
+
+    // rotations are W, X, Y, Z as on the wire
+    pub fn base_rotation(&self) -> Option<[f32; 4]> {
+        self.halves(PacketHeader::BASE_ROTATION, BASE_ROTATION)
+    }
+
+    pub fn head_position(&self) -> Option<[f32; 3]> {
+        self.halves(PacketHeader::HEAD_POSITION, HEAD_POSITION)
+    }
+
+    pub fn head_rotation(&self) -> Option<[f32; 4]> {
+        self.halves(PacketHeader::HEAD_ROTATION, HEAD_ROTATION)
+    }
+
+    pub fn left_hand_position(&self) -> Option<[f32; 3]> {
+        self.halves(PacketHeader::LEFT_HAND_POSITION, LEFT_HAND_POSITION)
+    }
+
+    pub fn left_hand_rotation(&self) -> Option<[f32; 4]> {
+        self.halves(PacketHeader::LEFT_HAND_ROTATION, LEFT_HAND_ROTATION)
+    }
+
+    pub fn right_hand_position(&self) -> Option<[f32; 3]> {
+        self.halves(PacketHeader::RIGHT_HAND_POSITION, RIGHT_HAND_POSITION)
+    }
+
+    pub fn right_hand_rotation(&self) -> Option<[f32; 4]> {
+        self.halves(PacketHeader::RIGHT_HAND_ROTATION, RIGHT_HAND_ROTATION)
+    }
+
+    pub fn game_piece_position(&self) -> Option<[f32; 3]> {
+        self.halves(PacketHeader::GAME_PIECE_POSITION, GAME_PIECE_POSITION)
+    }
+
+    pub fn game_piece_rotation(&self) -> Option<[f32; 4]> {
+        self.halves(PacketHeader::GAME_PIECE_ROTATION, GAME_PIECE_ROTATION)
+    }
+
+    pub fn to_frame(self) -> MovementFrame {
+        MovementFrame {
+            frequent: self.header().contains(PacketHeader::FREQUENT),
+            base_position: self.base_position(),
+            base_rotation: self.base_rotation(),
+            head_position: self.head_position(),
+            head_rotation: self.head_rotation(),
+            left_hand_position: self.left_hand_position(),
+            left_hand_rotation: self.left_hand_rotation(),
+            right_hand_position: self.right_hand_position(),
+            right_hand_rotation: self.right_hand_rotation(),
+            game_piece_position: self.game_piece_position(),
+            game_piece_rotation: self.game_piece_rotation(),
+        }
+    }
+
+    fn halves<const N: usize>(&self, bit: u16, offset: usize) -> Option<[f32; N]> {
+        if !self.header().contains(bit) || offset + N * 2 > self.bytes.len() {
+            return None;
+        }
+        let mut values = [0f32; N];
+        for (i, value) in values.iter_mut().enumerate() {
+            *value = f16_to_f32(LittleEndian::read_u16(&self.bytes[offset + i * 2..]));
+        }
+        Some(values)
+    }
+}
+
+// Decoded movement, MovementFrame in movementProtocol.ts. Every field but the
+// base position is rounded to float16 when written.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MovementFrame {
+    pub frequent: bool,
+    pub base_position: Option<[f32; 3]>,
+    pub base_rotation: Option<[f32; 4]>,
+    pub head_position: Option<[f32; 3]>,
+    pub head_rotation: Option<[f32; 4]>,
+    pub left_hand_position: Option<[f32; 3]>,
+    pub left_hand_rotation: Option<[f32; 4]>,
+    pub right_hand_position: Option<[f32; 3]>,
+    pub right_hand_rotation: Option<[f32; 4]>,
+    pub game_piece_position: Option<[f32; 3]>,
+    pub game_piece_rotation: Option<[f32; 4]>,
+}
+
+impl MovementFrame {
+    // header a client would send for this frame
+    pub fn header(&self) -> PacketHeader {
+        let fields = [
+            (self.base_position.is_some(), PacketHeader::BASE_POSITION),
+            (self.base_rotation.is_some(), PacketHeader::BASE_ROTATION),
+            (self.head_position.is_some(), PacketHeader::HEAD_POSITION),
+            (self.head_rotation.is_some(), PacketHeader::HEAD_ROTATION),
+            (
+                self.left_hand_position.is_some(),
+                PacketHeader::LEFT_HAND_POSITION,
+            ),
+            (
+                self.left_hand_rotation.is_some(),
+                PacketHeader::LEFT_HAND_ROTATION,
+            ),
+            (
+                self.right_hand_position.is_some(),
+                PacketHeader::RIGHT_HAND_POSITION,
+            ),
+            (
+                self.right_hand_rotation.is_some(),
+                PacketHeader::RIGHT_HAND_ROTATION,
+            ),
+            (
+                self.game_piece_position.is_some(),
+                PacketHeader::GAME_PIECE_POSITION,
+            ),
+            (
+                self.game_piece_rotation.is_some(),
+                PacketHeader::GAME_PIECE_ROTATION,
+            ),
+            (self.frequent, PacketHeader::FREQUENT),
+        ];
+        let mut header = fields
+            .iter()
+            .filter(|(present, _)| *present)
+            .fold(0, |header, (_, bit)| header | bit);
+        if header & PacketHeader::FULL_FIELDS != 0 {
+            header |= PacketHeader::FULL_PACKET;
+        }
+        PacketHeader(header)
+    }
+
+    // Overwrite the fields `packet` carries, keeping the others.
+    pub fn merge(&mut self, packet: MovementPacket) {
+        let frame = packet.to_frame();
+        self.frequent = frame.frequent;
+        let fields = [
+            (&mut self.base_rotation, frame.base_rotation),
+            (&mut self.head_rotation, frame.head_rotation),
+            (&mut self.left_hand_rotation, frame.left_hand_rotation),
+            (&mut self.right_hand_rotation, frame.right_hand_rotation),
+            (&mut self.game_piece_rotation, frame.game_piece_rotation),
+        ];
+        for (field, value) in fields {
+            if value.is_some() {
+                *field = value;
+            }
+        }
+        let fields = [
+            (&mut self.base_position, frame.base_position),
+            (&mut self.head_position, frame.head_position),
+            (&mut self.left_hand_position, frame.left_hand_position),
+            (&mut self.right_hand_position, frame.right_hand_position),
+            (&mut self.game_piece_position, frame.game_piece_position),
+        ];
+        for (field, value) in fields {
+            if value.is_some() {
+                *field = value;
+            }
+        }
+    }
+
+    // Write the frame into `out` and return the length of the packet, the
+    // small size when every field fits in it.
+    pub fn write(&self, out: &mut [u8; FULL_PACKET_SIZE]) -> usize {
+        out.fill(0);
+        let header = self.header();
+        LittleEndian::write_u16(out, header.0);
+        if let Some(position) = self.base_position {
+            LittleEndian::write_f32_into(&position, &mut out[BASE_POSITION..BASE_POSITION + 12]);
+        }
+        write_halves(out, BASE_ROTATION, self.base_rotation);
+        write_halves(out, HEAD_POSITION, self.head_position);
+        write_halves(out, HEAD_ROTATION, self.head_rotation);
+        write_halves(out, LEFT_HAND_POSITION, self.left_hand_position);
+        write_halves(out, LEFT_HAND_ROTATION, self.left_hand_rotation);
+        write_halves(out, RIGHT_HAND_POSITION, self.right_hand_position);
+        write_halves(out, RIGHT_HAND_ROTATION, self.right_hand_rotation);
+        write_halves(out, GAME_PIECE_POSITION, self.game_piece_position);
+        write_halves(out, GAME_PIECE_ROTATION, self.game_piece_rotation);
+        header.packet_size()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = [0u8; FULL_PACKET_SIZE];
+        let len = self.write(&mut out);
+        out[..len].to_vec()
+    }
+
+    fn is_finite(&self) -> bool {
+        let positions = [
+            self.base_position,
+            self.head_position,
+            self.left_hand_position,
+            self.right_hand_position,
+            self.game_piece_position,
+        ];
+        let rotations = [
+            self.base_rotation,
+            self.head_rotation,
+            self.left_hand_rotation,
+            self.right_hand_rotation,
+            self.game_piece_rotation,
+        ];
+        positions
+            .iter()
+            .flatten()
+            .flatten()
+            .chain(rotations.iter().flatten().flatten())
+            .all(|v| v.is_finite())
+    }
+}
+
+fn write_halves<const N: usize>(out: &mut [u8], offset: usize, values: Option<[f32; N]>) {
+    if let Some(values) = values {
+        for (i, value) in values.iter().enumerate() {
+            LittleEndian::write_u16(&mut out[offset + i * 2..], f32_to_f16(*value));
+        }
+    }
+}
+
+// IEEE 754 half precision conversions, as getFloat16/setFloat16 of
+// @petamoriken/float16 on the client.
+pub fn f16_to_f32(half: u16) -> f32 {
+    let sign = ((half & 0x8000) as u32) << 16;
+    let exponent = ((half >> 10) & 0x1f) as u32;
+    let mantissa = (half & 0x3ff) as u32;
+    match exponent {
+        0 => {
+            let value = mantissa as f32 * f32::from_bits(0x3380_0000); // 2^-24
+            if sign != 0 {
+                -value
+            } else {
+                value
+            }
+        }
+        0x1f => f32::from_bits(sign | 0x7f80_0000 | (mantissa << 13)),
+        _ => f32::from_bits(sign | ((exponent + 112) << 23) | (mantissa << 13)),
+    }
+}
+
+// rounds to the nearest half, ties to even
+pub fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+    if exponent == 0xff {
+        let nan = if mantissa != 0 { 0x200 } else { 0 };
+        return sign | 0x7c00 | nan;
+    }
+    let half_exponent = exponent - 127 + 15;
+    if half_exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+    if half_exponent <= 0 {
+        if half_exponent < -10 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - half_exponent) as u32;
+        let half = (mantissa >> shift) as u16;
+        return sign | round(half, mantissa, shift);
+    }
+    let half = ((half_exponent as u16) << 10) | (mantissa >> 13) as u16;
+    // a carry out of the mantissa bumps the exponent, up to infinity
+    sign | round(half, mantissa, 13)
+}
+
+fn round(half: u16, mantissa: u32, shift: u32) -> u16 {
+    let rest = mantissa & ((1 << shift) - 1);
+    let halfway = 1 << (shift - 1);
+    if rest > halfway || (rest == halfway && half & 1 == 1) {
+        half + 1
+    } else {
+        half
+    }
+}
@@ -0,0 +1,267 @@
+use std::{collections::HashMap, sync::Arc};
+
+use mediasoup::{
+    data_producer::DataProducer,
+    prelude::{Consumer, ConsumerId, DataConsumer, MediaKind, Transport, WebRtcTransport},
+    producer::{Producer, ProducerId},
+    router::RouterId,
+};
+use uuid::Uuid;
+
+use crate::{
+    handlers::error::HandlerError,
+    models::{
+        movement::{MovementFrame, MovementPacket},
+        sfu::Loads,
+    },
+    utils::utils::Mut,
+};
+
+// Everything this node holds for one peer: its WebRTC transport, what it
+// produces, what it consumes and the relays of its producers to egress.
+// mediasoup closes each of them once its last handle is dropped, so the peer
+// going away releases it all.
+#[derive(Debug)]
+pub struct Peer {
+    pub id: Uuid,
+    pub transport: Option<WebRtcTransport>,
+    // audio and video, produced on the transport, relayed or plain ingress
+    pub producers: Vec<Producer>,
+    pub consumers: Vec<Consumer>,
+    // pipe consumers relaying the producers to egress
+    pub relay_consumers: Vec<Consumer>,
+    // event and movement producers handed to consumers, the validated or
+    // limited copies when the node taps the channel
+    pub events: Option<DataProducer>,
+    pub movement: Option<DataProducer>,
+    // data producers of the client, of a bot or relayed from ingress
+    pub data_producers: Vec<DataProducer>,
+    // data consumers of the peer and those relaying its channels to egress
+    pub data_consumers: Vec<DataConsumer>,
+    // direct consumers reading the data producers back on this node
+    pub taps: Vec<DataConsumer>,
+    // last movement merged over the partial packets, for late joiners
+    pub movement_frame: Option<MovementFrame>,
+    // last base position decoded from the movement
+    pub position: Option<[f32; 3]>,
+}
+
+impl Peer {
+    pub fn new(id: Uuid) -> Self {
+        Peer {
+            id,
+            transport: None,
+            producers: vec![],
+            consumers: vec![],
+            relay_consumers: vec![],
+            events: None,
+            movement: None,
+            data_producers: vec![],
+            data_consumers: vec![],
+            taps: vec![],
+            movement_frame: None,
+            position: None,
+        }
+    }
+    pub fn producer(&self, producer_id: ProducerId) -> Option<Producer> {
+        self.producers
+            .iter()
+            .find(|p| p.id() == producer_id)
+            .cloned()
+    }
+    pub fn producers_of(&self, kind: MediaKind) -> Vec<Producer> {
+        self.producers
+            .iter()
+            .filter(|p| p.kind() == kind)
+            .cloned()
+            .collect()
+    }
+    pub fn remove_producer(&mut self, producer_id: ProducerId) -> Option<Producer> {
+        let index = self.producers.iter().position(|p| p.id() == producer_id)?;
+        Some(self.producers.remove(index))
+    }
+    // whether the peer already consumes the producer
+    pub fn consumes(&self, producer_id: ProducerId) -> bool {
+        self.consumers
+            .iter()
+            .any(|c| c.producer_id() == producer_id)
+    }
+    pub fn update_frame(&mut self, packet: MovementPacket) {
+        self.movement_frame
+            .get_or_insert_with(MovementFrame::default)
+            .merge(packet);
+    }
+    // Close everything of the peer and give its transport back to `loads`.
+    pub fn close(self, loads: &mut Loads) {
+        if let Some(transport) = &self.transport {
+            release(transport, loads);
+        }
+    }
+}
+
+// Take a transport off the load of the worker it lives on.
+pub fn release(transport: &WebRtcTransport, loads: &mut Loads) {
+    let router = transport.router();
+    loads.remove(router.worker().id(), router.id());
+}
+
+// Give `item`, a handle created while awaiting, to the peer with `add`. When
+// the peer disconnected meanwhile it is dropped after the lock rather than
+// kept on a new entry, where it would hold the closed transport and its
+// router open.
+pub fn attach<T, R>(
+    peers: &Mut<Peers>,
+    peer_id: Uuid,
+    item: T,
+    add: impl FnOnce(&mut Peer, T) -> R,
+) -> Result<R, HandlerError> {
+    let left = peers.with(|p| match p.get_mut(peer_id) {
+        Some(peer) => Ok(add(peer, item)),
+        None => Err(item),
+    });
+    left.map_err(|item| {
+        drop(item);
+        HandlerError::NotFound(format!("peer {} disconnected", peer_id))
+    })
+}
+
+// Peers of this node by id. Removed handles are returned instead of dropped so
+// callers release the lock first: closing runs mediasoup handlers which may
+// need it again.
+#[derive(Debug)]
+pub struct Peers(HashMap<Uuid, Peer>);
+impl Peers {
+    pub fn new() -> Self {
+        Peers(HashMap::new())
+    }
+    pub fn get(&self, peer_id: Uuid) -> Option<&Peer> {
+        self.0.get(&peer_id)
+    }
+    pub fn get_mut(&mut self, peer_id: Uuid) -> Option<&mut Peer> {
+        self.0.get_mut(&peer_id)
+    }
+    // the peer, registered the first time
+    pub fn entry(&mut self, peer_id: Uuid) -> &mut Peer {
+        self.0.entry(peer_id).or_insert_with(|| Peer::new(peer_id))
+    }
+    pub fn remove(&mut self, peer_id: Uuid) -> Option<Peer> {
+        self.0.remove(&peer_id)
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &Peer> {
+        self.0.values()
+    }
+    pub fn transport(&self, peer_id: Uuid) -> Option<WebRtcTransport> {
+        self.get(peer_id).and_then(|peer| peer.transport.clone())
+    }
+    pub fn transports(&self) -> Vec<(Uuid, WebRtcTransport)> {
+        self.iter()
+            .filter_map(|peer| peer.transport.clone().map(|t| (peer.id, t)))
+            .collect()
+    }
+    // a producer of any peer with the peer producing it
+    pub fn producer(&self, producer_id: ProducerId) -> Option<(Uuid, Producer)> {
+        self.iter()
+            .find_map(|peer| peer.producer(producer_id).map(|p| (peer.id, p)))
+    }
+    // every producer of `kind` with the peer producing it
+    pub fn producers(&self, kind: MediaKind) -> Vec<(Uuid, Producer)> {
+        self.iter()
+            .flat_map(|peer| {
+                peer.producers_of(kind)
+                    .into_iter()
+                    .map(move |p| (peer.id, p))
+            })
+            .collect()
+    }
+    // a consumer some peer receives
+    pub fn consumer(&self, consumer_id: ConsumerId) -> Option<Consumer> {
+        self.iter()
+            .flat_map(|peer| peer.consumers.iter())
+            .find(|c| c.id() == consumer_id)
+            .cloned()
+    }
+    pub fn remove_producer(&mut self, producer_id: ProducerId) -> Option<Producer> {
+        self.0
+            .values_mut()
+            .find_map(|peer| peer.remove_producer(producer_id))
+    }
+    pub fn remove_consumer(&mut self, consumer_id: ConsumerId) -> Option<Consumer> {
+        for peer in self.0.values_mut() {
+            for consumers in [&mut peer.consumers, &mut peer.relay_consumers] {
+                if let Some(index) = consumers.iter().position(|c| c.id() == consumer_id) {
+                    return Some(consumers.remove(index));
+                }
+            }
+        }
+        None
+    }
+    // consumers of every peer, relays included, living on `router_id`
+    pub fn consumers_on(&self, router_id: RouterId) -> usize {
+        self.iter()
+            .flat_map(|peer| peer.consumers.iter().chain(peer.relay_consumers.iter()))
+            .filter(|c| c.transport().router().id() == router_id)
+            .count()
+    }
+    // producers living on one of the given routers, with the data producers
+    // handed to consumers
+    pub fn producers_on(&self, router_ids: &[RouterId]) -> (Vec<Producer>, Vec<DataProducer>) {
+        let on = |router_id: RouterId| router_ids.contains(&router_id);
+        let producers = self
+            .iter()
+            .flat_map(|peer| peer.producers.iter())
+            .filter(|p| on(p.transport().router().id()))
+            .cloned()
+            .collect();
+        let data_producers = self
+            .iter()
+            .flat_map(|peer| peer.events.iter().chain(peer.movement.iter()))
+            .filter(|p| on(p.transport().router().id()))
+            .cloned()
+            .collect();
+        (producers, data_producers)
+    }
+    // remove the peers whose transport lives on the given routers
+    pub fn remove_routers(&mut self, router_ids: &[RouterId]) -> Vec<Peer> {
+        let ids: Vec<Uuid> = self
+            .iter()
+            .filter(|peer| {
+                peer.transport
+                    .as_ref()
+                    .map(|t| router_ids.contains(&t.router().id()))
+                    .unwrap_or(false)
+            })
+            .map(|peer| peer.id)
+            .collect();
+        ids.into_iter().filter_map(|id| self.remove(id)).collect()
+    }
+}
+
+// Forget `producer` once mediasoup closed it, with its transport or the
+// producer it pipes. on_close runs wherever the last handle is dropped, the
+// registry is only locked from a task.
+pub fn forget_producer(peers: &Arc<Mut<Peers>>, producer: &Producer) {
+    let peers = peers.clone();
+    let producer_id = producer.id();
+    let handle = tokio::runtime::Handle::current();
+    producer
+        .on_close(move || {
+            handle.spawn(async move {
+                let _closed = peers.with(|p| p.remove_producer(producer_id));
+            });
+        })
+        .detach();
+}
+
+// Forget `consumer` once mediasoup closed it, e.g. with its producer.
+pub fn forget_consumer(peers: &Arc<Mut<Peers>>, consumer: &Consumer) {
+    let peers = peers.clone();
+    let consumer_id = consumer.id();
+    let handle = tokio::runtime::Handle::current();
+    consumer
+        .on_close(move || {
+            handle.spawn(async move {
+                let _closed = peers.with(|p| p.remove_consumer(consumer_id));
+            });
+        })
+        .detach();
+}
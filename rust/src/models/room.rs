@@ -0,0 +1,136 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
+
+use mediasoup::{
+    router::{Router, RouterId},
+    transport::TransportId,
+};
+use uuid::Uuid;
+
+// A room on this node: its router on each worker, the peers with a WebRTC
+// transport on them and the pipe transports relaying it to other nodes.
+#[derive(Debug, Clone)]
+pub struct Room {
+    pub name: String,
+    pub routers: Vec<Router>,
+    pub peers: HashSet<Uuid>,
+    pub relays: HashSet<TransportId>,
+    pub created_at: Instant,
+    // since when the room has no peer, None while it has some
+    pub idle_since: Option<Instant>,
+}
+
+impl Room {
+    pub fn new(name: String) -> Self {
+        let now = Instant::now();
+        Room {
+            name,
+            routers: vec![],
+            peers: HashSet::new(),
+            relays: HashSet::new(),
+            created_at: now,
+            idle_since: Some(now),
+        }
+    }
+    pub fn router_ids(&self) -> Vec<RouterId> {
+        self.routers.iter().map(|r| r.id()).collect()
+    }
+    // whether the room had no peer for at least `timeout`
+    pub fn idle_for(&self, timeout: Duration, now: Instant) -> bool {
+        self.idle_since
+            .map(|since| now.duration_since(since) >= timeout)
+            .unwrap_or(false)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Rooms(pub HashMap<String, Room>);
+
+impl Rooms {
+    pub fn new() -> Self {
+        Rooms(HashMap::new())
+    }
+    // add a router to the room, creating the room with its first router
+    pub fn create(&mut self, room: String, router: Router) {
+        self.0
+            .entry(room.clone())
+            .or_insert_with(|| Room::new(room))
+            .routers
+            .push(router)
+    }
+    pub fn get(&self, room: &str) -> Option<&Room> {
+        self.0.get(room)
+    }
+    pub fn routers(&self, room: &str) -> Option<Vec<Router>> {
+        self.0.get(room).map(|r| r.routers.clone())
+    }
+    pub fn remove(&mut self, room: &str) -> Option<Room> {
+        self.0.remove(room)
+    }
+    // the peer has a transport in the room, which is no longer idle
+    pub fn join(&mut self, room: &str, peer_id: Uuid) {
+        if let Some(room) = self.0.get_mut(room) {
+            room.peers.insert(peer_id);
+            room.idle_since = None;
+        }
+    }
+    // whether the peer may have a transport in the room without going over
+    // `max` peers, peers already in it always may
+    pub fn admits(&self, room: &str, peer_id: Uuid, max: u32) -> bool {
+        match self.0.get(room) {
+            Some(room) => room.peers.contains(&peer_id) || room.peers.len() < max as usize,
+            None => true,
+        }
+    }
+    // the peer closed its transport, the room is idle once it was the last one
+    pub fn leave(&mut self, peer_id: Uuid) {
+        for room in self.0.values_mut() {
+            if room.peers.remove(&peer_id) && room.peers.is_empty() {
+                room.idle_since = Some(Instant::now());
+            }
+        }
+    }
+    pub fn add_relay(&mut self, room: &str, transport_id: TransportId) {
+        if let Some(room) = self.0.get_mut(room) {
+            room.relays.insert(transport_id);
+        }
+    }
+    // The room is in use through something that is not a peer, a plain
+    // transport, a bot or a recording: its idle time starts over.
+    pub fn touch(&mut self, room: &str) {
+        if let Some(room) = self.0.get_mut(room) {
+            if room.idle_since.is_some() {
+                room.idle_since = Some(Instant::now());
+            }
+        }
+    }
+    // rooms without peers for at least `timeout`
+    pub fn idle(&self, timeout: Duration) -> Vec<String> {
+        let now = Instant::now();
+        self.0
+            .values()
+            .filter(|room| room.idle_for(timeout, now))
+            .map(|room| room.name.clone())
+            .collect()
+    }
+    // Drop the given routers from every room. Returns the rooms that lost one
+    // and, among them, those left without any router: they stay registered so
+    // close_room can still release what the node holds for them.
+    pub fn remove_routers(&mut self, router_ids: &[RouterId]) -> (Vec<String>, Vec<String>) {
+        let mut rooms = vec![];
+        let mut empty = vec![];
+        for (name, room) in self.0.iter_mut() {
+            let count = room.routers.len();
+            room.routers.retain(|r| !router_ids.contains(&r.id()));
+            if room.routers.len() != count {
+                rooms.push(name.clone());
+                if room.routers.is_empty() {
+                    empty.push(name.clone());
+                }
+            }
+        }
+        (rooms, empty)
+    }
+}
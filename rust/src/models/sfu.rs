@@ -1,20 +1,26 @@
 #![allow(non_snake_case, non_camel_case_types)]
 use mediasoup::{
-    data_producer::{DataProducer, DataProducerId},
-    prelude::{ConsumerId, DataConsumerId, PipeTransport, WebRtcTransport},
+    active_speaker_observer::ActiveSpeakerObserver,
+    audio_level_observer::AudioLevelObserver,
+    prelude::{
+        Consumer, ConsumerId, DirectTransport, MediaKind, PipeTransport, PlainTransport, Transport,
+    },
     producer::{Producer, ProducerId},
-    router::{Router, RouterId},
+    router::{PipeProducerToRouterPair, Router, RouterId},
     srtp_parameters::SrtpParameters,
     transport::TransportId,
     webrtc_server::WebRtcServer,
     worker::{Worker, WorkerId},
 };
-use os_id::ProcessId;
-use std::{collections::HashMap, net::IpAddr, sync::Arc, vec};
-use tokio::sync::Mutex;
+use std::{collections::HashMap, io, net::IpAddr, path::PathBuf, sync::Arc, time::Instant, vec};
+use tokio::{sync::oneshot, task::JoinHandle};
 use uuid::Uuid;
 
-use crate::utils::codec::ResponseMessage;
+use crate::{
+    config::config::LoadBalancing,
+    handlers::{bot_peer::BotPeer, cpu_load::CLOCK_TICKS_PER_SEC},
+    utils::{codec::ResponseMessage, utils::Mut},
+};
 #[derive(Debug)]
 pub struct Routers(pub HashMap<RouterId, Router>); // Array of routers
 
@@ -39,48 +45,6 @@ impl Routers {
     }
 }
 #[derive(Debug, Clone)]
-pub struct RoomRouters(pub HashMap<String, Vec<Router>>);
-
-impl RoomRouters {
-    pub fn new() -> Self {
-        RoomRouters(HashMap::new())
-    }
-
-    pub fn create(&mut self, room: String, router: Router) {
-        self.0.entry(room).or_default().push(router)
-    }
-
-    pub fn get(&self, room: String) -> std::option::Option<Vec<Router>> {
-        match self.0.get(&room) {
-            Some(router) => Some(router.clone()),
-            None => None,
-        }
-    }
-
-    pub fn remove(&mut self, room_name: String) {
-        self.0.remove(&room_name);
-    }
-}
-#[derive(Clone, Debug)]
-pub struct Transports(HashMap<Uuid, WebRtcTransport>); // Array of transports
-impl Transports {
-    pub fn new() -> Self {
-        Transports(HashMap::new())
-    }
-    pub fn create(&mut self, id: Uuid, transport: WebRtcTransport) {
-        self.0.insert(id, transport);
-    }
-    pub fn get(&self, id: Uuid) -> Option<WebRtcTransport> {
-        match self.0.get(&id) {
-            Some(t) => Some(t.clone()),
-            None => None,
-        }
-    }
-    pub fn remove(&mut self, id: Uuid) {
-        self.0.remove(&id);
-    }
-}
-#[derive(Debug, Clone)]
 pub struct Relays(pub Vec<PipeTransportsref>); // Array of relays
 
 impl Relays {
@@ -166,6 +130,9 @@ impl PipeTransports {
     pub fn new() -> Self {
         PipeTransports(vec![])
     }
+    pub fn count(&self) -> usize {
+        self.0.len()
+    }
     pub fn create(
         &mut self,
         room_name: String,
@@ -199,8 +166,19 @@ impl PipeTransports {
     }
 
     pub fn update_connected_state(&mut self, transport_id: TransportId) {
-        self.get(transport_id)
-            .and_then(|mut t| Some(t.is_connected = true));
+        if let Some(t) = self
+            .0
+            .iter_mut()
+            .find(|data| data.transport_id == transport_id)
+        {
+            t.is_connected = true;
+        }
+    }
+
+    // number of pipe transports as (connected, not connected)
+    pub fn count_by_state(&self) -> (usize, usize) {
+        let connected = self.0.iter().filter(|t| t.is_connected).count();
+        (connected, self.0.len() - connected)
     }
 
     pub fn delete(&mut self, transport_id: TransportId) {
@@ -341,304 +319,199 @@ impl Transport2Router {
     }
 }
 
-#[derive(Clone, Debug)]
-pub struct Endpoints(HashMap<TransportId, Uuid>);
-
-impl Endpoints {
-    pub fn new() -> Self {
-        Endpoints(HashMap::new())
-    }
-
-    pub fn create(&mut self, transport_id: TransportId, peerId: Uuid) {
-        self.0.insert(transport_id, peerId);
-    }
-
-    pub fn get(&self, transport_id: TransportId) -> std::option::Option<Uuid> {
-        match self.0.get(&transport_id) {
-            Some(r) => Some(r.clone()),
-            None => None,
-        }
-    }
-
-    pub fn delete(&mut self, transport_id: TransportId) {
-        self.0.remove(&transport_id);
-    }
-}
+// Audio level and active speaker observers of each room. Every audio producer
+// of a room is observed on the router that received the room's first one.
 #[derive(Debug, Clone)]
-pub struct AudioProducers(HashMap<Uuid, Vec<Producer>>);
-impl AudioProducers {
+pub struct AudioObservers(HashMap<String, RoomAudioObserver>);
+impl AudioObservers {
     pub fn new() -> Self {
-        AudioProducers(HashMap::new())
+        AudioObservers(HashMap::new())
     }
-    pub fn create(&mut self, peerid: Uuid, producer: Producer) {
-        self.0.entry(peerid).or_default().push(producer)
+    pub fn create(&mut self, room: String, observer: RoomAudioObserver) {
+        self.0.insert(room, observer);
     }
-    pub fn get(&self, peerid: Uuid) -> Option<Vec<Producer>> {
-        match self.0.get(&peerid) {
-            Some(router) => Some(router.clone()),
-            None => None,
-        }
-    }
-    pub fn get_internal<'a>(&'a self, peerid: &Uuid) -> Option<&'a [Producer]> {
-        match self.0.get(&peerid) {
-            Some(router) => Some(router),
-            None => None,
-        }
+    pub fn get(&self, room: &str) -> Option<RoomAudioObserver> {
+        self.0.get(room).cloned()
     }
-    pub fn get_producer<'a>(
-        &'a self,
-        peer_id: Uuid,
-        producer_id: ProducerId,
-    ) -> Option<&'a Producer> {
-        self.get_internal(&peer_id)
-            .and_then(|p| p.iter().find(|p| p.id() == producer_id))
-    }
-    pub fn _remove_producer(&mut self, peer_id: Uuid, producer_id: ProducerId) {
-        match self.0.get_mut(&peer_id) {
-            Some(p) => p.retain(|producer: &Producer| producer.id() != producer_id),
-            None => println!("cannot find the producer or don't have a producer to remove"),
-        }
+    pub fn remove(&mut self, room: &str) {
+        self.0.remove(room);
     }
-    pub fn remove(&mut self, peerid: Uuid) {
-        self.0.remove(&peerid);
+    // drop the observers living on the given routers
+    pub fn remove_routers(&mut self, router_ids: &[RouterId]) {
+        self.0
+            .retain(|_, observer| !router_ids.contains(&observer.router.id()));
     }
 }
 #[derive(Debug, Clone)]
-pub struct VideoProducers(HashMap<Uuid, Vec<Producer>>);
-impl VideoProducers {
-    pub fn new() -> Self {
-        VideoProducers(HashMap::new())
-    }
-    pub fn create(&mut self, peerid: Uuid, producer: Producer) {
-        self.0.entry(peerid).or_default().push(producer)
-    }
-    pub fn get(&self, peerid: Uuid) -> Option<Vec<Producer>> {
-        match self.0.get(&peerid) {
-            Some(p) => Some(p.clone()),
-            None => None,
-        }
-    }
-    pub fn _add(&mut self, peerid: Uuid, producer: Producer) {
-        match self.get(peerid) {
-            Some(mut p) => p.push(producer),
-            None => (),
-        }
-    }
-    pub fn remove_producer(&mut self, peer_id: Uuid, producer_id: ProducerId) {
-        match self.0.get_mut(&peer_id) {
-            Some(p) => p.retain(|producer: &Producer| producer.id() != producer_id),
-
-            None => println!("cannot find the producer or don't have a producer"),
-        }
-    }
-    pub fn remove(&mut self, peerid: Uuid) {
-        self.0.remove(&peerid);
-    }
-}
-
-#[derive(Clone, Debug)]
-pub struct EventProducers(HashMap<Uuid, DataProducer>);
-impl EventProducers {
+pub struct RoomAudioObserver {
+    pub router: Router,
+    pub audio_level: AudioLevelObserver,
+    pub active_speaker: ActiveSpeakerObserver,
+    // peer of every observed producer, to name it in audioLevels/activeSpeaker
+    pub peers: Arc<Mut<HashMap<ProducerId, Uuid>>>,
+    // producers piped into the observer router, kept until they close
+    pub pipes: Arc<Mut<HashMap<ProducerId, PipeProducerToRouterPair>>>,
+}
+// Plain RTP transports of local processes, each either consuming one producer
+// of a room (egress) or producing into it (ingress).
+#[derive(Debug)]
+pub struct PlainTransports(HashMap<TransportId, PlainTransportData>);
+impl PlainTransports {
     pub fn new() -> Self {
-        EventProducers(HashMap::new())
+        PlainTransports(HashMap::new())
     }
-    pub fn create(&mut self, peer_id: Uuid, producer: DataProducer) {
-        self.0.insert(peer_id, producer);
+    pub fn count(&self) -> usize {
+        self.0.len()
     }
-    pub fn get(&self, peerid: Uuid) -> Option<DataProducer> {
-        match self.0.get(&peerid) {
-            Some(p) => Some(p.clone()),
-            None => None,
-        }
+    pub fn create(&mut self, data: PlainTransportData) {
+        self.0.insert(data.transport.id(), data);
     }
-    pub fn _delete(&mut self, peer_id: Uuid) -> Result<(), String> {
-        match self.0.get(&peer_id) {
-            Some(_) => {
-                self.0.remove(&peer_id);
-                return Ok(());
-            }
-            None => return Err("cannot find peer".to_string()),
-        }
+    pub fn remove(&mut self, transport_id: TransportId) -> Option<PlainTransportData> {
+        self.0.remove(&transport_id)
     }
-    pub fn remove(&mut self, peer_id: Uuid) {
-        self.0.remove(&peer_id);
+    // the consumer of a plain egress
+    pub fn consumer(&self, consumer_id: ConsumerId) -> Option<Consumer> {
+        self.0
+            .values()
+            .filter_map(|data| data.consumer.as_ref())
+            .find(|c| c.id() == consumer_id)
+            .cloned()
+    }
+    pub fn in_room(&self, room: &str) -> bool {
+        self.0.values().any(|data| data.room == room)
+    }
+    pub fn remove_room(&mut self, room: &str) -> Vec<PlainTransportData> {
+        let ids: Vec<_> = self
+            .0
+            .iter()
+            .filter(|(_, data)| data.room == room)
+            .map(|(id, _)| *id)
+            .collect();
+        ids.iter().filter_map(|id| self.0.remove(id)).collect()
+    }
+    pub fn remove_routers(&mut self, router_ids: &[RouterId]) {
+        self.0
+            .retain(|_, data| !router_ids.contains(&data.transport.router().id()));
     }
 }
-#[derive(Clone, Debug)]
-pub struct MovementProducers(HashMap<Uuid, DataProducer>);
-impl MovementProducers {
+#[derive(Debug)]
+pub struct PlainTransportData {
+    pub room: String,
+    pub transport: PlainTransport,
+    // consumer of a plain egress
+    pub consumer: Option<Consumer>,
+    // producer of a plain ingress and the peer it is announced as
+    pub producer: Option<(Uuid, Producer)>,
+}
+// Peers played by this node, see handlers::bot_peer.
+#[derive(Debug)]
+pub struct BotPeers(HashMap<Uuid, BotPeer>);
+impl BotPeers {
     pub fn new() -> Self {
-        MovementProducers(HashMap::new())
+        BotPeers(HashMap::new())
     }
-    pub fn create(&mut self, peer_id: Uuid, producer: DataProducer) {
-        self.0.insert(peer_id, producer);
+    pub fn create(&mut self, bot: BotPeer) {
+        self.0.insert(bot.peer_id, bot);
     }
-    pub fn get(&self, peerid: Uuid) -> Option<DataProducer> {
-        match self.0.get(&peerid) {
-            Some(p) => Some(p.clone()),
-            None => None,
-        }
+    pub fn get(&self, peer_id: Uuid) -> Option<BotPeer> {
+        self.0.get(&peer_id).cloned()
     }
-    pub fn _delete(&mut self, peer_id: Uuid) -> Result<(), String> {
-        match self.0.get(&peer_id) {
-            Some(_) => {
-                self.0.remove(&peer_id);
-                return Ok(());
-            }
-            None => return Err("cannot find peer".to_string()),
-        }
+    pub fn remove(&mut self, peer_id: Uuid) -> Option<BotPeer> {
+        self.0.remove(&peer_id)
     }
-    pub fn remove(&mut self, peer_id: Uuid) {
-        self.0.remove(&peer_id);
+    pub fn in_room(&self, room: &str) -> bool {
+        self.0.values().any(|bot| bot.room == room)
     }
-}
-#[derive(Clone, Debug)]
-pub struct PeerConsumed(Vec<PeerConsumedData>);
-
-impl PeerConsumed {
-    pub fn new() -> Self {
-        PeerConsumed(vec![])
-    }
-    pub fn get(&self, peer_id: Uuid) -> Vec<PeerConsumedData> {
-        self.0
-            .clone()
-            .into_iter()
-            .filter(|producer| producer.peer_id == peer_id)
-            .collect()
+    pub fn remove_room(&mut self, room: &str) -> Vec<BotPeer> {
+        let ids: Vec<_> = self
+            .0
+            .iter()
+            .filter(|(_, bot)| bot.room == room)
+            .map(|(id, _)| *id)
+            .collect();
+        ids.iter().filter_map(|id| self.0.remove(id)).collect()
     }
-    pub fn find(&self, peer_id: Uuid, producer_id: ProducerId) -> Vec<PeerConsumedData> {
+    pub fn remove_routers(&mut self, router_ids: &[RouterId]) {
         self.0
-            .clone()
-            .into_iter()
-            .filter(|producer| producer.peer_id == peer_id && producer.produce_id == producer_id)
-            .collect()
-    }
-    pub fn create(&mut self, peer: PeerConsumedData) {
-        self.0.push(peer)
+            .retain(|_, bot| !router_ids.contains(&bot.transport.router().id()));
     }
-
-    pub fn remove(&mut self, peer_id: Uuid) {
-        self.0.retain(|x| x.peer_id != peer_id);
-    }
-}
-
-#[derive(Clone, Debug)]
-pub struct PeerConsumedData {
-    pub peer_id: Uuid,
-    pub produce_id: ProducerId,
-    pub consumer_id: ConsumerId,
 }
-#[derive(Clone, Debug)]
-pub struct PeerMovementConsumed(Vec<PeerMovementConsumedData>);
-
-impl PeerMovementConsumed {
+// Running recording of each room, see handlers::recording.
+#[derive(Debug)]
+pub struct Recordings(HashMap<String, Recording>);
+impl Recordings {
     pub fn new() -> Self {
-        PeerMovementConsumed(vec![])
+        Recordings(HashMap::new())
     }
-    pub fn get(&self, peer_id: Uuid) -> Vec<PeerMovementConsumedData> {
-        self.0
-            .clone()
-            .into_iter()
-            .filter(|producer| producer.peer_id == peer_id)
-            .collect()
+    pub fn contains(&self, room: &str) -> bool {
+        self.0.contains_key(room)
     }
-    pub fn _find(
-        &self,
-        peer_id: Uuid,
-        producer_id: DataProducerId,
-    ) -> Vec<PeerMovementConsumedData> {
-        self.0
-            .clone()
-            .into_iter()
-            .filter(|producer| producer.produce_id == producer_id && producer.peer_id == peer_id)
-            .collect()
+    pub fn create(&mut self, room: String, recording: Recording) {
+        self.0.insert(room, recording);
     }
-    pub fn create(&mut self, peer_consume_data: PeerMovementConsumedData) {
-        self.0.push(peer_consume_data)
+    pub fn remove(&mut self, room: &str) -> Option<Recording> {
+        self.0.remove(room)
     }
-    pub fn remove(&mut self, peer_id: Uuid) {
-        self.0.retain(|x| x.peer_id != peer_id);
+    // drop the tracks recorded on the given routers, which ends their files
+    pub fn remove_routers(&mut self, router_ids: &[RouterId]) {
+        for recording in self.0.values_mut() {
+            recording
+                .tracks
+                .retain(|track| !router_ids.contains(&track.transport.router().id()));
+        }
     }
 }
-
-#[derive(Clone, Debug)]
-pub struct PeerMovementConsumedData {
-    pub peer_id: Uuid,
-    pub produce_id: DataProducerId,
-    pub consumer_id: DataConsumerId,
-}
-
-#[derive(Clone, Debug)]
-pub struct PeerAudioConsumed(Vec<PeerAudioConsumedData>);
-
-impl PeerAudioConsumed {
-    pub fn new() -> Self {
-        PeerAudioConsumed(vec![])
-    }
-    pub fn get(&self, peer_id: Uuid) -> Vec<PeerAudioConsumedData> {
-        self.0
-            .clone()
-            .into_iter()
-            .filter(|producer| producer.peer_id == peer_id)
-            .collect()
-    }
-    pub fn find(&self, peer_id: Uuid, producer_id: ProducerId) -> Vec<PeerAudioConsumedData> {
-        self.0
-            .clone()
-            .into_iter()
-            .filter(|producer| producer.peer_id == peer_id && producer.produce_id == producer_id)
-            .collect()
-    }
-    pub fn create(&mut self, peer: PeerAudioConsumedData) {
-        self.0.push(peer)
-    }
-
-    pub fn remove(&mut self, peer_id: Uuid) {
-        self.0.retain(|x| x.peer_id != peer_id);
-    }
+#[derive(Debug)]
+pub struct Recording {
+    pub id: Uuid,
+    pub tracks: Vec<RecordingTrack>,
 }
-
-#[derive(Clone, Debug)]
-pub struct PeerAudioConsumedData {
+// One recorded producer, consumed on a plain transport of its router which
+// sends RTP to the writer task. Dropping `stop` also ends the writer.
+#[derive(Debug)]
+pub struct RecordingTrack {
     pub peer_id: Uuid,
-    pub produce_id: ProducerId,
-    pub consumer_id: ConsumerId,
+    pub kind: MediaKind,
+    pub path: PathBuf,
+    pub transport: PlainTransport,
+    pub consumer: Consumer,
+    pub stop: oneshot::Sender<()>,
+    pub writer: JoinHandle<io::Result<u64>>,
 }
-#[derive(Clone, Debug)]
-pub struct PeerDataConsumed(Vec<PeerDataConsumedData>);
 
-impl PeerDataConsumed {
+// Direct transports reading the data producers of this node back, one per
+// router. The producers sending the validated or rate limited copies live on
+// them too, the tapping consumers belong to their peer.
+#[derive(Debug)]
+pub struct DataTaps {
+    transports: HashMap<RouterId, DirectTransport>,
+}
+impl DataTaps {
     pub fn new() -> Self {
-        PeerDataConsumed(vec![])
+        DataTaps {
+            transports: HashMap::new(),
+        }
     }
-    pub fn get(&self, peer_id: Uuid) -> Vec<PeerDataConsumedData> {
-        self.0
-            .clone()
-            .into_iter()
-            .filter(|producer| producer.peer_id == peer_id)
-            .collect()
+    pub fn get_transport(&self, router_id: RouterId) -> Option<DirectTransport> {
+        self.transports.get(&router_id).cloned()
     }
-    pub fn _find(&self, peer_id: Uuid, producer_id: DataProducerId) -> Vec<PeerDataConsumedData> {
-        self.0
+    // keep the transport already created for the router if there is one
+    pub fn create_transport(
+        &mut self,
+        router_id: RouterId,
+        transport: DirectTransport,
+    ) -> DirectTransport {
+        self.transports
+            .entry(router_id)
+            .or_insert(transport)
             .clone()
-            .into_iter()
-            .filter(|producer| producer.produce_id == producer_id && producer.peer_id == peer_id)
-            .collect()
-    }
-    pub fn create(&mut self, peer_consume_data: PeerDataConsumedData) {
-        self.0.push(peer_consume_data)
     }
-    pub fn remove(&mut self, peer_id: Uuid) {
-        self.0.retain(|x| x.peer_id != peer_id);
+    // drop the transports living on the given routers
+    pub fn remove_routers(&mut self, router_ids: &[RouterId]) {
+        self.transports
+            .retain(|router_id, _| !router_ids.contains(router_id));
     }
 }
-
-#[derive(Clone, Debug)]
-pub struct PeerDataConsumedData {
-    pub peer_id: Uuid,
-    pub produce_id: DataProducerId,
-    pub consumer_id: DataConsumerId,
-}
 #[derive(Clone, Debug)]
 pub struct Routers2Worker(pub HashMap<RouterId, WorkerId>); // Associates routers to their underyling cpu worker
 impl Routers2Worker {
@@ -662,6 +535,19 @@ impl Routers2Worker {
             None => println!("cannot find or don't have a router: {:?}", router_id),
         }
     }
+    pub fn get_by_worker(&self, worker_id: WorkerId) -> Vec<RouterId> {
+        self.0
+            .iter()
+            .filter(|(_, w)| **w == worker_id)
+            .map(|(r, _)| *r)
+            .collect()
+    }
+}
+// worker reported dead by mediasoup, port is the one its webrtc server listened on
+#[derive(Clone, Debug)]
+pub struct DeadWorker {
+    pub worker_id: WorkerId,
+    pub port: u16,
 }
 #[derive(Clone, Debug)]
 pub struct Workers(pub Vec<Worker>);
@@ -686,6 +572,9 @@ impl Workers {
         self.0.clear();
         return Ok(());
     }
+    pub fn remove(&mut self, worker_id: WorkerId) {
+        self.0.retain(|w| w.id() != worker_id);
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -704,144 +593,131 @@ impl WebrtcServers {
             None => None,
         }
     }
+    pub fn remove(&mut self, worker_id: WorkerId) {
+        self.0.remove(&worker_id);
+    }
 }
 #[derive(Clone, Debug)]
-pub struct Loads(pub HashMap<WorkerId, LoadData>);
+pub struct Loads {
+    pub workers: HashMap<WorkerId, LoadData>,
+    pub strategy: LoadBalancing,
+    // next router to hand out when balancing round-robin
+    next: usize,
+}
 impl Loads {
-    pub fn new() -> Self {
-        Self(HashMap::new())
+    pub fn new(strategy: LoadBalancing) -> Self {
+        Self {
+            workers: HashMap::new(),
+            strategy,
+            next: 0,
+        }
     }
     pub fn _get(&self, worker_id: WorkerId) -> Option<LoadData> {
-        match self.0.get(&worker_id) {
+        match self.workers.get(&worker_id) {
             Some(w) => Some(w.clone()),
             None => None,
         }
     }
+    // remember the os thread a worker runs on so its cpu usage can be sampled
+    pub fn register(&mut self, worker_id: WorkerId, thread_id: Option<i32>) {
+        self.workers.entry(worker_id).or_default().thread_id = thread_id;
+    }
     pub fn add(&mut self, worker_id: WorkerId, router_id: RouterId) {
-        match self.0.get_mut(&worker_id) {
-            Some(w) => {
-                w.router_id = router_id;
-                w.loads += 1;
-            }
-            None => {
-                self.0.insert(
-                    worker_id,
-                    LoadData {
-                        router_id,
-                        loads: 1,
-                    },
-                );
-            }
-        }
+        let w = self.workers.entry(worker_id).or_default();
+        w.router_id = Some(router_id);
+        w.transports += 1;
     }
     pub fn remove(&mut self, worker_id: WorkerId, router_id: RouterId) {
-        match self.0.get_mut(&worker_id) {
+        match self.workers.get_mut(&worker_id) {
             Some(w) => {
-                w.router_id = router_id;
-                if w.loads > 0 {
-                    w.loads -= 1;
+                w.router_id = Some(router_id);
+                if w.transports > 0 {
+                    w.transports -= 1;
                 }
             }
             None => (),
         }
     }
-}
-#[derive(Clone, Debug)]
-pub struct LoadData {
-    pub router_id: RouterId,
-    pub loads: u32,
-}
-
-#[derive(Clone, Debug)]
-pub struct WorkerLoads(pub HashMap<WorkerId, WorkerLoad>);
-
-impl WorkerLoads {
-    pub(crate) fn new() -> Self {
-        Self(HashMap::new())
-    }
-    pub fn _create(&mut self, worker_id: WorkerId, worker_load: WorkerLoad) {
-        self.0.insert(worker_id, worker_load);
-    }
-    pub fn _get(&self, worker_id: WorkerId) -> Option<WorkerLoad> {
-        match self.0.get(&worker_id) {
-            Some(r) => Some(r.clone()),
-            None => None,
-        }
-    }
-    pub fn _add(&mut self, worker_id: WorkerId, worker_load: f64) {
-        match self._get(worker_id) {
-            Some(mut w) => {
-                w.cpu += worker_load;
+    pub fn delete(&mut self, worker_id: WorkerId) {
+        self.workers.remove(&worker_id);
+    }
+    // store a new sample, cpu_ticks is the total cpu time the worker thread used so far
+    pub fn sample(&mut self, worker_id: WorkerId, cpu_ticks: Option<u64>, consumers: u32) {
+        let w = self.workers.entry(worker_id).or_default();
+        let now = Instant::now();
+        if let (Some(ticks), Some(last_ticks), Some(last)) = (cpu_ticks, w.cpu_ticks, w.sampled_at)
+        {
+            let elapsed = now.duration_since(last).as_secs_f32();
+            if elapsed > 0.0 {
+                let used = ticks.saturating_sub(last_ticks) as f32 / CLOCK_TICKS_PER_SEC;
+                w.cpu = used / elapsed * 100.0;
             }
-            None => (),
         }
-    }
-    pub fn _update(&mut self, worker_id: WorkerId, worker_load: WorkerLoad) {
-        self.0.insert(worker_id, worker_load);
-    }
-    pub fn _delete(&mut self, worker_id: WorkerId) -> Result<(), String> {
-        match self.0.get(&worker_id) {
-            Some(_) => {
-                self.0.remove(&worker_id);
-                return Ok(());
-            }
-            None => return Err("cannot find worker".to_string()),
+        w.cpu_ticks = cpu_ticks;
+        w.sampled_at = Some(now);
+        w.consumers = consumers;
+    }
+    // pick one of the given routers according to the configured strategy,
+    // routers are paired with the worker they live on
+    pub fn select(&mut self, routers: &[(RouterId, Option<WorkerId>)]) -> Option<RouterId> {
+        self.pick(routers)
+    }
+    // pick the worker a new router goes to, by the same strategy
+    pub fn select_worker(&mut self, workers: &[WorkerId]) -> Option<WorkerId> {
+        let workers: Vec<(WorkerId, Option<WorkerId>)> =
+            workers.iter().map(|w| (*w, Some(*w))).collect();
+        self.pick(&workers)
+    }
+    fn pick<T: Copy>(&mut self, routers: &[(T, Option<WorkerId>)]) -> Option<T> {
+        if routers.is_empty() {
+            return None;
         }
+        let load = |worker: &Option<WorkerId>| {
+            worker
+                .and_then(|w| self.workers.get(&w))
+                .cloned()
+                .unwrap_or_default()
+        };
+        let selected = match self.strategy {
+            LoadBalancing::RoundRobin => {
+                let i = self.next % routers.len();
+                self.next = self.next.wrapping_add(1);
+                routers[i].0
+            }
+            LoadBalancing::LeastTransports => {
+                routers
+                    .iter()
+                    .min_by_key(|(_, w)| {
+                        let l = load(w);
+                        (l.transports, l.consumers, l.cpu.round() as u32)
+                    })?
+                    .0
+            }
+            // cpu is compared in whole percents so workers sampled as equally
+            // busy are split by how many transports and consumers they carry
+            LoadBalancing::LeastCpu => {
+                routers
+                    .iter()
+                    .min_by_key(|(_, w)| {
+                        let l = load(w);
+                        (l.cpu.round() as u32, l.transports + l.consumers)
+                    })?
+                    .0
+            }
+        };
+        Some(selected)
     }
 }
-
-#[derive(Clone, Debug)]
-pub struct WorkerLoad {
-    pub worker_id: WorkerId,
-    pub pid: u32,
-    pub thread_id: u32,
-    pub wall: u128,
-    pub cpu: f64,
-}
-
-#[derive(Clone, Debug)]
-pub struct TempWorker(pub Vec<TempLoad>);
-impl TempWorker {
-    pub fn new() -> Self {
-        Self(vec![])
-    }
-    pub fn _insert(&mut self, temp_load: TempLoad) {
-        self.0.push(temp_load);
-    }
-    pub fn _delete(&mut self) -> Result<(), String> {
-        self.0.clear();
-        return Ok(());
-    }
-}
-#[derive(Clone, Debug)]
-pub struct TempLoader {
-    pub inner: Arc<Mutex<TempLoad>>,
-}
-#[derive(Clone, Debug)]
-pub struct TempLoad {
-    pub pid: ProcessId,
-    pub thread_id: u32,
-}
-
-#[derive(Clone, Debug)]
-pub struct RouterIndex(pub usize);
-
-impl RouterIndex {
-    pub fn new() -> Self {
-        Self(0)
-    }
-    pub fn _add(&mut self) {
-        self.0 += 1;
-    }
-    // pub fn add(&mut self) {
-    //     self.clone().0.fetch_add(1, Ordering::Relaxed);
-    // }
-    // pub fn reset(&mut self) {
-    //     self.clone().0.fetch_sub(1, Ordering::Relaxed);
-    // }
-    // pub fn get(&self) -> usize {
-    //     self.0.load(Ordering::Relaxed)
-    // }
+#[derive(Clone, Debug, Default)]
+pub struct LoadData {
+    pub router_id: Option<RouterId>,
+    pub transports: u32,
+    pub consumers: u32,
+    // percent of one core used by the worker thread since the previous sample
+    pub cpu: f32,
+    pub thread_id: Option<i32>,
+    pub cpu_ticks: Option<u64>,
+    pub sampled_at: Option<Instant>,
 }
 
 #[derive(Debug)]
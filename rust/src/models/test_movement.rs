@@ -0,0 +1,180 @@
+#[cfg(test)]
+mod tests {
+    use crate::models::movement::{
+        f16_to_f32, f32_to_f16, MovementError, MovementFrame, MovementPacket, PacketHeader,
+        FULL_PACKET_SIZE, SMALL_PACKET_SIZE,
+    };
+
+    // base position (1.5, -2.25, 100) and rotation (1, 0, 0.5, -0.5) as the
+    // client sends them, only the base fields set
+    fn small_sample() -> Vec<u8> {
+        let mut packet = vec![0u8; SMALL_PACKET_SIZE];
+        packet[0..2].copy_from_slice(&3u16.to_le_bytes());
+        packet[2..6].copy_from_slice(&1.5f32.to_le_bytes());
+        packet[6..10].copy_from_slice(&(-2.25f32).to_le_bytes());
+        packet[10..14].copy_from_slice(&100f32.to_le_bytes());
+        for (i, half) in [0x3c00u16, 0x0000, 0x3800, 0xb800].iter().enumerate() {
+            packet[14 + i * 2..16 + i * 2].copy_from_slice(&half.to_le_bytes());
+        }
+        packet
+    }
+
+    // base position and left hand with the fullPacket bit, as sent in VR
+    fn full_sample() -> Vec<u8> {
+        let mut packet = vec![0u8; FULL_PACKET_SIZE];
+        let header = PacketHeader::BASE_POSITION
+            | PacketHeader::LEFT_HAND_POSITION
+            | PacketHeader::LEFT_HAND_ROTATION
+            | PacketHeader::FULL_PACKET;
+        packet[0..2].copy_from_slice(&header.to_le_bytes());
+        packet[2..6].copy_from_slice(&4f32.to_le_bytes());
+        // left hand at (0.5, 1, 2), identity rotation
+        for (i, half) in [0x3800u16, 0x3c00, 0x4000, 0x3c00, 0, 0, 0]
+            .iter()
+            .enumerate()
+        {
+            packet[36 + i * 2..38 + i * 2].copy_from_slice(&half.to_le_bytes());
+        }
+        packet
+    }
+
+    // xorshift, enough to spread values over every field
+    struct Rng(u64);
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+        fn some<const N: usize>(&mut self, scale: f32) -> Option<[f32; N]> {
+            if self.next().is_multiple_of(2) {
+                return None;
+            }
+            let mut values = [0f32; N];
+            for value in values.iter_mut() {
+                let unit = (self.next() % 20001) as f32 / 10000.0 - 1.0;
+                // float16 fields only keep what survives the rounding
+                *value = f16_to_f32(f32_to_f16(unit * scale));
+            }
+            Some(values)
+        }
+    }
+
+    #[test]
+    fn test_parse_samples() {
+        let small = small_sample();
+        let packet = MovementPacket::parse(&small).unwrap();
+        assert_eq!(packet.base_position(), Some([1.5, -2.25, 100.0]));
+        assert_eq!(packet.base_rotation(), Some([1.0, 0.0, 0.5, -0.5]));
+        assert_eq!(packet.head_position(), None);
+        assert_eq!(packet.left_hand_position(), None);
+
+        let full = full_sample();
+        let packet = MovementPacket::parse(&full).unwrap();
+        assert_eq!(packet.base_position(), Some([4.0, 0.0, 0.0]));
+        assert_eq!(packet.base_rotation(), None);
+        assert_eq!(packet.left_hand_position(), Some([0.5, 1.0, 2.0]));
+        assert_eq!(packet.left_hand_rotation(), Some([1.0, 0.0, 0.0, 0.0]));
+        assert_eq!(packet.right_hand_position(), None);
+    }
+
+    #[test]
+    fn test_samples_round_trip() {
+        for sample in [small_sample(), full_sample()] {
+            let frame = MovementPacket::parse(&sample).unwrap().to_frame();
+            assert_eq!(frame.to_bytes(), sample);
+        }
+    }
+
+    #[test]
+    fn test_merge_keeps_fields_of_earlier_packets() {
+        let mut frame = MovementFrame::default();
+        frame.merge(MovementPacket::parse(&small_sample()).unwrap());
+        frame.merge(MovementPacket::parse(&full_sample()).unwrap());
+        assert_eq!(frame.base_position, Some([4.0, 0.0, 0.0]));
+        assert_eq!(frame.base_rotation, Some([1.0, 0.0, 0.5, -0.5]));
+        assert_eq!(frame.left_hand_position, Some([0.5, 1.0, 2.0]));
+
+        let snapshot = frame.to_bytes();
+        assert_eq!(snapshot.len(), FULL_PACKET_SIZE);
+        assert_eq!(MovementPacket::parse(&snapshot).unwrap().to_frame(), frame);
+    }
+
+    #[test]
+    fn test_random_frames_round_trip() {
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..10_000 {
+            let frame = MovementFrame {
+                frequent: rng.next().is_multiple_of(2),
+                base_position: rng.some(1000.0).map(|p: [f32; 3]| p.map(|v| v * 3.3)),
+                base_rotation: rng.some(1.0),
+                head_position: rng.some(2.0),
+                head_rotation: rng.some(1.0),
+                left_hand_position: rng.some(2.0),
+                left_hand_rotation: rng.some(1.0),
+                right_hand_position: rng.some(2.0),
+                right_hand_rotation: rng.some(1.0),
+                game_piece_position: rng.some(50.0),
+                game_piece_rotation: rng.some(1.0),
+            };
+            let bytes = frame.to_bytes();
+            assert_eq!(bytes.len(), frame.header().packet_size());
+            let packet = MovementPacket::parse(&bytes).unwrap();
+            assert_eq!(packet.header(), frame.header());
+            assert_eq!(packet.to_frame(), frame);
+        }
+    }
+
+    #[test]
+    fn test_float16_round_trip() {
+        for half in 0..=u16::MAX {
+            let value = f16_to_f32(half);
+            if value.is_nan() {
+                assert!(f32_to_f16(value) & 0x7c00 == 0x7c00 && f32_to_f16(value) & 0x3ff != 0);
+                continue;
+            }
+            assert_eq!(f32_to_f16(value), half, "{:#06x} -> {}", half, value);
+        }
+        assert_eq!(f32_to_f16(65520.0), 0x7c00);
+        assert_eq!(f32_to_f16(1.0 + f32::EPSILON), 0x3c00);
+        // ties go to the even half
+        assert_eq!(f32_to_f16(2049.0), f32_to_f16(2048.0));
+        assert_eq!(f32_to_f16(1e-10), 0);
+    }
+
+    #[test]
+    fn test_malformed_packets_are_rejected() {
+        assert_eq!(
+            MovementPacket::parse(&[]).unwrap_err(),
+            MovementError::Length(0)
+        );
+        assert_eq!(
+            MovementPacket::parse(&[1, 0, 0]).unwrap_err(),
+            MovementError::Length(3)
+        );
+        assert_eq!(
+            MovementPacket::parse(&[0; 200]).unwrap_err(),
+            MovementError::Oversized(200)
+        );
+
+        // hand fields announced in a small packet
+        let mut packet = small_sample();
+        packet[0..2].copy_from_slice(&(3u16 | PacketHeader::LEFT_HAND_POSITION).to_le_bytes());
+        assert!(matches!(
+            MovementPacket::parse(&packet),
+            Err(MovementError::Truncated { len: 36, .. })
+        ));
+
+        let mut packet = small_sample();
+        packet[2..6].copy_from_slice(&f32::NAN.to_le_bytes());
+        assert!(matches!(
+            MovementPacket::parse(&packet),
+            Err(MovementError::NotFinite { .. })
+        ));
+        // garbage in fields the header does not announce is ignored
+        let mut packet = full_sample();
+        packet[70..72].copy_from_slice(&0x7e00u16.to_le_bytes());
+        assert!(MovementPacket::parse(&packet).is_ok());
+    }
+}
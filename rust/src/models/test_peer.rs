@@ -0,0 +1,62 @@
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use crate::{
+        config::config::LoadBalancing,
+        models::{
+            movement::{MovementFrame, MovementPacket},
+            peer::Peers,
+            sfu::Loads,
+        },
+    };
+
+    #[test]
+    fn test_entry_registers_a_peer_once() {
+        let mut peers = Peers::new();
+        let peer_id = Uuid::new_v4();
+        peers.entry(peer_id).position = Some([1.0, 2.0, 3.0]);
+        peers.entry(peer_id);
+        assert_eq!(peers.iter().count(), 1);
+        assert_eq!(peers.get(peer_id).unwrap().position, Some([1.0, 2.0, 3.0]));
+        assert!(peers.transport(peer_id).is_none());
+    }
+
+    #[test]
+    fn test_removed_peer_is_gone() {
+        let mut peers = Peers::new();
+        let peer_id = Uuid::new_v4();
+        peers.entry(peer_id);
+        let peer = peers.remove(peer_id).unwrap();
+        assert_eq!(peer.id, peer_id);
+        assert!(peers.get(peer_id).is_none());
+        assert!(peers.remove(peer_id).is_none());
+        assert!(peers.transports().is_empty());
+    }
+
+    #[test]
+    fn test_closing_a_peer_without_transport_keeps_loads() {
+        let mut peers = Peers::new();
+        let peer_id = Uuid::new_v4();
+        peers.entry(peer_id);
+        let mut loads = Loads::new(LoadBalancing::LeastTransports);
+        peers.remove(peer_id).unwrap().close(&mut loads);
+        assert!(loads.workers.is_empty());
+    }
+
+    #[test]
+    fn test_frame_merges_movement_of_the_peer() {
+        let mut peers = Peers::new();
+        let peer_id = Uuid::new_v4();
+        let frame = MovementFrame {
+            base_position: Some([4.0, 5.0, 6.0]),
+            ..Default::default()
+        };
+        let bytes = frame.to_bytes();
+        peers
+            .entry(peer_id)
+            .update_frame(MovementPacket::parse(&bytes).unwrap());
+        let merged = peers.get(peer_id).unwrap().movement_frame.clone().unwrap();
+        assert_eq!(merged.base_position, Some([4.0, 5.0, 6.0]));
+    }
+}
@@ -0,0 +1,97 @@
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use uuid::Uuid;
+
+    use crate::models::room::{Room, Rooms};
+
+    fn rooms_with(name: &str) -> Rooms {
+        let mut rooms = Rooms::new();
+        rooms
+            .0
+            .insert(name.to_string(), Room::new(name.to_string()));
+        rooms
+    }
+
+    #[test]
+    fn test_new_room_is_idle_until_a_peer_joins() {
+        let room = Room::new("lobby".to_string());
+        let later = Instant::now() + Duration::from_secs(10);
+        assert!(room.idle_for(Duration::from_secs(5), later));
+        assert!(!room.idle_for(Duration::from_secs(60), later));
+
+        let mut rooms = rooms_with("lobby");
+        rooms.join("lobby", Uuid::new_v4());
+        let room = rooms.get("lobby").unwrap();
+        assert!(room.idle_since.is_none());
+        assert!(!room.idle_for(Duration::ZERO, later));
+    }
+
+    #[test]
+    fn test_room_is_idle_once_its_last_peer_leaves() {
+        let mut rooms = rooms_with("lobby");
+        let first = Uuid::new_v4();
+        let second = Uuid::new_v4();
+        rooms.join("lobby", first);
+        rooms.join("lobby", second);
+
+        rooms.leave(first);
+        assert!(rooms.get("lobby").unwrap().idle_since.is_none());
+        assert!(rooms.idle(Duration::ZERO).is_empty());
+
+        rooms.leave(second);
+        assert!(rooms.get("lobby").unwrap().peers.is_empty());
+        assert_eq!(rooms.idle(Duration::ZERO), vec!["lobby".to_string()]);
+        assert!(rooms.idle(Duration::from_secs(60)).is_empty());
+    }
+
+    #[test]
+    fn test_touched_room_is_idle_again_after_the_timeout() {
+        let mut rooms = rooms_with("lobby");
+        let since = Instant::now() - Duration::from_secs(60);
+        rooms.0.get_mut("lobby").unwrap().idle_since = Some(since);
+        assert_eq!(
+            rooms.idle(Duration::from_secs(30)),
+            vec!["lobby".to_string()]
+        );
+
+        rooms.touch("lobby");
+        assert!(rooms.idle(Duration::from_secs(30)).is_empty());
+        assert!(rooms.get("lobby").unwrap().idle_since.unwrap() > since);
+
+        // rooms with peers are not idle, touching them changes nothing
+        rooms.join("lobby", Uuid::new_v4());
+        rooms.touch("lobby");
+        assert!(rooms.get("lobby").unwrap().idle_since.is_none());
+    }
+
+    #[test]
+    fn test_joining_an_unknown_room_creates_nothing() {
+        let mut rooms = Rooms::new();
+        rooms.join("lobby", Uuid::new_v4());
+        assert!(rooms.get("lobby").is_none());
+        assert!(rooms.0.is_empty());
+    }
+
+    #[test]
+    fn test_removed_room_is_gone() {
+        let mut rooms = rooms_with("lobby");
+        let room = rooms.remove("lobby").unwrap();
+        assert_eq!(room.name, "lobby");
+        assert!(rooms.routers("lobby").is_none());
+        assert!(rooms.remove("lobby").is_none());
+    }
+
+    #[test]
+    fn test_full_room_admits_its_own_peers_only() {
+        let mut rooms = rooms_with("lobby");
+        let first = Uuid::new_v4();
+        assert!(rooms.admits("lobby", first, 1));
+        rooms.join("lobby", first);
+        assert!(rooms.admits("lobby", first, 1));
+        assert!(!rooms.admits("lobby", Uuid::new_v4(), 1));
+        assert!(rooms.admits("lobby", Uuid::new_v4(), 2));
+        assert!(rooms.admits("hall", Uuid::new_v4(), 1));
+    }
+}
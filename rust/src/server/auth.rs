@@ -0,0 +1,135 @@
+use std::{
+    fmt::{self, Write},
+    io,
+    time::Duration,
+};
+
+use futures::{Sink, SinkExt, Stream, StreamExt};
+use ring::{
+    hmac,
+    rand::{SecureRandom, SystemRandom},
+};
+use tokio::time::timeout;
+use uuid::Uuid;
+
+use crate::utils::{
+    codec::{MessageRequest, MessageResponse, RequestMessage, ResponseMessage},
+    utils::Error,
+};
+
+// signaling must answer the challenge within this delay
+pub const AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Each side signs the nonce of the other one, prefixed with its own role so a
+// proof can never be replayed in the other direction.
+pub const SIGNALING_ROLE: &str = "signaling";
+pub const NODE_ROLE: &str = "node";
+
+// The TLS or auth handshake with signaling failed: it is not the server this
+// node expects or does not share its secret, so retrying soon will not help.
+#[derive(Debug)]
+pub struct AuthError(pub String);
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+pub fn new_nonce() -> Result<String, Error> {
+    let mut nonce = [0u8; 32];
+    SystemRandom::new()
+        .fill(&mut nonce)
+        .map_err(|_| "cannot generate auth nonce")?;
+    Ok(to_hex(&nonce))
+}
+
+pub fn sign(secret: &str, role: &str, nonce: &str) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    to_hex(hmac::sign(&key, format!("{}:{}", role, nonce).as_bytes()).as_ref())
+}
+
+pub fn verify(secret: &str, role: &str, nonce: &str, signature: &str) -> bool {
+    let signature = match from_hex(signature) {
+        Some(signature) => signature,
+        None => return false,
+    };
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    hmac::verify(&key, format!("{}:{}", role, nonce).as_bytes(), &signature).is_ok()
+}
+
+// Challenge signaling and answer its challenge before anything else is sent
+// or handled on the connection. Any other message, a wrong hmac or no answer
+// within AUTH_TIMEOUT fails the connection.
+pub async fn authenticate<R, W>(
+    read: &mut R,
+    write: &mut W,
+    secret: &str,
+    node: Option<Uuid>,
+) -> Result<(), Error>
+where
+    R: Stream<Item = Result<RequestMessage, io::Error>> + Unpin,
+    W: Sink<ResponseMessage, Error = io::Error> + Unpin,
+{
+    let nonce = new_nonce()?;
+    write
+        .send(ResponseMessage::OutgoingServer {
+            node,
+            requestId: None,
+            message: MessageResponse::authChallenge {
+                nonce: nonce.clone(),
+            },
+        })
+        .await?;
+
+    let answer = match timeout(AUTH_TIMEOUT, read.next()).await {
+        Ok(Some(answer)) => answer?,
+        Ok(None) => return Err("signaling closed the connection during auth".into()),
+        Err(_) => return Err("signaling did not answer the auth challenge".into()),
+    };
+    let data = match answer {
+        RequestMessage::Incoming {
+            message: MessageRequest::authResponse { data },
+            ..
+        }
+        | RequestMessage::IncomingServer {
+            message: MessageRequest::authResponse { data },
+            ..
+        } => data,
+        other => return Err(format!("expected authResponse, got {:?}", other).into()),
+    };
+    if !verify(secret, SIGNALING_ROLE, &nonce, &data.hmac) {
+        return Err("signaling failed the auth challenge".into());
+    }
+
+    write
+        .send(ResponseMessage::OutgoingServer {
+            node,
+            requestId: None,
+            message: MessageResponse::authProof {
+                hmac: sign(secret, NODE_ROLE, &data.nonce),
+            },
+        })
+        .await?;
+    Ok(())
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        let _ = write!(hex, "{:02x}", b);
+    }
+    hex
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
@@ -0,0 +1,218 @@
+use std::{collections::HashMap, ops::RangeInclusive, time::Instant};
+
+use log::debug;
+use mediasoup::{
+    transport::Transport,
+    worker::{WorkerId, WorkerSettings},
+};
+use systemstat::{Platform, System};
+use tokio::time::interval;
+
+use crate::{
+    handlers::cpu_load::{get_process_memory, CpuSampler},
+    server::models::MediaServer,
+    utils::{
+        codec::{NodeCapacity, WorkerCapacity},
+        worker_load::LOAD_SAMPLE_INTERVAL,
+    },
+};
+
+// Byte counters of the host interfaces, turned into rates between two samples.
+struct NetworkSampler {
+    system: System,
+    last: Option<(Instant, u64, u64)>,
+}
+
+impl NetworkSampler {
+    fn new() -> Self {
+        NetworkSampler {
+            system: System::new(),
+            last: None,
+        }
+    }
+    // received and sent bytes per second since the previous call
+    fn sample(&mut self) -> Result<(u64, u64), String> {
+        let networks = self
+            .system
+            .networks()
+            .map_err(|e| format!("error listing networks: {}", e))?;
+        let (mut rx, mut tx) = (0u64, 0u64);
+        for name in networks.keys().filter(|name| name.as_str() != "lo") {
+            if let Ok(stats) = self.system.network_stats(name) {
+                rx += stats.rx_bytes.as_u64();
+                tx += stats.tx_bytes.as_u64();
+            }
+        }
+        let now = Instant::now();
+        let rates = match self.last {
+            Some((last, last_rx, last_tx)) => {
+                let elapsed = now.duration_since(last).as_secs_f64();
+                if elapsed > 0.0 {
+                    (
+                        (rx.saturating_sub(last_rx) as f64 / elapsed) as u64,
+                        (tx.saturating_sub(last_tx) as f64 / elapsed) as u64,
+                    )
+                } else {
+                    (0, 0)
+                }
+            }
+            None => (0, 0),
+        };
+        self.last = Some((now, rx, tx));
+        Ok(rates)
+    }
+}
+
+// Refresh the capacity sent with serverLoad, forever. Nothing here waits on a
+// measurement: cpu and network rates are taken between two ticks.
+pub async fn sample_capacity(media_server: MediaServer) {
+    let mut cpu = CpuSampler::new();
+    let mut network = NetworkSampler::new();
+    let system = System::new();
+    let mut tick = interval(LOAD_SAMPLE_INTERVAL);
+    loop {
+        tick.tick().await;
+        let mut capacity = worker_capacity(&media_server).await;
+        capacity.cpu = match cpu.sample() {
+            Ok(Some(cpu)) => cpu,
+            Ok(None) => 0.0,
+            Err(e) => {
+                debug!("cannot sample cpu: {}", e);
+                media_server.capacity.with(|c| c.cpu)
+            }
+        };
+        match network.sample() {
+            Ok((rx, tx)) => {
+                capacity.rxBytesPerSec = rx;
+                capacity.txBytesPerSec = tx;
+            }
+            Err(e) => debug!("cannot sample network: {}", e),
+        }
+        match system.memory() {
+            Ok(memory) => {
+                capacity.memoryFree = memory.free.as_u64();
+                capacity.memoryTotal = memory.total.as_u64();
+            }
+            Err(e) => debug!("cannot sample memory: {}", e),
+        }
+        match get_process_memory() {
+            Ok(used) => capacity.memoryUsed = used,
+            Err(e) => debug!("cannot sample process memory: {}", e),
+        }
+
+        let ports = WorkerSettings::default().rtc_ports_range;
+        let workers = capacity.workers.len() as u16;
+        let used_ports = media_server.pipetransports.with(|p| p.count())
+            + media_server.plainTransports.with(|p| p.count())
+            + webrtc_ports_in(&ports, media_server.config.webrtc_port, workers);
+        capacity.freePorts = (ports.len() as u32).saturating_sub(used_ports as u32);
+
+        capacity.remaining = remaining_capacity(
+            &capacity,
+            ports.len() as u32,
+            media_server.config.room_limits.max_rooms,
+        );
+        media_server.capacity.with(|c| *c = capacity);
+    }
+}
+
+// cpu, routers, transports and consumers of each worker
+async fn worker_capacity(media_server: &MediaServer) -> NodeCapacity {
+    let worker_ids: Vec<WorkerId> = media_server
+        .workers
+        .read()
+        .await
+        .0
+        .iter()
+        .map(|w| w.id())
+        .collect();
+    let mut workers: HashMap<WorkerId, WorkerCapacity> = worker_ids
+        .iter()
+        .map(|worker_id| {
+            let capacity = WorkerCapacity {
+                workerId: *worker_id,
+                cpu: 0.0,
+                routers: 0,
+                transports: 0,
+                consumers: 0,
+            };
+            (*worker_id, capacity)
+        })
+        .collect();
+    {
+        let loads = media_server.loads.lock().await;
+        for (worker_id, worker) in workers.iter_mut() {
+            if let Some(load) = loads.workers.get(worker_id) {
+                worker.cpu = load.cpu;
+            }
+        }
+    }
+    for worker_id in media_server.routers2workers.lock().await.0.values() {
+        if let Some(worker) = workers.get_mut(worker_id) {
+            worker.routers += 1;
+        }
+    }
+    media_server.peers.with(|p| {
+        for peer in p.iter() {
+            if let Some(transport) = &peer.transport {
+                if let Some(worker) = workers.get_mut(&transport.router().worker().id()) {
+                    worker.transports += 1;
+                }
+            }
+            for consumer in peer.consumers.iter().chain(peer.relay_consumers.iter()) {
+                let worker_id = consumer.transport().router().worker().id();
+                if let Some(worker) = workers.get_mut(&worker_id) {
+                    worker.consumers += 1;
+                }
+            }
+        }
+    });
+    let rooms = media_server.rooms.lock().await.0.len() as u32;
+    // keep the order the workers were started in
+    let workers: Vec<WorkerCapacity> = worker_ids
+        .iter()
+        .filter_map(|worker_id| workers.remove(worker_id))
+        .collect();
+    NodeCapacity {
+        transports: workers.iter().map(|w| w.transports).sum(),
+        consumers: workers.iter().map(|w| w.consumers).sum(),
+        rooms,
+        workers,
+        ..Default::default()
+    }
+}
+
+// webrtc servers listening inside the rtc port range, worker n on webrtc_port + n
+pub fn webrtc_ports_in(ports: &RangeInclusive<u16>, webrtc_port: u16, workers: u16) -> usize {
+    (0..workers)
+        .filter_map(|n| webrtc_port.checked_add(n))
+        .filter(|port| ports.contains(port))
+        .count()
+}
+
+// Percent of the node left, the scarcest of worker cpu, host memory, rtc
+// ports and, with max_rooms, rooms. Each worker runs on one core, so a node
+// is full once its workers are busy on average.
+pub fn remaining_capacity(
+    capacity: &NodeCapacity,
+    total_ports: u32,
+    max_rooms: Option<u32>,
+) -> f32 {
+    let mut remaining: f32 = 100.0;
+    if !capacity.workers.is_empty() {
+        let cpu =
+            capacity.workers.iter().map(|w| w.cpu).sum::<f32>() / capacity.workers.len() as f32;
+        remaining = remaining.min(100.0 - cpu);
+    }
+    if capacity.memoryTotal > 0 {
+        remaining = remaining.min(capacity.memoryFree as f32 / capacity.memoryTotal as f32 * 100.0);
+    }
+    if total_ports > 0 {
+        remaining = remaining.min(capacity.freePorts as f32 / total_ports as f32 * 100.0);
+    }
+    if let Some(max) = max_rooms {
+        let rooms = capacity.rooms.min(max);
+        remaining = remaining.min((max - rooms) as f32 / max as f32 * 100.0);
+    }
+    remaining.clamp(0.0, 100.0)
+}
@@ -0,0 +1,104 @@
+use std::{sync::atomic::Ordering, time::Duration};
+
+use log::{info, warn};
+use tokio::{
+    sync::mpsc::unbounded_channel,
+    time::{sleep, timeout, Instant},
+};
+
+use crate::{
+    models::{
+        peer::Peers,
+        room::Rooms,
+        sfu::{
+            AudioObservers, BotPeers, DataTaps, PendingRelays, PipeTransports, PlainTransports,
+            Recordings, RelayRouters, Relays, Routers, WebrtcServers, Workers,
+        },
+    },
+    server::models::MediaServer,
+};
+
+// how often a draining node checks whether its rooms are gone
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_secs(1);
+// time given to mediasoup to close the workers once every handle is dropped
+const WORKER_CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Stop taking new rooms, wait until the existing ones are destroyed or
+// `drain_timeout` elapses, then close the workers and exit 0. Returns false
+// without doing anything when the node is already draining.
+pub fn start_drain(media_server: &MediaServer, drain_timeout: Duration) -> bool {
+    if media_server.draining.swap(true, Ordering::SeqCst) {
+        return false;
+    }
+    let media_server = media_server.clone();
+    tokio::spawn(async move {
+        let deadline = Instant::now() + drain_timeout;
+        loop {
+            let rooms = media_server.rooms.lock().await.0.len();
+            if rooms == 0 {
+                info!("all rooms closed, stopping");
+                break;
+            }
+            if Instant::now() >= deadline {
+                warn!("drain timeout elapsed with {} rooms left, stopping", rooms);
+                break;
+            }
+            sleep(DRAIN_POLL_INTERVAL).await;
+        }
+        close_workers(&media_server).await;
+        std::process::exit(0);
+    });
+    true
+}
+
+// mediasoup closes a worker when its last handle is dropped, so forget every
+// router, transport and producer still referencing one and wait for them.
+async fn close_workers(media_server: &MediaServer) {
+    let (closed_tx, mut closed_rx) = unbounded_channel::<()>();
+    let count = {
+        let workers = media_server.workers.read().await;
+        for worker in workers.0.iter() {
+            let closed_tx = closed_tx.clone();
+            worker
+                .on_close(move || {
+                    let _ = closed_tx.send(());
+                })
+                .detach();
+        }
+        workers.0.len()
+    };
+
+    let peers = media_server
+        .peers
+        .with(|p| std::mem::replace(p, Peers::new()));
+    drop(peers);
+    *media_server.audioObservers.lock().await = AudioObservers::new();
+    media_server.dataTaps.with(|m| *m = DataTaps::new());
+    media_server
+        .plainTransports
+        .with(|p| *p = PlainTransports::new());
+    media_server.botPeers.with(|b| *b = BotPeers::new());
+    // dropping the tracks ends their files
+    *media_server.recordings.lock().await = Recordings::new();
+    // relays hold pipe transports on both routers they join
+    *media_server.pendingRelays.lock().await = PendingRelays::new();
+    *media_server.relays.lock().await = Relays::new();
+    *media_server.relayRouters.lock().await = RelayRouters::new();
+    media_server
+        .pipetransports
+        .with(|p| *p = PipeTransports::new());
+    *media_server.rooms.lock().await = Rooms::new();
+    *media_server.routers.lock().await = Routers::new();
+    *media_server.webrtc_server.write().await = WebrtcServers::new();
+    *media_server.workers.write().await = Workers::new();
+
+    let all_closed = async {
+        for _ in 0..count {
+            let _ = closed_rx.recv().await;
+        }
+    };
+    match timeout(WORKER_CLOSE_TIMEOUT, all_closed).await {
+        Ok(_) => info!("closed {} workers", count),
+        Err(_) => warn!("workers still open after {:?}", WORKER_CLOSE_TIMEOUT),
+    }
+}
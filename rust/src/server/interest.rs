@@ -0,0 +1,236 @@
+use std::collections::{HashMap, HashSet};
+
+use log::{debug, error, warn};
+use mediasoup::{
+    consumer::ConsumerType,
+    prelude::{Consumer, ConsumerId, ConsumerLayers, MediaKind},
+};
+use tokio::time::interval;
+use uuid::Uuid;
+
+use crate::{
+    config::config::InterestConfig,
+    server::models::MediaServer,
+    utils::codec::{
+        InterestChange, InterestChangedData, InterestState, MessageResponse, ResponseMessage,
+    },
+};
+
+// a consumer leaves a state once its peer is back within this share of the
+// threshold, so peers standing on the boundary do not flap
+const HYSTERESIS: f32 = 0.9;
+
+// What the interest manager did to a consumer, consumers it never touched are
+// not tracked.
+#[derive(Default)]
+pub struct Managed {
+    pub paused: bool,
+    // the preferred layers to restore once lowered
+    pub lowered: Option<Option<ConsumerLayers>>,
+}
+
+// Every interval, compare the distance between each consuming peer and the
+// peer it consumes, pause or lower the consumers of far away peers and
+// restore them once they come back. Consumers paused by their client are left
+// alone.
+pub async fn manage_interest(media_server: MediaServer, interest: InterestConfig) {
+    let mut managed: HashMap<ConsumerId, Managed> = HashMap::new();
+    let mut tick = interval(interest.interval);
+    loop {
+        tick.tick().await;
+        let (positions, pairs) = media_server.peers.with(|peers| {
+            let mut owners = HashMap::new();
+            let mut positions = HashMap::new();
+            for peer in peers.iter() {
+                for producer in peer.producers.iter() {
+                    owners.insert(producer.id(), peer.id);
+                }
+                if let Some(position) = peer.position {
+                    positions.insert(peer.id, position);
+                }
+            }
+            let mut pairs = vec![];
+            for peer in peers.iter() {
+                for consumer in peer.consumers.iter() {
+                    if let Some(producer_peer) = owners.get(&consumer.producer_id()) {
+                        pairs.push((peer.id, *producer_peer, consumer.clone()));
+                    }
+                }
+            }
+            (positions, pairs)
+        });
+
+        let mut seen = HashSet::new();
+        let mut changes: HashMap<Uuid, Vec<InterestChange>> = HashMap::new();
+        for (peer_id, producer_peer, consumer) in pairs {
+            if consumer.closed() {
+                continue;
+            }
+            let consumer_id = consumer.id();
+            seen.insert(consumer_id);
+            // unknown positions count as close, nothing is hidden before movement
+            let distance = match (positions.get(&peer_id), positions.get(&producer_peer)) {
+                (Some(a), Some(b)) => distance(*a, *b),
+                _ => 0.0,
+            };
+            let current = managed.remove(&consumer_id).unwrap_or_default();
+            let simulcast = consumer.r#type() != ConsumerType::Simple;
+            let target = target_state(&interest, consumer.kind(), simulcast, distance, &current);
+            let (state, changed) = apply(&consumer, current, target).await;
+            if state.paused || state.lowered.is_some() {
+                managed.insert(consumer_id, state);
+            }
+            if changed {
+                changes.entry(peer_id).or_default().push(InterestChange {
+                    consumerId: consumer_id,
+                    producerPeerId: producer_peer,
+                    kind: consumer.kind(),
+                    state: target,
+                });
+            }
+        }
+        managed.retain(|consumer_id, _| seen.contains(consumer_id));
+
+        for (peer_id, changes) in changes {
+            notify(&media_server, peer_id, changes).await;
+        }
+    }
+}
+
+fn distance(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a.iter()
+        .zip(b.iter())
+        .map(|(a, b)| (a - b) * (a - b))
+        .sum::<f32>()
+        .sqrt()
+}
+
+pub fn beyond(distance: f32, threshold: Option<f32>, was_beyond: bool) -> bool {
+    match threshold {
+        Some(threshold) if was_beyond => distance > threshold * HYSTERESIS,
+        Some(threshold) => distance > threshold,
+        None => false,
+    }
+}
+
+// Where a consumer of `kind` should be at `distance`, only consumers with
+// layers to choose from, simulcast or svc, are lowered.
+pub fn target_state(
+    interest: &InterestConfig,
+    kind: MediaKind,
+    simulcast: bool,
+    distance: f32,
+    current: &Managed,
+) -> InterestState {
+    match kind {
+        MediaKind::Audio => {
+            if beyond(distance, interest.audio_distance, current.paused) {
+                InterestState::paused
+            } else {
+                InterestState::active
+            }
+        }
+        MediaKind::Video => {
+            if beyond(distance, interest.video_distance, current.paused) {
+                InterestState::paused
+            } else if simulcast
+                && beyond(
+                    distance,
+                    interest.video_low_distance,
+                    current.lowered.is_some() || current.paused,
+                )
+            {
+                InterestState::lowered
+            } else {
+                InterestState::active
+            }
+        }
+    }
+}
+
+// Whether a consumer heading to `target` keeps or gets its lowest layers. A
+// paused consumer stays lowered so resuming it starts low.
+pub fn lowers(target: InterestState, state: &Managed) -> bool {
+    target == InterestState::lowered || (target == InterestState::paused && state.lowered.is_some())
+}
+
+// Whether a consumer heading to `target` is or stays paused by the interest
+// manager. One its client paused is left alone, so it is not resumed either.
+pub fn pauses(target: InterestState, state: &Managed, client_paused: bool) -> bool {
+    target == InterestState::paused && (state.paused || !client_paused)
+}
+
+// Move a consumer towards `target`, returns its new state and whether anything
+// changed.
+async fn apply(consumer: &Consumer, mut state: Managed, target: InterestState) -> (Managed, bool) {
+    let mut changed = false;
+    let pause = pauses(target, &state, consumer.paused());
+    let lower = lowers(target, &state);
+    if lower && state.lowered.is_none() {
+        let preferred = consumer.preferred_layers();
+        let lowest = ConsumerLayers {
+            spatial_layer: 0,
+            temporal_layer: None,
+        };
+        match consumer.set_preferred_layers(lowest).await {
+            Ok(_) => {
+                state.lowered = Some(preferred);
+                changed = true;
+            }
+            Err(e) => error!("cannot lower consumer {}: {}", consumer.id(), e),
+        }
+    } else if !lower {
+        if let Some(preferred) = state.lowered.take() {
+            changed = true;
+            match preferred {
+                Some(layers) => {
+                    if let Err(e) = consumer.set_preferred_layers(layers).await {
+                        error!("cannot restore consumer {}: {}", consumer.id(), e);
+                    }
+                }
+                None => warn!("no preferred layers to restore on {}", consumer.id()),
+            }
+        }
+    }
+    if pause && !state.paused {
+        match consumer.pause().await {
+            Ok(_) => {
+                state.paused = true;
+                changed = true;
+            }
+            Err(e) => error!("cannot pause consumer {}: {}", consumer.id(), e),
+        }
+    } else if target == InterestState::paused && !pause {
+        debug!("consumer {} already paused by its client", consumer.id());
+    } else if !pause && state.paused {
+        match consumer.resume().await {
+            Ok(_) => {
+                state.paused = false;
+                changed = true;
+            }
+            Err(e) => error!("cannot resume consumer {}: {}", consumer.id(), e),
+        }
+    }
+    (state, changed)
+}
+
+async fn notify(media_server: &MediaServer, peer_id: Uuid, changes: Vec<InterestChange>) {
+    let sender = match media_server.signaling.with(|s| s.clone()) {
+        Some(sender) => sender,
+        // not connected to signaling, the client keeps its previous view
+        None => return,
+    };
+    let msg = ResponseMessage::OutgoingCommunication {
+        ws: None,
+        requestId: None,
+        communication: MessageResponse::interestChanged {
+            data: InterestChangedData {
+                peerId: peer_id,
+                changes,
+            },
+        },
+    };
+    if let Err(e) = sender.send(msg).await {
+        error!("error sending interest changes: {:?}", e);
+    }
+}
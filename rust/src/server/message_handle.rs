@@ -1,26 +1,49 @@
 use crate::{
     config::config::Config,
     handlers::{
-        audio_consumer::consume_audio, connect_ingress_egress::connect_webrtc,
-        data_relay_producer::create_relay_datachannel_producer, egress::create_webrtc_egress,
-        event_consumer::consume_event, event_producer::create_event_data_producer,
-        ingress::create_webrtc_ingress, media_producer::create_media_producer,
-        movement_consumer::consume_movement, movement_producer::create_movement_data_producer,
-        pendingrelays::store_pipe_relay, relay_connect::connect_pipe_relay,
-        relay_egress::create_egress_relay, relay_producer::create_relay_producer,
-        router::create_router_group, video_consumer::consume_video,
+        audio_consumer::consume_audio,
+        bot_peer::{bot_consume, bot_send, close_bot_peer, create_bot_peer},
+        connect_ingress_egress::connect_webrtc,
+        consumer_layers::{find_consumer, set_consumer_preferred_layers, set_consumer_priority},
+        data_relay_producer::create_relay_datachannel_producer,
+        egress::create_webrtc_egress,
+        error::HandlerError,
+        event_consumer::consume_event,
+        event_producer::create_event_data_producer,
+        ingress::create_webrtc_ingress,
+        media_producer::create_media_producer,
+        movement_consumer::consume_movement,
+        movement_producer::create_movement_data_producer,
+        pendingrelays::store_pipe_relay,
+        plain_transport::{close_plain_transport, create_plain_egress, create_plain_ingress},
+        recording::{start_recording, stop_recording},
+        relay_connect::connect_pipe_relay,
+        relay_egress::create_egress_relay,
+        relay_producer::create_relay_producer,
+        router::create_router_group,
+        stats::{get_consumer_stats, get_producer_stats, get_transport_stats},
+        video_consumer::consume_video,
     },
-    utils::codec::{
-        MessageRequest, MessageResponse, ProducerReplyMuteData, RequestMessage, ResponseMessage,
-        RestartedIceData,
+    utils::{
+        codec::{
+            MessageRequest, MessageResponse, ProducerReplyMuteData, RequestMessage,
+            ResponseMessage, RestartedIceData, RoomClosedReason,
+        },
+        utils::{get_nodeid, Responder},
     },
 };
 use mediasoup::transport::Transport;
+use std::time::Duration;
 use tokio::sync::mpsc::Sender;
 
 use log::{debug, error, info};
 
-use super::models::MediaServer;
+use super::{
+    drain::start_drain,
+    metrics::RequestTimer,
+    models::MediaServer,
+    rooms::{close_room, room_closed},
+};
 
 pub async fn handle_request_message(
     msg: RequestMessage,
@@ -30,26 +53,53 @@ pub async fn handle_request_message(
 ) {
     println!("Received netsocket message: {:?}", &msg);
 
+    let (request_id, request) = match &msg {
+        RequestMessage::Incoming {
+            requestId, message, ..
+        }
+        | RequestMessage::IncomingServer {
+            requestId, message, ..
+        } => (requestId.clone(), message.name()),
+    };
+    let timer = RequestTimer::new(media_server.metrics.clone(), request);
+    let sender = Responder::new(sender, request_id, Some(timer));
+    let node = get_nodeid(config.ingress, config.egress);
+
     match msg {
-        RequestMessage::Incoming { wsid, message } => match message {
+        RequestMessage::Incoming { wsid, message, .. } => match message {
             MessageRequest::createRouterGroup { data } => {
                 let media_server = media_server.clone(); // Clone for shared ownership
                 let config = config.clone(); // Assume Config implements Clone
                 tokio::spawn(async move {
-                    let room_routers = media_server.roomRouters.clone();
+                    let room_routers = media_server.rooms.clone();
+                    if media_server.is_draining()
+                        && room_routers.lock().await.get(&data.room).is_none()
+                    {
+                        let e = HandlerError::Draining(format!(
+                            "node is draining, not creating room: {}",
+                            data.room
+                        ));
+                        sender.fail(Some(wsid), None, &e).await;
+                        return;
+                    }
                     let workers = media_server.workers.clone();
                     let routers = media_server.routers.clone();
                     let routers2workers = media_server.routers2workers.clone();
 
                     let response = create_router_group(
                         data.room.clone(),
-                        wsid,
+                        wsid.clone(),
                         config.ingress,
                         config.egress,
                         room_routers,
                         workers,
                         routers,
                         routers2workers,
+                        media_server.loads.clone(),
+                        config.router_placement,
+                        config.room_limits.max_rooms,
+                        config.media_codecs.clone(),
+                        media_server.signaling.clone(),
                         sender.clone(),
                     )
                     .await;
@@ -59,8 +109,8 @@ pub async fn handle_request_message(
                             info!("@@ Successfully created room: {:?}", &data.room);
                         }
                         Err(e) => {
-                            // Handle or log error
                             error!("Error: {}", e);
+                            sender.fail(Some(wsid), None, &e).await;
                         }
                     }
                 });
@@ -69,19 +119,19 @@ pub async fn handle_request_message(
                 tokio::spawn(async move {
                     match create_webrtc_ingress(
                         media_server.ingress.clone(),
-                        media_server.endpoints.clone(),
-                        media_server.transports.clone(),
+                        media_server.peers.clone(),
                         media_server.pipetransports.clone(),
                         media_server.relays.clone(),
                         media_server.transport2router.clone(),
                         media_server.webrtc_server.clone(),
                         media_server.routers.clone(),
-                        media_server.roomRouters.clone(),
+                        media_server.rooms.clone(),
                         media_server.routers2workers.clone(),
+                        media_server.workers.clone(),
                         media_server.loads.clone(),
                         data.sctpOptions,
                         data.routerNetwork,
-                        wsid,
+                        wsid.clone(),
                         data.routerPipes,
                         data.peerId,
                         media_server.config.clone(),
@@ -93,7 +143,8 @@ pub async fn handle_request_message(
                             println!("ingress created: {:?}", e)
                         }
                         Err(e) => {
-                            error!("failed to create ingress: {:?}", e)
+                            error!("failed to create ingress: {:?}", e);
+                            sender.fail(Some(wsid), None, &e).await;
                         }
                     }
                 });
@@ -102,18 +153,19 @@ pub async fn handle_request_message(
                 tokio::spawn(async move {
                     match create_webrtc_egress(
                         media_server.routers.clone(),
-                        media_server.roomRouters.clone(),
+                        media_server.rooms.clone(),
                         media_server.routers2workers.clone(),
+                        media_server.workers.clone(),
                         media_server.webrtc_server.clone(),
-                        media_server.transports.clone(),
-                        media_server.endpoints.clone(),
+                        media_server.peers.clone(),
                         media_server.transport2router.clone(),
                         media_server.loads.clone(),
                         data.sctpOptions,
                         data.routerNetwork,
-                        wsid,
+                        wsid.clone(),
                         data.peerId,
                         media_server.egress.clone(),
+                        media_server.config.clone(),
                         sender.clone(),
                     )
                     .await
@@ -121,60 +173,68 @@ pub async fn handle_request_message(
                         Ok(_) => {
                             info!("egress has been created!")
                         }
-                        Err(e) => error!("error creating egress!!: {:?}", e),
+                        Err(e) => {
+                            error!("error creating egress!!: {:?}", e);
+                            sender.fail(Some(wsid), None, &e).await;
+                        }
                     }
                 });
             }
             MessageRequest::connectWebRTCIngress { data } => {
                 tokio::spawn(async move {
                     match connect_webrtc(
-                        wsid,
+                        wsid.clone(),
                         data.peerId,
                         data.dtlsParameters,
                         true,
-                        media_server.transports.clone(),
+                        media_server.peers.clone(),
                         sender.clone(),
                     )
                     .await
                     {
                         Ok(_) => info!("connected webrtc ingress transport"),
-                        Err(_) => error!("error to connect webrtc ingress transprot"),
+                        Err(e) => {
+                            error!("error to connect webrtc ingress transprot: {:?}", e);
+                            sender.fail(Some(wsid), None, &e).await;
+                        }
                     }
                 });
             }
             MessageRequest::connectWebRTCEgress { data } => {
                 tokio::spawn(async move {
                     match connect_webrtc(
-                        wsid,
+                        wsid.clone(),
                         data.peerId,
                         data.dtlsParameters,
                         false,
-                        media_server.transports.clone(),
+                        media_server.peers.clone(),
                         sender.clone(),
                     )
                     .await
                     {
                         Ok(_) => info!("connected webrtc egress transport"),
-                        Err(_) => error!("error to connect webrtc ingress transprot"),
+                        Err(e) => {
+                            error!("error to connect webrtc egress transprot: {:?}", e);
+                            sender.fail(Some(wsid), None, &e).await;
+                        }
                     }
                 });
             }
             MessageRequest::createMediaProducer { data } => {
                 tokio::spawn(async move {
                     match create_media_producer(
-                        media_server.transports.clone(),
+                        media_server.peers.clone(),
                         media_server.transport2router.clone(),
-                        media_server.audioProducers.clone(),
-                        media_server.videoProducers.clone(),
+                        media_server.audioObservers.clone(),
+                        media_server.signaling.clone(),
                         media_server.pipetransports.clone(),
                         media_server.relays.clone(),
-                        media_server.producers.clone(),
-                        media_server.consumers.clone(),
+                        media_server.rooms.clone(),
                         media_server.config.clone(),
                         data.egress.clone(),
                         data.producerOptions,
                         data.peerId,
-                        wsid,
+                        wsid.clone(),
                         data.routerNetwork,
                         data.rtpCapabilities,
                         sender.clone(),
@@ -184,368 +244,501 @@ pub async fn handle_request_message(
                         Ok(_) => {
                             info!("Successfully created media")
                         }
-                        Err(_) => error!("Failed created media"),
+                        Err(e) => {
+                            error!("Failed created media: {:?}", e);
+                            sender.fail(Some(wsid), None, &e).await;
+                        }
                     }
                 });
             }
             MessageRequest::createEventProducer { data } => {
                 tokio::spawn(async move {
                     match create_event_data_producer(
-                        wsid,
+                        wsid.clone(),
                         data.routerNetwork,
                         data.peerId,
                         data.egress,
                         data.producerOptions,
-                        media_server.transports.clone(),
-                        media_server.eventProducers.clone(),
+                        media_server.peers.clone(),
+                        media_server.dataTaps.clone(),
+                        media_server.signaling.clone(),
                         media_server.transport2router.clone(),
                         media_server.pipetransports.clone(),
                         media_server.relays.clone(),
-                        media_server.endpoints.clone(),
-                        media_server.peerdataconsumed.clone(),
-                        media_server.data_producers.clone(),
-                        media_server.data_consumer.clone(),
                         media_server.config.clone(),
                         sender.clone(),
                     )
                     .await
                     {
                         Ok(_) => info!("Successfully created data producer"),
-                        Err(_) => info!("failed created data producer"),
+                        Err(e) => {
+                            error!("failed created data producer: {:?}", e);
+                            sender.fail(Some(wsid), None, &e).await;
+                        }
                     }
                 });
             }
             MessageRequest::createDataProducer { data } => {
                 tokio::spawn(async move {
                     match create_movement_data_producer(
-                        wsid,
+                        wsid.clone(),
                         data.routerNetwork,
                         data.peerId,
                         data.egress,
                         data.producerOptions,
-                        media_server.transports.clone(),
-                        media_server.movementProducers.clone(),
+                        media_server.peers.clone(),
+                        media_server.dataTaps.clone(),
+                        media_server.signaling.clone(),
                         media_server.transport2router.clone(),
                         media_server.pipetransports.clone(),
                         media_server.relays.clone(),
-                        media_server.endpoints.clone(),
-                        media_server.peermovementconsumed.clone(),
-                        media_server.data_producers.clone(),
-                        media_server.data_consumer.clone(),
                         media_server.config.clone(),
                         sender.clone(),
                     )
                     .await
                     {
                         Ok(_) => info!("Successfully created movement producer"),
-                        Err(_) => info!("failed created movement producer"),
+                        Err(e) => {
+                            error!("failed created movement producer: {:?}", e);
+                            sender.fail(Some(wsid), None, &e).await;
+                        }
                     }
                 });
             }
             MessageRequest::consumeAudio { data } => {
                 tokio::spawn(async move {
                     let consume_audio = consume_audio(
-                        wsid,
+                        wsid.clone(),
                         data.consumerPeer,
                         data.producerPeer.clone(),
                         data.rtpCaps,
-                        media_server.audioProducers.clone(),
-                        media_server.peeraudioconsumed,
-                        media_server.transports.clone(),
+                        media_server.peers.clone(),
                         media_server.transport2router.clone(),
                         media_server.routers.clone(),
-                        media_server.consumers.clone(),
                         sender.clone(),
                     )
                     .await;
-                    if let Ok(_) = consume_audio {
-                        debug!("Successfully consumed audio: {:?}", data.producerPeer);
-                    } else {
-                        error!("error consume audio of: {:?}", data.producerPeer);
+                    match consume_audio {
+                        Ok(_) => debug!("Successfully consumed audio: {:?}", data.producerPeer),
+                        Err(e) => {
+                            error!("error consume audio of: {:?} {:?}", data.producerPeer, e);
+                            sender.fail(Some(wsid), None, &e).await;
+                        }
                     }
                 });
             }
             MessageRequest::consumeMovement { data } => {
                 tokio::spawn(async move {
                     let events_consume = consume_movement(
-                        wsid,
+                        wsid.clone(),
                         data.producerPeer,
                         data.consumerPeer,
-                        media_server.transports.clone(),
-                        media_server.movementProducers.clone(),
-                        media_server.peermovementconsumed.clone(),
-                        media_server.data_consumer.clone(),
+                        media_server.peers.clone(),
                         sender.clone(),
                     )
                     .await;
-                    if let Ok(_) = events_consume {
-                        debug!("Successfully consumed movement");
-                    } else {
-                        error!("failed to consume movement")
+                    match events_consume {
+                        Ok(_) => debug!("Successfully consumed movement"),
+                        Err(e) => {
+                            error!("failed to consume movement: {:?}", e);
+                            sender.fail(Some(wsid), None, &e).await;
+                        }
                     }
                 });
             }
             MessageRequest::consumeEvents { data } => {
                 tokio::spawn(async move {
                     let events_consume = consume_event(
-                        wsid,
+                        wsid.clone(),
                         data.producerPeer,
                         data.consumerPeer,
-                        media_server.transports.clone(),
-                        media_server.eventProducers.clone(),
-                        media_server.peerdataconsumed.clone(),
-                        media_server.data_consumer.clone(),
+                        media_server.peers.clone(),
                         sender.clone(),
                     )
                     .await;
-                    if let Ok(_) = events_consume {
-                        debug!("Successfully consumed events");
-                    } else {
-                        error!("failed to consume event")
+                    match events_consume {
+                        Ok(_) => debug!("Successfully consumed events"),
+                        Err(e) => {
+                            error!("failed to consume event: {:?}", e);
+                            sender.fail(Some(wsid), None, &e).await;
+                        }
                     }
                 });
             }
             MessageRequest::consumeVideo { data } => {
                 tokio::spawn(async move {
                     let video_consumer = consume_video(
-                        wsid,
+                        wsid.clone(),
                         data.consumerPeer,
                         data.producerPeer,
                         data.rtpCaps,
-                        media_server.videoProducers.clone(),
-                        media_server.peerconsumed.clone(),
-                        media_server.transports.clone(),
+                        media_server.peers.clone(),
                         media_server.transport2router.clone(),
                         media_server.routers.clone(),
-                        media_server.consumers.clone(),
+                        media_server.signaling.clone(),
+                        media_server.config.room_limits.max_consumers_per_transport,
                         sender.clone(),
                     )
                     .await;
-                    if let Ok(_) = video_consumer {
-                        debug!("video consumer Successfully");
-                    } else {
-                        error!("video consumer failed");
+                    match video_consumer {
+                        Ok(_) => debug!("video consumer Successfully"),
+                        Err(e) => {
+                            error!("video consumer failed: {:?}", e);
+                            sender.fail(Some(wsid), None, &e).await;
+                        }
                     }
                 });
             }
             MessageRequest::disconnectTransport { data } => {
                 tokio::spawn(async move {
-                    let peer_id = data.peerId;
-                    let mut producers = media_server.producers.lock().await;
-                    let mut consumers = media_server.consumers.lock().await;
-                    let mut audio_producers = media_server.audioProducers.lock().await;
-                    let mut data_consumer = media_server.data_consumer.lock().await;
-                    let mut data_producers = media_server.data_producers.lock().await;
-                    if let Some(producer) = audio_producers.get(peer_id) {
-                        for p in producer.into_iter() {
-                            producers.remove(&p.id());
+                    // closing the peer closes its transport and everything on it
+                    let peer = media_server.peers.with(|p| p.remove(data.peerId));
+                    if let Some(peer) = peer {
+                        peer.close(&mut *media_server.loads.lock().await);
+                    }
+                    media_server.rooms.lock().await.leave(data.peerId);
+                });
+            }
+            MessageRequest::destroyRouterGroup { data } => {
+                tokio::spawn(async move {
+                    info!("destorying room router {:?}", &data.room);
+                    let mut rooms = media_server.rooms.lock().await;
+                    let room = close_room(&media_server, &mut rooms, &data.room).await;
+                    drop(rooms);
+                    match room {
+                        Some(room) => {
+                            room_closed(&media_server, room, RoomClosedReason::destroyed).await
+                        }
+                        None => {
+                            let e =
+                                HandlerError::NotFound(format!("cannot find room: {}", data.room));
+                            sender.fail(Some(wsid), None, &e).await;
                         }
-                        audio_producers.remove(peer_id);
                     }
-                    let mut video_producers = media_server.videoProducers.lock().await;
-                    if let Some(video_producer) = video_producers.get(peer_id) {
-                        for vp in video_producer.into_iter() {
-                            producers.remove(&vp.id());
+                });
+            }
+            MessageRequest::consumerPause { data } => {
+                tokio::spawn(async move {
+                    let consumer = match find_consumer(
+                        &media_server.peers,
+                        &media_server.plainTransports,
+                        data.consumerId,
+                    ) {
+                        Ok(consumer) => consumer,
+                        Err(e) => {
+                            println!(
+                                "cannot find consumer on this server: {:?}",
+                                &data.consumerId
+                            );
+                            sender.fail(Some(wsid), None, &e).await;
+                            return;
                         }
-                        video_producers.remove(peer_id);
+                    };
+                    if let Err(e) = consumer.pause().await {
+                        let e = HandlerError::Mediasoup(format!("failed to pause consumer: {}", e));
+                        sender.fail(Some(wsid), None, &e).await;
+                        return;
                     }
-
-                    let movement_producers = media_server.movementProducers.with(|mp| mp.clone());
-                    if let Some(movement_producer) = movement_producers.get(peer_id) {
-                        data_producers.remove(&movement_producer.id());
-                        media_server.movementProducers.with(|mp| mp.remove(peer_id));
+                    println!("mute consumer: {:?}", &data.consumerId);
+                });
+            }
+            MessageRequest::consumerResume { data } => {
+                tokio::spawn(async move {
+                    let consumer = match find_consumer(
+                        &media_server.peers,
+                        &media_server.plainTransports,
+                        data.consumerId,
+                    ) {
+                        Ok(consumer) => consumer,
+                        Err(e) => {
+                            println!(
+                                "cannot find consumer on this server: {:?}",
+                                &data.consumerId
+                            );
+                            sender.fail(Some(wsid), None, &e).await;
+                            return;
+                        }
+                    };
+                    if let Err(e) = consumer.resume().await {
+                        let e =
+                            HandlerError::Mediasoup(format!("failed to resume consumer: {}", e));
+                        sender.fail(Some(wsid), None, &e).await;
+                        return;
                     }
-
-                    let event_producers = media_server.eventProducers.with(|ep| ep.clone());
-                    if let Some(data_producer) = event_producers.get(peer_id) {
-                        data_producers.remove(&data_producer.id());
-                        media_server.eventProducers.with(|ep| ep.remove(peer_id));
+                    println!("unmute consumer: {:?}", &data.consumerId);
+                });
+            }
+            MessageRequest::setConsumerPreferredLayers { data } => {
+                tokio::spawn(async move {
+                    let consumer_id = data.consumerId;
+                    match set_consumer_preferred_layers(
+                        media_server.peers.clone(),
+                        media_server.plainTransports.clone(),
+                        data,
+                    )
+                    .await
+                    {
+                        Ok(_) => debug!("set preferred layers of consumer {}", consumer_id),
+                        Err(e) => sender.fail(Some(wsid), None, &e).await,
                     }
-
-                    let mut video_consumer = media_server.peerconsumed.lock().await;
-                    if video_consumer.get(peer_id).len() > 0 {
-                        for v in video_consumer.get(peer_id).into_iter() {
-                            consumers.remove(&v.consumer_id);
-                        }
-                        video_consumer.remove(peer_id);
+                });
+            }
+            MessageRequest::setConsumerPriority { data } => {
+                tokio::spawn(async move {
+                    let consumer_id = data.consumerId;
+                    match set_consumer_priority(
+                        media_server.peers.clone(),
+                        media_server.plainTransports.clone(),
+                        data,
+                    )
+                    .await
+                    {
+                        Ok(_) => debug!("set priority of consumer {}", consumer_id),
+                        Err(e) => sender.fail(Some(wsid), None, &e).await,
                     }
-
-                    let mut audio_consumer = media_server.peeraudioconsumed.lock().await;
-                    if audio_consumer.get(peer_id).len() > 0 {
-                        for a in audio_consumer.get(peer_id).into_iter() {
-                            consumers.remove(&a.consumer_id);
+                });
+            }
+            MessageRequest::createPlainEgress { data } => {
+                tokio::spawn(async move {
+                    match create_plain_egress(
+                        wsid.clone(),
+                        data,
+                        media_server.rooms.clone(),
+                        media_server.peers.clone(),
+                        media_server.plainTransports.clone(),
+                        media_server.config.clone(),
+                        sender.clone(),
+                    )
+                    .await
+                    {
+                        Ok(_) => info!("Successfully created plain egress"),
+                        Err(e) => {
+                            error!("failed to create plain egress: {:?}", e);
+                            sender.fail(Some(wsid), None, &e).await;
                         }
-                        audio_consumer.remove(peer_id);
                     }
-
-                    let mut movement_consumer = media_server.peermovementconsumed.lock().await;
-                    if movement_consumer.get(peer_id).len() > 0 {
-                        for m in movement_consumer.get(peer_id).into_iter() {
-                            data_consumer.remove(&m.consumer_id);
+                });
+            }
+            MessageRequest::createPlainIngress { data } => {
+                tokio::spawn(async move {
+                    match create_plain_ingress(
+                        wsid.clone(),
+                        data,
+                        media_server.rooms.clone(),
+                        media_server.relays.clone(),
+                        media_server.pipetransports.clone(),
+                        media_server.peers.clone(),
+                        media_server.audioObservers.clone(),
+                        media_server.signaling.clone(),
+                        media_server.plainTransports.clone(),
+                        media_server.config.clone(),
+                        sender.clone(),
+                    )
+                    .await
+                    {
+                        Ok(_) => info!("Successfully created plain ingress"),
+                        Err(e) => {
+                            error!("failed to create plain ingress: {:?}", e);
+                            sender.fail(Some(wsid), None, &e).await;
                         }
-
-                        movement_consumer.remove(peer_id);
                     }
-
-                    let mut event_consumer = media_server.peerdataconsumed.lock().await;
-                    if event_consumer.get(peer_id).len() > 0 {
-                        for e in event_consumer.get(peer_id).into_iter() {
-                            data_consumer.remove(&e.consumer_id);
+                });
+            }
+            MessageRequest::closePlainTransport { data } => {
+                tokio::spawn(async move {
+                    let plain = media_server
+                        .plainTransports
+                        .with(|p| p.remove(data.transportId));
+                    match plain {
+                        Some(plain) => {
+                            close_plain_transport(plain, media_server.peers.clone()).await
+                        }
+                        None => {
+                            let e = HandlerError::NotFound(format!(
+                                "cannot find plain transport: {}",
+                                data.transportId
+                            ));
+                            sender.fail(Some(wsid), None, &e).await;
                         }
-
-                        event_consumer.remove(peer_id);
                     }
-
+                });
+            }
+            MessageRequest::createBotPeer { data } => {
+                tokio::spawn(async move {
+                    match create_bot_peer(
+                        wsid.clone(),
+                        data,
+                        media_server.rooms.clone(),
+                        media_server.relays.clone(),
+                        media_server.pipetransports.clone(),
+                        media_server.botPeers.clone(),
+                        media_server.peers.clone(),
+                        media_server.config.clone(),
+                        sender.clone(),
+                    )
+                    .await
                     {
-                        let transport_clone =
-                            media_server.transports.with(|inner| inner.get(peer_id));
-                        if let Some(transport) = transport_clone {
-                            let worker_guard = media_server
-                                .transport2router
-                                .read()
-                                .await
-                                .get(transport.id())
-                                .clone();
-                            if let Some(router) = worker_guard {
-                                let worker_guard = media_server
-                                    .routers2workers
-                                    .lock()
-                                    .await
-                                    .get(router)
-                                    .clone();
-                                if let Some(worker) = worker_guard {
-                                    let mut loads_guard = media_server.loads.lock().await;
-                                    loads_guard.remove(worker, router);
-                                }
-                            }
+                        Ok(_) => info!("Successfully created bot peer"),
+                        Err(e) => {
+                            error!("failed to create bot peer: {:?}", e);
+                            sender.fail(Some(wsid), None, &e).await;
                         }
                     }
-                    media_server.transports.with(|inner| inner.remove(peer_id));
                 });
             }
-            MessageRequest::destroyRouterGroup { data } => {
+            MessageRequest::botSend { data } => {
                 tokio::spawn(async move {
-                    info!("destorying room router {:?}", &data.room);
-                    let room_name = data.room;
-                    let mut room_router = media_server.roomRouters.lock().await;
-                    if room_router.get(room_name.clone()).is_none() {
-                        return;
+                    if let Err(e) = bot_send(
+                        data,
+                        media_server.botPeers.clone(),
+                        media_server.peers.clone(),
+                        &media_server.config,
+                    ) {
+                        error!("failed to send as bot peer: {:?}", e);
+                        sender.fail(Some(wsid), None, &e).await;
                     }
-
-                    let mut pending_relay_guard = media_server.pendingRelays.lock().await;
-                    let mut relays_guard = media_server.relays.lock().await;
-                    let pipetransports_guard = media_server.pipetransports.with(|p| p.clone());
-                    let mut relay_routers_guard = media_server.relayRouters.lock().await;
-                    let mut routers2workers_guard = media_server.routers2workers.lock().await;
-                    let mut loads_guard = media_server.loads.lock().await;
-                    let mut routers_guard = media_server.routers.lock().await;
-                    for router in room_router.get(room_name.clone()).unwrap().into_iter() {
-                        // egress cleaning up
-                        {
-                            let get_relay_router = relay_routers_guard.get_by_egress(router.id());
-                            if get_relay_router.is_some() {
-                                relay_routers_guard.delete(get_relay_router.unwrap());
-                                pending_relay_guard.delete(get_relay_router.unwrap());
-                                let get_pipetransport =
-                                    relays_guard.get_router(get_relay_router.unwrap());
-                                if !get_pipetransport.is_empty() {
-                                    media_server
-                                        .pipetransports
-                                        .with(|p| p.delete(get_pipetransport[0].transport));
-                                }
-
-                                relays_guard.delete(get_relay_router.unwrap());
-                            }
+                });
+            }
+            MessageRequest::botConsume { data } => {
+                tokio::spawn(async move {
+                    match bot_consume(
+                        wsid.clone(),
+                        data,
+                        media_server.botPeers.clone(),
+                        media_server.peers.clone(),
+                        media_server.signaling.clone(),
+                    )
+                    .await
+                    {
+                        Ok(_) => debug!("bot peer consuming"),
+                        Err(e) => {
+                            error!("failed to consume as bot peer: {:?}", e);
+                            sender.fail(Some(wsid), None, &e).await;
                         }
-                        // ingress cleaning up
-                        {
-                            let get_relays = relays_guard.get_router(router.id());
-                            if !get_relays.is_empty() {
-                                let get_pipetransport =
-                                    pipetransports_guard.get(get_relays[0].transport);
-                                if get_pipetransport.is_some() {
-                                    media_server
-                                        .pipetransports
-                                        .with(|p| p.delete(get_relays[0].transport));
-                                }
-                                relay_routers_guard.delete(get_relays[0].router);
-                                relays_guard.delete(get_relays[0].router);
-                            }
+                    }
+                });
+            }
+            MessageRequest::closeBotPeer { data } => {
+                tokio::spawn(async move {
+                    let bot = media_server.botPeers.with(|b| b.remove(data.peerId));
+                    match bot {
+                        Some(bot) => {
+                            close_bot_peer(
+                                bot,
+                                media_server.peers.clone(),
+                                media_server.loads.clone(),
+                            )
+                            .await
                         }
-                        // pipe relay clean up
-                        // remove rotuer2workers
-                        {
-                            if let Some(worker) = routers2workers_guard.get(router.id()) {
-                                routers2workers_guard.delete(router.id());
-                                loads_guard.remove(worker, router.id());
-                            }
-                            routers_guard.remove(router.id());
+                        None => {
+                            let e = HandlerError::NotFound(format!(
+                                "cannot find bot peer: {}",
+                                data.peerId
+                            ));
+                            sender.fail(Some(wsid), None, &e).await;
                         }
                     }
-                    // deep clean up
+                });
+            }
+            MessageRequest::getTransportStats { data } => {
+                tokio::spawn(async move {
+                    if let Err(e) = get_transport_stats(
+                        wsid.clone(),
+                        data,
+                        media_server.peers.clone(),
+                        sender.clone(),
+                    )
+                    .await
                     {
-                        if let Some(pipe_transport) = media_server
-                            .pipetransports
-                            .with(|p| p.get_by_room(room_name.clone()))
-                        {
-                            for pipe in pipe_transport.into_iter() {
-                                println!("deep clean pipe Transports: {:?}", &pipe);
-                                media_server
-                                    .pipetransports
-                                    .with(|p| p.delete(pipe.transport_id));
-                            }
-                        }
-                        if let Some(relay) = relays_guard.get_by_room(room_name.clone()) {
-                            for r in relay.into_iter() {
-                                relays_guard.delete(r.router);
-                            }
-                        }
+                        error!("failed to get transport stats: {:?}", e);
+                        sender.fail(Some(wsid), None, &e).await;
+                    }
+                });
+            }
+            MessageRequest::getProducerStats { data } => {
+                tokio::spawn(async move {
+                    if let Err(e) = get_producer_stats(
+                        wsid.clone(),
+                        data,
+                        media_server.peers.clone(),
+                        sender.clone(),
+                    )
+                    .await
+                    {
+                        error!("failed to get producer stats: {:?}", e);
+                        sender.fail(Some(wsid), None, &e).await;
                     }
-                    println!("pipe transports guard {:?}", &pipetransports_guard);
-                    let pipes_log = media_server.pipetransports.with(|p| p.clone());
-                    println!("with pipe transports: {:?}", pipes_log);
+                });
+            }
+            MessageRequest::getConsumerStats { data } => {
+                tokio::spawn(async move {
+                    if let Err(e) = get_consumer_stats(
+                        wsid.clone(),
+                        data,
+                        media_server.peers.clone(),
+                        media_server.plainTransports.clone(),
+                        sender.clone(),
+                    )
+                    .await
                     {
-                        room_router.remove(room_name);
+                        error!("failed to get consumer stats: {:?}", e);
+                        sender.fail(Some(wsid), None, &e).await;
                     }
                 });
             }
-            MessageRequest::consumerPause { data } => {
+            MessageRequest::startRecording { data } => {
                 tokio::spawn(async move {
-                    let consumer_guard = media_server.consumers.lock().await;
-                    let consumer = consumer_guard.get(&data.consumerId);
-                    if consumer.is_none() {
-                        println!(
-                            "cannot find consumer on this server: {:?}",
-                            &data.consumerId
-                        );
-                        return;
+                    match start_recording(
+                        wsid.clone(),
+                        data,
+                        media_server.rooms.clone(),
+                        media_server.peers.clone(),
+                        media_server.recordings.clone(),
+                        media_server.config.clone(),
+                        sender.clone(),
+                    )
+                    .await
+                    {
+                        Ok(_) => info!("Successfully started recording"),
+                        Err(e) => {
+                            error!("failed to start recording: {:?}", e);
+                            sender.fail(Some(wsid), None, &e).await;
+                        }
                     }
-                    let _ = consumer.unwrap().pause().await;
-                    println!("mute consumer: {:?}", &data.consumerId);
                 });
             }
-            MessageRequest::consumerResume { data } => {
+            MessageRequest::stopRecording { data } => {
                 tokio::spawn(async move {
-                    let consumer_guard = media_server.consumers.lock().await;
-                    let consumer = consumer_guard.get(&data.consumerId);
-                    if consumer.is_none() {
-                        println!(
-                            "cannot find consumer on this server: {:?}",
-                            &data.consumerId
-                        );
-                        return;
+                    match stop_recording(
+                        wsid.clone(),
+                        data,
+                        media_server.recordings.clone(),
+                        sender.clone(),
+                    )
+                    .await
+                    {
+                        Ok(_) => info!("Successfully stopped recording"),
+                        Err(e) => {
+                            error!("failed to stop recording: {:?}", e);
+                            sender.fail(Some(wsid), None, &e).await;
+                        }
                     }
-                    let _ = consumer.unwrap().resume().await;
-                    println!("unmute consumer: {:?}", &data.consumerId);
                 });
             }
             MessageRequest::producerPause { data } => {
                 tokio::spawn(async move {
-                    let producer_guard = media_server.audioProducers.lock().await;
-                    let producer = producer_guard.get_producer(data.peerId, data.producerId);
+                    let producer = media_server.peers.with(|p| {
+                        p.get(data.peerId)
+                            .and_then(|peer| peer.producer(data.producerId))
+                    });
 
                     if producer.is_none() {
                         println!("cannot find producer");
+                        let e = HandlerError::NotFound(format!(
+                            "cannot find producer: {}",
+                            data.producerId
+                        ));
+                        sender.fail(Some(wsid), None, &e).await;
                         return;
                     };
                     let producer = producer.unwrap();
@@ -563,6 +756,7 @@ pub async fn handle_request_message(
                     }
                     let message = ResponseMessage::OutgoingCommunication {
                         ws: Some(wsid),
+                        requestId: None,
                         communication: MessageResponse::producerPaused {
                             data: ProducerReplyMuteData {
                                 peerId: data.peerId,
@@ -579,11 +773,18 @@ pub async fn handle_request_message(
             }
             MessageRequest::producerResume { data } => {
                 tokio::spawn(async move {
-                    let audio_proudcer_guard = media_server.audioProducers.lock().await;
-                    let producer = audio_proudcer_guard.get_producer(data.peerId, data.producerId);
+                    let producer = media_server.peers.with(|p| {
+                        p.get(data.peerId)
+                            .and_then(|peer| peer.producer(data.producerId))
+                    });
 
                     if producer.is_none() {
                         println!("cannot find producer");
+                        let e = HandlerError::NotFound(format!(
+                            "cannot find producer: {}",
+                            data.producerId
+                        ));
+                        sender.fail(Some(wsid), None, &e).await;
                         return;
                     };
                     let producer = producer.unwrap();
@@ -601,6 +802,7 @@ pub async fn handle_request_message(
                     }
                     let message = ResponseMessage::OutgoingCommunication {
                         ws: Some(wsid),
+                        requestId: None,
                         communication: MessageResponse::producerResume {
                             data: ProducerReplyMuteData {
                                 peerId: data.peerId,
@@ -620,29 +822,39 @@ pub async fn handle_request_message(
                     let peer_id = data.peerId;
                     let producer_id = data.producerId;
                     let media_type = data.mediaType;
-                    let mut video_producers_guard = media_server.videoProducers.lock().await;
                     match media_type.as_str() {
-                        "screenVideo" | "webCam" => {
-                            video_producers_guard.remove_producer(peer_id, producer_id);
-                        }
-                        "audio" | "screenAudio" => {
-                            video_producers_guard.remove_producer(peer_id, producer_id);
+                        "screenVideo" | "webCam" | "audio" | "screenAudio" => {
+                            // dropped outside the lock, closing the producer
+                            let _closed = media_server.peers.with(|p| {
+                                p.get_mut(peer_id)
+                                    .and_then(|peer| peer.remove_producer(producer_id))
+                            });
                         }
                         _ => println!("none found!"),
                     }
                 });
             }
             MessageRequest::restartIce { data } => {
-                let transport = media_server.transports.with(|inner| inner.get(data.peerId));
+                let transport = media_server.peers.with(|p| p.transport(data.peerId));
 
                 if transport.is_none() {
                     println!("Cannot find transport {:?} for restartIce", data.peerId);
+                    let e = HandlerError::NotFound(format!(
+                        "cannot find transport for peer: {}",
+                        data.peerId
+                    ));
+                    sender.fail(Some(wsid), None, &e).await;
                     return;
                 }
 
                 let transport = transport.unwrap();
                 if transport.id() != data.transportId {
                     println!("Found transport {:?} but it does not match the transportId {:?} for restartIce", transport.id(), data.transportId);
+                    let e = HandlerError::InvalidRequest(format!(
+                        "transport {} does not match peer transport",
+                        data.transportId
+                    ));
+                    sender.fail(Some(wsid), None, &e).await;
                     return;
                 }
 
@@ -650,6 +862,7 @@ pub async fn handle_request_message(
                     Ok(ice_parameters) => {
                         let message = ResponseMessage::OutgoingCommunication {
                             ws: Some(wsid),
+                            requestId: None,
                             communication: MessageResponse::restartedIce {
                                 data: RestartedIceData {
                                     transportId: data.transportId,
@@ -669,17 +882,20 @@ pub async fn handle_request_message(
                         print!(
                             "Error restarting ICE for {:?}: {:?}",
                             data.transportId, error
-                        )
+                        );
+                        let e =
+                            HandlerError::Mediasoup(format!("failed to restart ice: {}", error));
+                        sender.fail(Some(wsid), None, &e).await;
                     }
                 }
             }
-            _ => error!("\n nothing matched: {:?}", &message),
+            _ => {
+                error!("\n nothing matched: {:?}", &message);
+                let e = HandlerError::InvalidRequest(String::from("unsupported client request"));
+                sender.fail(Some(wsid), None, &e).await;
+            }
         },
-        RequestMessage::IncomingServer {
-            node: _,
-            wsid: _,
-            message,
-        } => match message {
+        RequestMessage::IncomingServer { message, .. } => match message {
             MessageRequest::storePipeRelay { data } => {
                 tokio::spawn(async move {
                     match store_pipe_relay(
@@ -692,7 +908,10 @@ pub async fn handle_request_message(
                     .await
                     {
                         Ok(_) => debug!("Successfully stored pipRelay"),
-                        Err(_) => debug!("failed stored pipRelay"),
+                        Err(e) => {
+                            debug!("failed stored pipRelay");
+                            sender.fail(None, node, &e).await;
+                        }
                     }
                 });
             }
@@ -711,7 +930,7 @@ pub async fn handle_request_message(
                         data.ingressRoute.clone(),
                         media_server.relays.clone(),
                         media_server.routers.clone(),
-                        media_server.roomRouters.clone(),
+                        media_server.rooms.clone(),
                         media_server.routers2workers.clone(),
                         media_server.relayRouters.clone(),
                         media_server.pipetransports.clone(),
@@ -734,24 +953,27 @@ pub async fn handle_request_message(
                                 data.appData,
                                 media_server.relays.clone(),
                                 media_server.pipetransports.clone(),
-                                media_server.roomRouters.clone(),
+                                media_server.rooms.clone(),
                                 media_server.relayRouters.clone(),
                                 media_server.routers.clone(),
-                                media_server.audioProducers.clone(),
-                                media_server.videoProducers.clone(),
+                                media_server.peers.clone(),
+                                media_server.audioObservers.clone(),
+                                media_server.signaling.clone(),
                                 config.clone(),
-                                media_server.producers.clone(),
                                 sender.clone(),
                             )
                             .await;
-                            if let Ok(_) = producer_relay {
-                                debug!("created producer relay for: {:?}", data.peerId);
-                            } else {
-                                error!(
-                                    "failed to create producer relay: {:?} {:?}",
-                                    data.peerId,
-                                    producer_relay.err()
-                                );
+                            match producer_relay {
+                                Ok(_) => {
+                                    debug!("created producer relay for: {:?}", data.peerId)
+                                }
+                                Err(e) => {
+                                    error!(
+                                        "failed to create producer relay: {:?} {:?}",
+                                        data.peerId, e
+                                    );
+                                    sender.fail(None, node, &e).await;
+                                }
                             }
                         } else if data.dataProducerId.is_some() {
                             let data_producerid = data.dataProducerId.clone();
@@ -759,14 +981,27 @@ pub async fn handle_request_message(
                             let sctp_stream_parameters = data.sctpStreamParameters.clone();
                             if label.is_none() {
                                 println!("data label was not supply by client side");
+                                let e = HandlerError::InvalidRequest(String::from(
+                                    "data label was not supplied",
+                                ));
+                                sender.fail(None, node, &e).await;
                                 return;
                             }
                             if data_producerid.is_none() {
                                 println!("data producerId was not supply by client side");
+                                let e = HandlerError::InvalidRequest(String::from(
+                                    "data producerId was not supplied",
+                                ));
+                                sender.fail(None, node, &e).await;
                                 return;
                             }
                             if sctp_stream_parameters.is_none() {
                                 println!("sctpStreamParameters was not supply by client side");
+                                let e = HandlerError::InvalidRequest(String::from(
+                                    "sctpStreamParameters was not supplied",
+                                ));
+                                sender.fail(None, node, &e).await;
+                                return;
                             }
                             let produce_data_relay = create_relay_datachannel_producer(
                                 data.ingressRoute,
@@ -777,29 +1012,31 @@ pub async fn handle_request_message(
                                 sctp_stream_parameters.unwrap(),
                                 data.appData,
                                 media_server.routers.clone(),
-                                media_server.roomRouters.clone(),
+                                media_server.rooms.clone(),
                                 media_server.relays.clone(),
                                 media_server.pipetransports.clone(),
-                                media_server.eventProducers.clone(),
-                                media_server.movementProducers.clone(),
+                                media_server.peers.clone(),
+                                media_server.dataTaps.clone(),
+                                media_server.signaling.clone(),
                                 media_server.relayRouters.clone(),
-                                media_server.data_producers.clone(),
                                 config.clone(),
                                 sender.clone(),
                             )
                             .await;
-                            if let Ok(_) = produce_data_relay {
-                                debug!("data producer relay created Successfully");
-                            } else {
-                                error!(
-                                    "failed to create data producer relay: {:?} {:?}",
-                                    data.peerId,
-                                    produce_data_relay.err()
-                                );
+                            match produce_data_relay {
+                                Ok(_) => debug!("data producer relay created Successfully"),
+                                Err(e) => {
+                                    error!(
+                                        "failed to create data producer relay: {:?} {:?}",
+                                        data.peerId, e
+                                    );
+                                    sender.fail(None, node, &e).await;
+                                }
                             }
                         }
-                    } else {
-                        error!("created relay does not have producerId or dataProducerId");
+                    } else if let Err(e) = new_egress_relay {
+                        error!("failed to create egress relay: {:?}", e);
+                        sender.fail(None, node, &e).await;
                     }
                 });
             }
@@ -815,14 +1052,42 @@ pub async fn handle_request_message(
                         media_server.relays.clone(),
                     )
                     .await;
-                    if let Ok(_) = connect_relay {
-                        debug!("connect pip relay Successfully");
-                    } else {
-                        error!("failed to connect pip relay");
+                    match connect_relay {
+                        Ok(_) => debug!("connect pip relay Successfully"),
+                        Err(HandlerError::AlreadyExists(reason)) => debug!("{}", reason),
+                        Err(e) => {
+                            error!("failed to connect pip relay: {:?}", e);
+                            sender.fail(None, node, &e).await;
+                        }
                     }
                 });
             }
-            _ => error!("\n nothing matched: {:?}", &message),
+            MessageRequest::drainNode { data } => {
+                let timeout = data
+                    .timeout
+                    .map(Duration::from_secs)
+                    .unwrap_or(config.drain_timeout);
+                if start_drain(&media_server, timeout) {
+                    info!("draining node, exiting within {:?}", timeout);
+                }
+                let rooms = media_server.rooms.lock().await.0.keys().cloned().collect();
+                let msg = ResponseMessage::OutgoingServer {
+                    node,
+                    requestId: None,
+                    message: MessageResponse::nodeDraining {
+                        timeout: timeout.as_secs(),
+                        rooms,
+                    },
+                };
+                if let Err(e) = sender.send(msg).await {
+                    error!("error sending nodeDraining: {:?}", e);
+                }
+            }
+            _ => {
+                error!("\n nothing matched: {:?}", &message);
+                let e = HandlerError::InvalidRequest(String::from("unsupported server request"));
+                sender.fail(None, node, &e).await;
+            }
         },
     }
 }
@@ -0,0 +1,285 @@
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use log::{error, info};
+use mediasoup::rtp_parameters::MediaKind;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::{server::models::MediaServer, utils::utils::Mut};
+
+// Counters updated while the node runs, gauges are read from MediaServer when scraped.
+#[derive(Debug)]
+pub struct Metrics {
+    // messages waiting in QueuedWrite to be written to signaling
+    pub queue_depth: AtomicUsize,
+    handlers: Mut<HashMap<&'static str, HandlerStats>>,
+}
+
+#[derive(Debug, Default, Clone)]
+struct HandlerStats {
+    requests: u64,
+    errors: u64,
+    seconds: f64,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self {
+            queue_depth: AtomicUsize::new(0),
+            handlers: Mut::new(HashMap::new()),
+        }
+    }
+
+    pub fn observe(&self, request: &'static str, seconds: f64) {
+        self.handlers.with(|h| {
+            let stats = h.entry(request).or_default();
+            stats.requests += 1;
+            stats.seconds += seconds;
+        });
+    }
+
+    pub fn error(&self, request: &'static str) {
+        self.handlers
+            .with(|h| h.entry(request).or_default().errors += 1);
+    }
+}
+
+// Measures a request from the moment it is received until the last clone of
+// its Responder is dropped, i.e. until every task handling it is done.
+#[derive(Debug)]
+pub struct RequestTimer {
+    metrics: Arc<Metrics>,
+    request: &'static str,
+    start: Instant,
+}
+
+impl RequestTimer {
+    pub fn new(metrics: Arc<Metrics>, request: &'static str) -> Self {
+        Self {
+            metrics,
+            request,
+            start: Instant::now(),
+        }
+    }
+
+    pub fn error(&self) {
+        self.metrics.error(self.request);
+    }
+}
+
+impl Drop for RequestTimer {
+    fn drop(&mut self) {
+        self.metrics
+            .observe(self.request, self.start.elapsed().as_secs_f64());
+    }
+}
+
+// Serve the prometheus text format on every path of the given port.
+pub async fn serve_metrics(port: u16, media_server: MediaServer) {
+    let addr = SocketAddr::from(([0, 0, 0, 0], port));
+    let listener = match TcpListener::bind(addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("cannot listen for metrics on {}: {:?}", addr, e);
+            return;
+        }
+    };
+    info!("serving metrics on {}", addr);
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                let media_server = media_server.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = reply_metrics(stream, media_server).await {
+                        error!("error serving metrics: {:?}", e);
+                    }
+                });
+            }
+            Err(e) => error!("metrics accept error: {:?}", e),
+        }
+    }
+}
+
+async fn reply_metrics(mut stream: TcpStream, media_server: MediaServer) -> std::io::Result<()> {
+    // the request itself is not inspected, scrapers only ever GET
+    let mut buf = [0u8; 1024];
+    let _ = stream.read(&mut buf).await?;
+    let body = render_metrics(&media_server).await;
+    let response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+pub async fn render_metrics(media_server: &MediaServer) -> String {
+    let mut out = String::new();
+
+    let rooms = media_server.rooms.lock().await.0.len();
+    gauge(&mut out, "frame_rooms", "Rooms with routers on this node");
+    let _ = writeln!(out, "frame_rooms {}", rooms);
+
+    gauge(&mut out, "frame_worker_routers", "Routers per worker");
+    let mut routers_per_worker: HashMap<String, usize> = HashMap::new();
+    for worker in media_server.workers.read().await.0.iter() {
+        routers_per_worker.insert(worker.id().to_string(), 0);
+    }
+    for worker_id in media_server.routers2workers.lock().await.0.values() {
+        *routers_per_worker.entry(worker_id.to_string()).or_default() += 1;
+    }
+    for (worker, count) in routers_per_worker.iter() {
+        let _ = writeln!(
+            out,
+            "frame_worker_routers{{worker=\"{}\"}} {}",
+            worker, count
+        );
+    }
+
+    gauge(
+        &mut out,
+        "frame_worker_cpu_percent",
+        "Cpu used by each worker thread",
+    );
+    for (worker, load) in media_server.loads.lock().await.workers.iter() {
+        let _ = writeln!(
+            out,
+            "frame_worker_cpu_percent{{worker=\"{}\"}} {}",
+            worker, load.cpu
+        );
+    }
+
+    let transports = media_server
+        .peers
+        .with(|p| p.iter().filter(|peer| peer.transport.is_some()).count());
+    gauge(&mut out, "frame_webrtc_transports", "WebRTC transports");
+    let _ = writeln!(out, "frame_webrtc_transports {}", transports);
+
+    let (producers, consumers, data_producers, data_consumers) = media_server.peers.with(|p| {
+        let producers: Vec<MediaKind> = p
+            .iter()
+            .flat_map(|peer| peer.producers.iter().map(|p| p.kind()))
+            .collect();
+        let consumers: Vec<MediaKind> = p
+            .iter()
+            .flat_map(|peer| peer.consumers.iter().chain(peer.relay_consumers.iter()))
+            .map(|c| c.kind())
+            .collect();
+        let data_producers: usize = p.iter().map(|peer| peer.data_producers.len()).sum();
+        let data_consumers: usize = p.iter().map(|peer| peer.data_consumers.len()).sum();
+        (producers, consumers, data_producers, data_consumers)
+    });
+    let (mut audio, mut video) = (0, 0);
+    for kind in producers {
+        match kind {
+            MediaKind::Audio => audio += 1,
+            MediaKind::Video => video += 1,
+        }
+    }
+    gauge(&mut out, "frame_producers", "Producers by kind");
+    let _ = writeln!(out, "frame_producers{{kind=\"audio\"}} {}", audio);
+    let _ = writeln!(out, "frame_producers{{kind=\"video\"}} {}", video);
+
+    let (mut audio, mut video) = (0, 0);
+    for kind in consumers {
+        match kind {
+            MediaKind::Audio => audio += 1,
+            MediaKind::Video => video += 1,
+        }
+    }
+    gauge(&mut out, "frame_consumers", "Consumers by kind");
+    let _ = writeln!(out, "frame_consumers{{kind=\"audio\"}} {}", audio);
+    let _ = writeln!(out, "frame_consumers{{kind=\"video\"}} {}", video);
+
+    gauge(&mut out, "frame_data_producers", "Data producers");
+    let _ = writeln!(out, "frame_data_producers {}", data_producers);
+
+    gauge(&mut out, "frame_data_consumers", "Data consumers");
+    let _ = writeln!(out, "frame_data_consumers {}", data_consumers);
+
+    let (connected, pending) = media_server.pipetransports.with(|p| p.count_by_state());
+    gauge(
+        &mut out,
+        "frame_pipe_relays",
+        "Pipe relays by connected state",
+    );
+    let _ = writeln!(
+        out,
+        "frame_pipe_relays{{state=\"connected\"}} {}",
+        connected
+    );
+    let _ = writeln!(out, "frame_pipe_relays{{state=\"pending\"}} {}", pending);
+
+    let metrics = &media_server.metrics;
+    gauge(
+        &mut out,
+        "frame_signaling_queue_depth",
+        "Messages queued for signaling",
+    );
+    let _ = writeln!(
+        out,
+        "frame_signaling_queue_depth {}",
+        metrics.queue_depth.load(Ordering::Relaxed)
+    );
+
+    let handlers = metrics.handlers.with(|h| h.clone());
+    let _ = writeln!(
+        out,
+        "# HELP frame_handler_requests_total Requests handled per message type"
+    );
+    let _ = writeln!(out, "# TYPE frame_handler_requests_total counter");
+    for (request, stats) in handlers.iter() {
+        let _ = writeln!(
+            out,
+            "frame_handler_requests_total{{request=\"{}\"}} {}",
+            request, stats.requests
+        );
+    }
+    let _ = writeln!(
+        out,
+        "# HELP frame_handler_errors_total Requests answered with requestFailed per message type"
+    );
+    let _ = writeln!(out, "# TYPE frame_handler_errors_total counter");
+    for (request, stats) in handlers.iter() {
+        let _ = writeln!(
+            out,
+            "frame_handler_errors_total{{request=\"{}\"}} {}",
+            request, stats.errors
+        );
+    }
+    let _ = writeln!(
+        out,
+        "# HELP frame_handler_duration_seconds Time spent handling requests per message type"
+    );
+    let _ = writeln!(out, "# TYPE frame_handler_duration_seconds summary");
+    for (request, stats) in handlers.iter() {
+        let _ = writeln!(
+            out,
+            "frame_handler_duration_seconds_sum{{request=\"{}\"}} {}",
+            request, stats.seconds
+        );
+        let _ = writeln!(
+            out,
+            "frame_handler_duration_seconds_count{{request=\"{}\"}} {}",
+            request, stats.requests
+        );
+    }
+    out
+}
+
+fn gauge(out: &mut String, name: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} gauge", name);
+}
@@ -1,4 +1,18 @@
+pub mod auth;
+pub mod capacity;
+pub mod drain;
+pub mod interest;
 pub mod message_handle;
+pub mod metrics;
 pub mod models;
 pub mod register_server;
+pub mod rooms;
+pub mod snapshot;
+pub mod stats;
 pub mod stream;
+pub mod supervisor;
+pub mod test_auth;
+pub mod test_capacity;
+pub mod test_interest;
+pub mod test_metrics;
+pub mod tls;
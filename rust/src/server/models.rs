@@ -1,23 +1,33 @@
 #![allow(non_camel_case_types, non_snake_case)]
 
-use std::{collections::HashMap, net::IpAddr, sync::Arc};
+use std::{
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 
-use tokio::sync::{Mutex, RwLock};
+use tokio::sync::{mpsc::Sender, Mutex, RwLock};
 use uuid::Uuid;
 
 use crate::{
     config::config::Config,
-    models::sfu::{
-        AudioProducers, Endpoints, EventProducers, Loads, MovementProducers, PeerAudioConsumed,
-        PeerConsumed, PeerDataConsumed, PeerMovementConsumed, PendingRelays, PipeTransports,
-        RelayRouters, Relays, RoomRouters, RouterIndex, Routers, Routers2Worker, Transport2Router,
-        Transports, VideoProducers, WebrtcServers, WorkerLoads, Workers,
+    models::{
+        peer::Peers,
+        room::Rooms,
+        sfu::{
+            AudioObservers, BotPeers, DataTaps, Loads, PendingRelays, PipeTransports,
+            PlainTransports, Recordings, RelayRouters, Relays, Routers, Routers2Worker,
+            Transport2Router, WebrtcServers, Workers,
+        },
+    },
+    server::metrics::Metrics,
+    utils::{
+        codec::{NodeCapacity, ResponseMessage},
+        utils::Mut,
     },
-    utils::utils::Mut,
 };
-use mediasoup::data_producer::{DataProducer, DataProducerId};
-use mediasoup::prelude::{Consumer, ConsumerId, DataConsumer, DataConsumerId};
-use mediasoup::producer::{Producer, ProducerId};
 
 #[derive(Debug, Clone)]
 pub struct MediaServer {
@@ -26,33 +36,32 @@ pub struct MediaServer {
     pub egress: Option<Uuid>,
     pub num_workers: Option<i32>,
     pub announceip: Option<IpAddr>,
-    pub roomRouters: Arc<Mutex<RoomRouters>>,
+    pub rooms: Arc<Mutex<Rooms>>,
     pub routers: Arc<Mutex<Routers>>,
-    pub transports: Arc<Mut<Transports>>,
+    // transports, producers, consumers and data channels of every peer
+    pub peers: Arc<Mut<Peers>>,
     pub transport2router: Arc<RwLock<Transport2Router>>,
     pub relays: Arc<Mutex<Relays>>,
-    pub endpoints: Arc<Mutex<Endpoints>>,
-    pub peerconsumed: Arc<Mutex<PeerConsumed>>,
-    pub peerdataconsumed: Arc<Mutex<PeerDataConsumed>>,
-    pub peermovementconsumed: Arc<Mutex<PeerMovementConsumed>>,
-    pub peeraudioconsumed: Arc<Mutex<PeerAudioConsumed>>,
     pub pipetransports: Arc<Mut<PipeTransports>>,
     pub pendingRelays: Arc<Mutex<PendingRelays>>,
-    pub audioProducers: Arc<Mutex<AudioProducers>>,
-    pub videoProducers: Arc<Mutex<VideoProducers>>,
-    pub movementProducers: Arc<Mut<MovementProducers>>,
-    pub eventProducers: Arc<Mut<EventProducers>>,
+    pub audioObservers: Arc<Mutex<AudioObservers>>,
+    // movement read back on this node when interest management is enabled
+    pub dataTaps: Arc<Mut<DataTaps>>,
+    pub plainTransports: Arc<Mut<PlainTransports>>,
+    pub botPeers: Arc<Mut<BotPeers>>,
+    pub recordings: Arc<Mutex<Recordings>>,
     pub relayRouters: Arc<Mutex<RelayRouters>>,
-    pub producers: Arc<Mutex<HashMap<ProducerId, Producer>>>,
-    pub consumers: Arc<Mutex<HashMap<ConsumerId, Consumer>>>,
-    pub data_producers: Arc<Mutex<HashMap<DataProducerId, DataProducer>>>,
-    pub data_consumer: Arc<Mutex<HashMap<DataConsumerId, DataConsumer>>>,
-    pub workerloads: WorkerLoads,
     pub workers: Arc<RwLock<Workers>>,
     pub routers2workers: Arc<Mutex<Routers2Worker>>,
-    pub router_index: RouterIndex,
     pub loads: Arc<Mutex<Loads>>,
     pub webrtc_server: Arc<RwLock<WebrtcServers>>,
+    // sender of the current signaling connection, for messages not tied to a request
+    pub signaling: Arc<Mut<Option<Sender<ResponseMessage>>>>,
+    pub metrics: Arc<Metrics>,
+    // last capacity sampled for serverLoad
+    pub capacity: Arc<Mut<NodeCapacity>>,
+    // set once drainNode or SIGTERM is received, never cleared
+    pub draining: Arc<AtomicBool>,
 }
 
 impl MediaServer {
@@ -62,35 +71,32 @@ impl MediaServer {
             announceip: Some(config.announceip),
             egress: config.egress,
             num_workers: Some(config.workers),
-            roomRouters: Arc::new(Mutex::new(RoomRouters::new())),
+            rooms: Arc::new(Mutex::new(Rooms::new())),
             routers: Arc::new(Mutex::new(Routers::new())),
-            // peerTranports: PeerTransports::new(),
-            transports: Arc::new(Mut::new(Transports::new())),
+            peers: Arc::new(Mut::new(Peers::new())),
             transport2router: Arc::new(RwLock::new(Transport2Router::new())),
             relays: Arc::new(Mutex::new(Relays::new())),
-            endpoints: Arc::new(Mutex::new(Endpoints::new())),
-            peerconsumed: Arc::new(Mutex::new(PeerConsumed::new())),
-            peerdataconsumed: Arc::new(Mutex::new(PeerDataConsumed::new())),
-            peermovementconsumed: Arc::new(Mutex::new(PeerMovementConsumed::new())),
-            peeraudioconsumed: Arc::new(Mutex::new(PeerAudioConsumed::new())),
             pipetransports: Arc::new(Mut::new(PipeTransports::new())),
             pendingRelays: Arc::new(Mutex::new(PendingRelays::new())),
-            audioProducers: Arc::new(Mutex::new(AudioProducers::new())),
-            videoProducers: Arc::new(Mutex::new(VideoProducers::new())),
-            movementProducers: Arc::new(Mut::new(MovementProducers::new())),
-            eventProducers: Arc::new(Mut::new(EventProducers::new())),
+            audioObservers: Arc::new(Mutex::new(AudioObservers::new())),
+            dataTaps: Arc::new(Mut::new(DataTaps::new())),
+            plainTransports: Arc::new(Mut::new(PlainTransports::new())),
+            botPeers: Arc::new(Mut::new(BotPeers::new())),
+            recordings: Arc::new(Mutex::new(Recordings::new())),
             relayRouters: Arc::new(Mutex::new(RelayRouters::new())),
-            producers: Arc::new(Mutex::new(HashMap::new())),
-            consumers: Arc::new(Mutex::new(HashMap::new())),
-            data_producers: Arc::new(Mutex::new(HashMap::new())),
-            data_consumer: Arc::new(Mutex::new(HashMap::new())),
-            workerloads: WorkerLoads::new(),
             workers: Arc::new(RwLock::new(Workers::new())),
             routers2workers: Arc::new(Mutex::new(Routers2Worker::new())),
-            router_index: RouterIndex::new(),
-            loads: Arc::new(Mutex::new(Loads::new())),
+            loads: Arc::new(Mutex::new(Loads::new(config.load_balancing))),
             config,
             webrtc_server: Arc::new(RwLock::new(WebrtcServers::new())),
+            signaling: Arc::new(Mut::new(None)),
+            metrics: Arc::new(Metrics::new()),
+            capacity: Arc::new(Mut::new(NodeCapacity::default())),
+            draining: Arc::new(AtomicBool::new(false)),
         }
     }
+
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::Relaxed)
+    }
 }
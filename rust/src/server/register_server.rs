@@ -3,29 +3,33 @@ use std::{net::IpAddr, str::FromStr};
 use uuid::Uuid;
 
 use crate::{
+    config::config::Mode,
     models::sfu::RegionResponse,
-    utils::{self, codec::ResponseMessage},
+    utils::{
+        self,
+        codec::{Encoding, ResponseMessage},
+    },
 };
 
 pub async fn register_server(
-    mode_check: Option<Uuid>,
+    mode: Mode,
     _annouced_ip: IpAddr,
     node_id: Option<Uuid>,
     region: String,
+    draining: bool,
 ) -> Result<RegionResponse, String> {
-    let mode = match mode_check {
-        Some(_) => "ingress",
-        None => "egress",
-    };
     println!("mode: {}", &mode);
     let _is_local = IpAddr::from_str("127.0.0.1").unwrap();
     //if annouced_ip.unwrap() != is_local {
 
     let msg = ResponseMessage::OutgoingServer {
         node: node_id,
+        requestId: None,
         message: utils::codec::MessageResponse::registerMediaServer {
             mode: mode.to_string(),
             region: region.clone(),
+            encodings: Encoding::supported(),
+            draining,
         },
     };
     let resp = RegionResponse {
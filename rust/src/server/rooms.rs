@@ -0,0 +1,215 @@
+use std::time::Duration;
+
+use log::{error, info};
+use mediasoup::router::RouterId;
+use tokio::time::interval;
+use uuid::Uuid;
+
+use crate::{
+    handlers::{
+        bot_peer::close_bot_peer, plain_transport::close_plain_transport,
+        recording::finish_recording,
+    },
+    models::{
+        room::{Room, Rooms},
+        sfu::{PendingRelays, RelayRouters, Relays},
+    },
+    server::models::MediaServer,
+    utils::codec::{MessageResponse, ResponseMessage, RoomClosedData, RoomClosedReason},
+};
+
+// how often rooms are checked for the idle timeout
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+// Destroy `room_name` and everything this node holds for it: recording, plain
+// transports, bots, peers, relays and its router on each worker. `rooms` is
+// the locked registry so nothing joins the room meanwhile. None when there is
+// no such room.
+pub async fn close_room(
+    media_server: &MediaServer,
+    rooms: &mut Rooms,
+    room_name: &str,
+) -> Option<Room> {
+    let room = rooms.get(room_name)?.clone();
+    let room_name = room_name.to_string();
+    let recording = media_server.recordings.lock().await.remove(&room_name);
+    if let Some(recording) = recording {
+        let stopped = finish_recording(&room_name, recording).await;
+        let signaling = media_server.signaling.with(|s| s.clone());
+        if let Some(signaling) = signaling {
+            let msg = ResponseMessage::OutgoingCommunication {
+                ws: None,
+                requestId: None,
+                communication: MessageResponse::recordingStopped { data: stopped },
+            };
+            let _ = signaling.send(msg).await;
+        }
+    }
+
+    let plain_transports = media_server
+        .plainTransports
+        .with(|p| p.remove_room(&room_name));
+    for plain in plain_transports {
+        close_plain_transport(plain, media_server.peers.clone()).await;
+    }
+
+    let bots = media_server.botPeers.with(|b| b.remove_room(&room_name));
+    for bot in bots {
+        close_bot_peer(bot, media_server.peers.clone(), media_server.loads.clone()).await;
+    }
+
+    let peers = media_server.peers.with(|p| {
+        room.peers
+            .iter()
+            .filter_map(|peer_id| p.remove(*peer_id))
+            .collect::<Vec<_>>()
+    });
+    for peer in peers {
+        peer.close(&mut *media_server.loads.lock().await);
+    }
+
+    let mut pending_relay_guard = media_server.pendingRelays.lock().await;
+    let mut relays_guard = media_server.relays.lock().await;
+    let mut relay_routers_guard = media_server.relayRouters.lock().await;
+    let mut routers2workers_guard = media_server.routers2workers.lock().await;
+    let mut loads_guard = media_server.loads.lock().await;
+    let mut routers_guard = media_server.routers.lock().await;
+    for router in room.routers.iter() {
+        forget_router_relays(
+            media_server,
+            &mut pending_relay_guard,
+            &mut relays_guard,
+            &mut relay_routers_guard,
+            router.id(),
+        );
+        // remove rotuer2workers
+        {
+            if let Some(worker) = routers2workers_guard.get(router.id()) {
+                routers2workers_guard.delete(router.id());
+                loads_guard.remove(worker, router.id());
+            }
+            routers_guard.remove(router.id());
+            media_server
+                .dataTaps
+                .with(|t| t.remove_routers(&[router.id()]));
+        }
+    }
+    // deep clean up
+    {
+        if let Some(pipe_transport) = media_server
+            .pipetransports
+            .with(|p| p.get_by_room(room_name.clone()))
+        {
+            for pipe in pipe_transport.into_iter() {
+                media_server
+                    .pipetransports
+                    .with(|p| p.delete(pipe.transport_id));
+            }
+        }
+        if let Some(relay) = relays_guard.get_by_room(room_name.clone()) {
+            for r in relay.into_iter() {
+                relays_guard.delete(r.router);
+            }
+        }
+    }
+    media_server.audioObservers.lock().await.remove(&room_name);
+    rooms.remove(&room_name)
+}
+
+// Drop the relays `router_id` takes part in, as egress or ingress router,
+// with their pipe transports.
+pub fn forget_router_relays(
+    media_server: &MediaServer,
+    pending_relays: &mut PendingRelays,
+    relays: &mut Relays,
+    relay_routers: &mut RelayRouters,
+    router_id: RouterId,
+) {
+    // egress cleaning up
+    if let Some(ingress) = relay_routers.get_by_egress(router_id) {
+        relay_routers.delete(ingress);
+        pending_relays.delete(ingress);
+        let get_pipetransport = relays.get_router(ingress);
+        if !get_pipetransport.is_empty() {
+            media_server
+                .pipetransports
+                .with(|p| p.delete(get_pipetransport[0].transport));
+        }
+        relays.delete(ingress);
+    }
+    // ingress cleaning up
+    let get_relays = relays.get_router(router_id);
+    if !get_relays.is_empty() {
+        let transport_id = get_relays[0].transport;
+        media_server.pipetransports.with(|p| {
+            if p.get(transport_id).is_some() {
+                p.delete(transport_id);
+            }
+        });
+        relay_routers.delete(get_relays[0].router);
+        relays.delete(get_relays[0].router);
+    }
+}
+
+// Tell signaling a room is gone from this node.
+pub async fn room_closed(media_server: &MediaServer, room: Room, reason: RoomClosedReason) {
+    let mut peers: Vec<Uuid> = room.peers.into_iter().collect();
+    peers.sort();
+    let msg = ResponseMessage::OutgoingServer {
+        node: media_server.ingress.or(media_server.egress),
+        requestId: None,
+        message: MessageResponse::roomClosed {
+            data: RoomClosedData {
+                room: room.name,
+                reason,
+                peers,
+                lifetime: room.created_at.elapsed().as_secs(),
+            },
+        },
+    };
+    match media_server.signaling.with(|s| s.clone()) {
+        Some(sender) => {
+            if let Err(e) = sender.send(msg).await {
+                error!("error sending roomClosed: {:?}", e);
+            }
+        }
+        None => error!("not connected to signaling, dropping roomClosed"),
+    }
+}
+
+// Plain transports, bots and recordings keep a room without peers in use.
+async fn in_use(media_server: &MediaServer, room: &str) -> bool {
+    media_server.plainTransports.with(|p| p.in_room(room))
+        || media_server.botPeers.with(|b| b.in_room(room))
+        || media_server.recordings.lock().await.contains(room)
+}
+
+// Destroy the rooms left without any transport for `timeout`, as signaling
+// would with destroyRouterGroup.
+pub async fn close_idle_rooms(media_server: MediaServer, timeout: Duration) {
+    let mut tick = interval(IDLE_CHECK_INTERVAL.min(timeout));
+    loop {
+        tick.tick().await;
+        let mut closed = vec![];
+        {
+            let mut rooms = media_server.rooms.lock().await;
+            for room_name in rooms.idle(timeout) {
+                if in_use(&media_server, &room_name).await {
+                    rooms.touch(&room_name);
+                    continue;
+                }
+                if let Some(room) = close_room(&media_server, &mut rooms, &room_name).await {
+                    closed.push(room);
+                }
+            }
+        }
+        for room in closed {
+            let idle = room
+                .idle_since
+                .map(|since| since.elapsed())
+                .unwrap_or_default();
+            info!("closed room {} idle for {:?}", room.name, idle);
+            room_closed(&media_server, room, RoomClosedReason::idle).await;
+        }
+    }
+}
@@ -0,0 +1,84 @@
+#![allow(non_snake_case)]
+use mediasoup::{prelude::Transport, producer::Producer, transport::TransportId};
+
+use crate::{
+    models::{
+        message::{PeerSnapshot, ProducerSnapshot, RelaySnapshot, RoomSnapshot},
+        peer::Peer,
+    },
+    server::models::MediaServer,
+    utils::codec::{MessageResponse, ResponseMessage},
+};
+
+// Build the serverSnapshot message sent to signaling after (re)registering,
+// one entry per room that still has routers on this node.
+pub async fn server_snapshot(media_server: &MediaServer) -> ResponseMessage {
+    let node = media_server.ingress.or(media_server.egress);
+    let room_routers = media_server.rooms.lock().await.clone();
+    let transport2router = media_server.transport2router.read().await.0.clone();
+    let relays = media_server.relays.lock().await.clone();
+
+    let mut rooms: Vec<RoomSnapshot> = vec![];
+    for (roomName, room) in room_routers.0.into_iter() {
+        let router_ids = room.router_ids();
+        let peers: Vec<PeerSnapshot> = media_server.peers.with(|p| {
+            p.iter()
+                .filter_map(|peer| {
+                    let transport = peer.transport.as_ref()?;
+                    let in_room = transport2router
+                        .get(&transport.id())
+                        .map(|router_id| router_ids.contains(router_id))
+                        .unwrap_or(false);
+                    in_room.then(|| peer_snapshot(peer, transport.id()))
+                })
+                .collect()
+        });
+        let room_relays = relays
+            .get_by_room(roomName.clone())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|r| RelaySnapshot {
+                egress: r.egress,
+                transportId: r.transport,
+                connected: media_server
+                    .pipetransports
+                    .with(|p| p.get(r.transport))
+                    .map(|p| p.is_connected)
+                    .unwrap_or(false),
+            })
+            .collect();
+        rooms.push(RoomSnapshot {
+            roomName,
+            routers: router_ids,
+            peers,
+            relays: room_relays,
+        });
+    }
+    ResponseMessage::OutgoingServer {
+        node,
+        requestId: None,
+        message: MessageResponse::serverSnapshot { rooms },
+    }
+}
+
+fn peer_snapshot(peer: &Peer, transportId: TransportId) -> PeerSnapshot {
+    PeerSnapshot {
+        peerId: peer.id,
+        transportId,
+        producers: peer.producers.iter().map(producer_snapshot).collect(),
+        dataProducers: peer
+            .movement
+            .iter()
+            .chain(peer.events.iter())
+            .map(|p| p.id())
+            .collect(),
+    }
+}
+
+fn producer_snapshot(producer: &Producer) -> ProducerSnapshot {
+    ProducerSnapshot {
+        id: producer.id(),
+        kind: producer.kind(),
+        paused: producer.paused(),
+    }
+}
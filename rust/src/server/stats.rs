@@ -0,0 +1,49 @@
+use std::time::Duration;
+
+use log::error;
+use mediasoup::prelude::{Transport, TransportGeneric};
+use tokio::time::interval;
+
+use crate::{
+    handlers::stats::peer_stat,
+    server::models::MediaServer,
+    utils::codec::{MessageResponse, PeerStatsData, ResponseMessage},
+};
+
+// Every interval, send signaling a peerStats message summarising the WebRTC
+// transport of every peer on this node. Nothing is sent while disconnected or
+// without peers.
+pub async fn push_peer_stats(media_server: MediaServer, every: Duration) {
+    let mut tick = interval(every);
+    loop {
+        tick.tick().await;
+        let sender = match media_server.signaling.with(|s| s.clone()) {
+            Some(sender) => sender,
+            None => continue,
+        };
+        let transports = media_server.peers.with(|p| p.transports());
+        let mut peers = Vec::with_capacity(transports.len());
+        for (peer_id, transport) in transports {
+            if transport.closed() {
+                continue;
+            }
+            match transport.get_stats().await {
+                Ok(stats) => peers.extend(peer_stat(peer_id, &stats)),
+                Err(e) => error!("cannot get stats of transport {}: {}", transport.id(), e),
+            }
+        }
+        if peers.is_empty() {
+            continue;
+        }
+        let msg = ResponseMessage::OutgoingCommunication {
+            ws: None,
+            requestId: None,
+            communication: MessageResponse::peerStats {
+                data: PeerStatsData { peers },
+            },
+        };
+        if let Err(e) = sender.send(msg).await {
+            error!("error sending peer stats: {:?}", e);
+        }
+    }
+}
@@ -1,8 +1,14 @@
 use futures::{future::poll_fn, ready, sink::Sink, stream::StreamExt};
-use log::error;
-use std::{collections::VecDeque, pin::Pin, task::Poll, time::Duration};
+use log::{error, info};
+use std::{
+    collections::VecDeque,
+    pin::Pin,
+    sync::{atomic::Ordering, Arc},
+    task::Poll,
+    time::Duration,
+};
 use tokio::{
-    net::TcpStream,
+    io::{split, AsyncRead, AsyncWrite},
     pin, select,
     time::{sleep, Instant},
 };
@@ -10,64 +16,67 @@ use tokio_util::codec::{FramedRead, FramedWrite};
 
 use crate::{
     config::config::Config,
-    handlers::{cpu_load::get_media_loads, worker::create_worker},
-    server::{message_handle::handle_request_message, register_server::register_server},
+    server::{
+        auth::{authenticate, AuthError},
+        message_handle::handle_request_message,
+        metrics::Metrics,
+        register_server::register_server,
+        snapshot::server_snapshot,
+    },
     utils::{
-        codec::{self, MessageResponse, ResponseMessage},
+        codec::{self, MessageRequest, MessageResponse, RequestMessage, ResponseMessage},
         utils::{get_nodeid, Error},
     },
 };
 
 use super::models::MediaServer;
 
-pub async fn handle_stream(
-    mut stream: TcpStream,
+// Serve one signaling connection until it closes. `registered` is set once
+// signaling sent something after registerMediaServer: the connection worked,
+// as opposed to one accepted and dropped right away.
+pub async fn handle_stream<S>(
+    stream: S,
     media_server: MediaServer,
     config: Config,
-) -> Result<(), Error> {
-    stream.set_nodelay(true)?;
-    let (read, write) = stream.split();
-    let mut read = FramedRead::new(read, codec::ServerCodec::default());
-    let mut write = FramedWrite::new(write, codec::ServerCodec::default());
+    registered: &mut bool,
+) -> Result<(), Error>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (read, write) = split(stream);
+    let mut read = FramedRead::new(read, codec::ServerCodec::new(config.max_frame_size));
+    let write_codec = codec::ServerCodec::new(config.max_frame_size);
+    let encoding = write_codec.encoding();
+    let mut write = FramedWrite::new(write, write_codec);
+    let node_id = get_nodeid(config.ingress, config.egress);
+    if let Some(secret) = &config.auth_secret {
+        authenticate(&mut read, &mut write, secret, node_id)
+            .await
+            .map_err(|e| AuthError(e.to_string()))?;
+        info!("authenticated signaling");
+    }
 
-    let mut queue_write = QueuedWrite::new(&mut write);
+    let mut queue_write = QueuedWrite::new(&mut write, media_server.metrics.clone());
     let (tx, mut rx) = tokio::sync::mpsc::channel::<ResponseMessage>(128);
     let timer = sleep(Duration::from_secs(10));
     pin!(timer);
-    // create workers
-    let workers = media_server.workers.clone();
-    let webrtc_server = media_server.webrtc_server.clone();
-    let num_workers = media_server.num_workers;
-    let _ = create_worker(workers, webrtc_server, num_workers, config.clone()).await;
+    media_server.signaling.with(|s| *s = Some(tx.clone()));
     // send register server
-    let node_id = get_nodeid(config.ingress, config.egress);
     let register_server = register_server(
-        config.ingress,
+        config.mode,
         config.announceip,
         node_id,
         config.clone().region,
+        media_server.is_draining(),
     )
     .await;
     if register_server.is_ok() {
         queue_write.push(register_server.unwrap().message);
-        let load = match get_media_loads() {
-            Ok(load) => load,
-            Err(_) => 0.0,
-        };
-        let mode = match config.clone().ingress {
-            Some(_) => "ingress",
-            None => "egress",
-        };
-        let server_id = get_nodeid(config.clone().ingress, config.clone().egress);
-        let response = ResponseMessage::OutgoingServer {
-            node: server_id,
-            message: MessageResponse::serverLoad {
-                mode: mode.to_string(),
-                region: config.clone().region,
-                load,
-            },
-        };
-        queue_write.push(response);
+        if !media_server.rooms.lock().await.0.is_empty() {
+            // let signaling reconcile the rooms that survived a disconnect
+            queue_write.push(server_snapshot(&media_server).await);
+        }
+        queue_write.push(server_load(&media_server, &config));
     }
     loop {
         let media_data = media_server.clone();
@@ -75,7 +84,16 @@ pub async fn handle_stream(
                 opt = read.next() => {
                 match opt {
                 Some(res) => {
+                    *registered |= res.is_ok();
                     match res {
+                        Ok(RequestMessage::Incoming { message: MessageRequest::setEncoding { data }, .. })
+                        | Ok(RequestMessage::IncomingServer { message: MessageRequest::setEncoding { data }, .. }) => {
+                            // the writer reads the encoding as it encodes each frame,
+                            // so messages still queued go out in the new one too;
+                            // signaling tells them apart by their first byte
+                            info!("signaling selected {:?} encoding", data.encoding);
+                            encoding.with(|e| *e = data.encoding);
+                        },
                         Ok(msg) => {
                             // Call the handle_request_message function
                     let _ = handle_request_message(msg, media_data, config.clone(), tx.clone()).await;
@@ -102,59 +120,68 @@ pub async fn handle_stream(
             _ = timer.as_mut() => {
                 // check for keep alive state.
                 // send heart beat message send back to signaling of the serverload.
-                let load = match get_media_loads() {
-                    Ok(load) => load,
-                    Err(_) => 0.0
-                };
-                let mode = match config.clone().ingress {
-                    Some(_) => "ingress",
-                    None => "egress",
-                };
-                let server_id = get_nodeid(config.clone().ingress, config.clone().egress);
-                let response = ResponseMessage::OutgoingServer {
-                    node: server_id,
-                    message: MessageResponse::serverLoad {
-                        mode: mode.to_string(),
-                        region: config.clone().region,
-                        load
-                    },
-                };
-                queue_write.push(response);
+                queue_write.push(server_load(&media_server, &config));
 
                 // reset timer.
-                timer.as_mut().reset(Instant::now() + Duration::from_secs(2));
+                timer.as_mut().reset(Instant::now() + config.heartbeat_interval);
             }
         }
     }
 }
 
+// serverLoad with the capacity last sampled by sample_capacity
+fn server_load(media_server: &MediaServer, config: &Config) -> ResponseMessage {
+    let capacity = media_server.capacity.with(|c| c.clone());
+    ResponseMessage::OutgoingServer {
+        node: get_nodeid(config.ingress, config.egress),
+        requestId: None,
+        message: MessageResponse::serverLoad {
+            mode: config.mode.to_string(),
+            region: config.region.clone(),
+            load: capacity.cpu,
+            draining: media_server.is_draining(),
+            capacity,
+        },
+    }
+}
+
 pub struct QueuedWrite<'a, S> {
     write: Pin<&'a mut S>,
     queue: VecDeque<ResponseMessage>,
     is_flush: bool,
+    metrics: Arc<Metrics>,
 }
 
 impl<'a, S> QueuedWrite<'a, S>
 where
     S: Sink<ResponseMessage, Error = std::io::Error> + Unpin,
 {
-    pub fn new(write: &'a mut S) -> Self {
+    pub fn new(write: &'a mut S, metrics: Arc<Metrics>) -> Self {
         Self {
             write: Pin::new(write),
             queue: VecDeque::new(),
             is_flush: false,
+            metrics,
         }
     }
 
     // push new message to queue.
     pub fn push(&mut self, msg: ResponseMessage) {
         self.queue.push_back(msg);
+        self.metrics
+            .queue_depth
+            .store(self.queue.len(), Ordering::Relaxed);
     }
 
     fn pop(&mut self) -> ResponseMessage {
-        self.queue
+        let msg = self
+            .queue
             .pop_front()
-            .expect("WriteQueue must not be operating io write on when empty")
+            .expect("WriteQueue must not be operating io write on when empty");
+        self.metrics
+            .queue_depth
+            .store(self.queue.len(), Ordering::Relaxed);
+        msg
     }
 
     // try to wire message in queue to io.
@@ -163,13 +190,18 @@ where
             match (self.is_flush, self.queue.is_empty()) {
                 (false, true) => return poll_fn(|_| Poll::Pending).await,
                 (false, false) => {
-                    poll_fn(|cx| {
+                    // start_send only encodes, a message that cannot be
+                    // encoded is dropped instead of closing the connection
+                    let sent = poll_fn(|cx| {
                         ready!(self.write.as_mut().poll_ready(cx))?;
                         let msg = self.pop();
-                        Poll::Ready(self.write.as_mut().start_send(msg))
+                        Poll::Ready(Ok::<_, std::io::Error>(self.write.as_mut().start_send(msg)))
                     })
                     .await?;
-                    self.is_flush = true;
+                    match sent {
+                        Ok(()) => self.is_flush = true,
+                        Err(e) => error!("dropping message that cannot be sent: {}", e),
+                    }
                 }
                 (true, _) => {
                     poll_fn(|cx| self.write.as_mut().poll_flush(cx)).await?;
@@ -0,0 +1,149 @@
+use log::{error, info};
+use mediasoup::prelude::Transport;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use uuid::Uuid;
+
+use crate::{
+    config::config::Config,
+    handlers::worker::start_worker,
+    models::sfu::DeadWorker,
+    server::{
+        models::MediaServer,
+        rooms::{close_room, forget_router_relays, room_closed},
+    },
+    utils::codec::{MessageResponse, ResponseMessage, RoomClosedReason},
+};
+
+// Replace every worker mediasoup reports dead: forget its routers and the
+// transports living on them, start a new worker on the same webrtc port and
+// tell signaling which rooms and peers were affected.
+pub async fn supervise_workers(
+    media_server: MediaServer,
+    config: Config,
+    dead_workers_tx: UnboundedSender<DeadWorker>,
+    mut dead_workers: UnboundedReceiver<DeadWorker>,
+) {
+    while let Some(dead) = dead_workers.recv().await {
+        error!("worker {} died, cleaning up its routers", dead.worker_id);
+        media_server.workers.write().await.remove(dead.worker_id);
+        media_server
+            .webrtc_server
+            .write()
+            .await
+            .remove(dead.worker_id);
+        media_server.loads.lock().await.delete(dead.worker_id);
+
+        let router_ids = {
+            let mut routers2workers = media_server.routers2workers.lock().await;
+            let router_ids = routers2workers.get_by_worker(dead.worker_id);
+            for router_id in router_ids.iter() {
+                routers2workers.delete(*router_id);
+            }
+            router_ids
+        };
+        {
+            let mut pending_relays = media_server.pendingRelays.lock().await;
+            let mut relays = media_server.relays.lock().await;
+            let mut relay_routers = media_server.relayRouters.lock().await;
+            for router_id in router_ids.iter() {
+                forget_router_relays(
+                    &media_server,
+                    &mut pending_relays,
+                    &mut relays,
+                    &mut relay_routers,
+                    *router_id,
+                );
+            }
+        }
+        {
+            let mut routers = media_server.routers.lock().await;
+            for router_id in router_ids.iter() {
+                routers.remove(*router_id);
+            }
+        }
+        media_server
+            .audioObservers
+            .lock()
+            .await
+            .remove_routers(&router_ids);
+        media_server
+            .dataTaps
+            .with(|t| t.remove_routers(&router_ids));
+        media_server
+            .plainTransports
+            .with(|p| p.remove_routers(&router_ids));
+        media_server
+            .botPeers
+            .with(|b| b.remove_routers(&router_ids));
+        media_server
+            .recordings
+            .lock()
+            .await
+            .remove_routers(&router_ids);
+
+        let lost = media_server.peers.with(|p| p.remove_routers(&router_ids));
+        let mut peers: Vec<Uuid> = vec![];
+        {
+            let mut transport2router = media_server.transport2router.write().await;
+            for peer in lost.iter() {
+                if let Some(transport) = &peer.transport {
+                    transport2router.delete(transport.id());
+                }
+                peers.push(peer.id);
+            }
+        }
+        let (rooms, closed) = {
+            let mut rooms = media_server.rooms.lock().await;
+            for peer_id in peers.iter() {
+                rooms.leave(*peer_id);
+            }
+            let (lost_rooms, empty) = rooms.remove_routers(&router_ids);
+            // rooms that only lived on the dead worker
+            let mut closed = vec![];
+            for room_name in empty.iter() {
+                if let Some(room) = close_room(&media_server, &mut rooms, room_name).await {
+                    closed.push(room);
+                }
+            }
+            (lost_rooms, closed)
+        };
+        // the loads of the dead worker go with it
+        drop(lost);
+
+        match start_worker(
+            media_server.workers.clone(),
+            media_server.webrtc_server.clone(),
+            dead.port,
+            config.clone(),
+            media_server.loads.clone(),
+            dead_workers_tx.clone(),
+        )
+        .await
+        {
+            Ok(worker) => info!("worker {} replaced by {}", dead.worker_id, worker.id()),
+            Err(e) => error!("cannot restart worker on port {}: {}", dead.port, e),
+        }
+
+        let msg = ResponseMessage::OutgoingServer {
+            node: media_server.ingress.or(media_server.egress),
+            requestId: None,
+            message: MessageResponse::workerLost {
+                workerId: dead.worker_id,
+                rooms,
+                peers,
+            },
+        };
+        let signaling = media_server.signaling.with(|s| s.clone());
+        match signaling {
+            Some(sender) => {
+                if let Err(e) = sender.send(msg).await {
+                    error!("error sending workerLost: {:?}", e);
+                }
+            }
+            None => error!("not connected to signaling, dropping workerLost"),
+        }
+        for room in closed {
+            room_closed(&media_server, room, RoomClosedReason::workerLost).await;
+        }
+    }
+}
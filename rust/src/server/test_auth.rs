@@ -0,0 +1,90 @@
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use futures::{channel::mpsc, SinkExt, StreamExt};
+
+    use crate::{
+        server::auth::{authenticate, new_nonce, sign, verify, NODE_ROLE, SIGNALING_ROLE},
+        utils::codec::{
+            AuthResponseData, MessageRequest, MessageResponse, RequestMessage, ResponseMessage,
+        },
+    };
+
+    const SECRET: &str = "s3cret";
+
+    #[test]
+    fn test_sign_and_verify() {
+        let nonce = new_nonce().unwrap();
+        assert_eq!(nonce.len(), 64);
+        assert_ne!(nonce, new_nonce().unwrap());
+
+        let signature = sign(SECRET, SIGNALING_ROLE, &nonce);
+        assert!(verify(SECRET, SIGNALING_ROLE, &nonce, &signature));
+        assert!(!verify("other", SIGNALING_ROLE, &nonce, &signature));
+        // a proof is only valid for the role that produced it
+        assert!(!verify(SECRET, NODE_ROLE, &nonce, &signature));
+        assert!(!verify(SECRET, SIGNALING_ROLE, &nonce, "zz"));
+    }
+
+    // Run the handshake against a fake signaling answering with `secret`,
+    // returns the node result and the proof it sent back, if any.
+    async fn handshake(secret: &'static str) -> (bool, Option<String>) {
+        let (to_node, mut read) = mpsc::unbounded::<RequestMessage>();
+        let (write, mut from_node) = mpsc::unbounded::<ResponseMessage>();
+        let mut read = read.by_ref().map(Ok::<_, io::Error>);
+        let mut write = write.sink_map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe));
+
+        let signaling = tokio::spawn(async move {
+            let nonce = match from_node.next().await {
+                Some(ResponseMessage::OutgoingServer {
+                    message: MessageResponse::authChallenge { nonce },
+                    ..
+                }) => nonce,
+                other => panic!("expected authChallenge, got {:?}", other),
+            };
+            let message = MessageRequest::authResponse {
+                data: AuthResponseData {
+                    nonce: "signaling-nonce".to_string(),
+                    hmac: sign(secret, SIGNALING_ROLE, &nonce),
+                },
+            };
+            let _ = to_node.unbounded_send(RequestMessage::IncomingServer {
+                node: None,
+                wsid: None,
+                requestId: None,
+                message,
+            });
+            match from_node.next().await {
+                Some(ResponseMessage::OutgoingServer {
+                    message: MessageResponse::authProof { hmac },
+                    ..
+                }) => Some(hmac),
+                _ => None,
+            }
+        });
+
+        let res = authenticate(&mut read, &mut write, SECRET, None).await;
+        drop(write);
+        (res.is_ok(), signaling.await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_handshake_with_shared_secret() {
+        let (ok, proof) = handshake(SECRET).await;
+        assert!(ok);
+        assert!(verify(
+            SECRET,
+            NODE_ROLE,
+            "signaling-nonce",
+            &proof.unwrap()
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_impostor_signaling_is_rejected() {
+        let (ok, proof) = handshake("guess").await;
+        assert!(!ok);
+        assert!(proof.is_none());
+    }
+}
@@ -0,0 +1,60 @@
+#[cfg(test)]
+mod tests {
+    use mediasoup::worker::WorkerId;
+    use uuid::Uuid;
+
+    use crate::{
+        server::capacity::{remaining_capacity, webrtc_ports_in},
+        utils::codec::{NodeCapacity, WorkerCapacity},
+    };
+
+    fn worker(cpu: f32) -> WorkerCapacity {
+        let worker_id: WorkerId =
+            serde_json::from_value(serde_json::json!(Uuid::new_v4())).unwrap();
+        WorkerCapacity {
+            workerId: worker_id,
+            cpu,
+            routers: 0,
+            transports: 0,
+            consumers: 0,
+        }
+    }
+
+    #[test]
+    fn test_idle_node_has_everything_left() {
+        let capacity = NodeCapacity {
+            workers: vec![worker(0.0), worker(0.0)],
+            memoryFree: 8,
+            memoryTotal: 8,
+            freePorts: 100,
+            ..Default::default()
+        };
+        assert_eq!(remaining_capacity(&capacity, 100, None), 100.0);
+        assert_eq!(remaining_capacity(&NodeCapacity::default(), 0, None), 100.0);
+    }
+
+    #[test]
+    fn test_scarcest_resource_wins() {
+        let capacity = NodeCapacity {
+            workers: vec![worker(90.0), worker(50.0)],
+            memoryFree: 6,
+            memoryTotal: 8,
+            freePorts: 90,
+            rooms: 3,
+            ..Default::default()
+        };
+        assert_eq!(remaining_capacity(&capacity, 100, None), 30.0);
+        assert_eq!(remaining_capacity(&capacity, 100, Some(5)), 30.0);
+        assert_eq!(remaining_capacity(&capacity, 100, Some(4)), 25.0);
+        assert_eq!(remaining_capacity(&capacity, 100, Some(2)), 0.0);
+        assert_eq!(remaining_capacity(&capacity, 1000, None), 9.0);
+    }
+
+    #[test]
+    fn test_webrtc_ports_in_rtc_range() {
+        assert_eq!(webrtc_ports_in(&(10000..=59999), 10000, 4), 4);
+        assert_eq!(webrtc_ports_in(&(10000..=59999), 9998, 4), 2);
+        assert_eq!(webrtc_ports_in(&(10000..=59999), 60000, 4), 0);
+        assert_eq!(webrtc_ports_in(&(10000..=59999), u16::MAX, 4), 0);
+    }
+}
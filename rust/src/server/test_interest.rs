@@ -0,0 +1,115 @@
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use mediasoup::prelude::{ConsumerLayers, MediaKind};
+
+    use crate::{
+        config::config::InterestConfig,
+        server::interest::{beyond, lowers, pauses, target_state, Managed},
+        utils::codec::InterestState,
+    };
+
+    fn interest() -> InterestConfig {
+        InterestConfig {
+            audio_distance: Some(30.0),
+            video_distance: Some(40.0),
+            video_low_distance: Some(15.0),
+            interval: Duration::from_millis(500),
+        }
+    }
+
+    fn lowered() -> Managed {
+        Managed {
+            paused: false,
+            lowered: Some(Some(ConsumerLayers {
+                spatial_layer: 2,
+                temporal_layer: None,
+            })),
+        }
+    }
+
+    fn paused() -> Managed {
+        Managed {
+            paused: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_beyond_uses_hysteresis_once_beyond() {
+        assert!(!beyond(30.0, Some(30.0), false));
+        assert!(beyond(30.5, Some(30.0), false));
+        // back within the threshold but not by its hysteresis share
+        assert!(beyond(28.0, Some(30.0), true));
+        assert!(!beyond(26.0, Some(30.0), true));
+        assert!(!beyond(1000.0, None, true));
+    }
+
+    #[test]
+    fn test_audio_is_paused_beyond_its_distance() {
+        let interest = interest();
+        let state = |distance, current: &Managed| {
+            target_state(&interest, MediaKind::Audio, false, distance, current)
+        };
+        assert_eq!(state(20.0, &Managed::default()), InterestState::active);
+        assert_eq!(state(31.0, &Managed::default()), InterestState::paused);
+        assert_eq!(state(28.0, &paused()), InterestState::paused);
+        assert_eq!(state(26.0, &paused()), InterestState::active);
+    }
+
+    #[test]
+    fn test_video_is_lowered_then_paused() {
+        let interest = interest();
+        let state = |distance, current: &Managed| {
+            target_state(&interest, MediaKind::Video, true, distance, current)
+        };
+        assert_eq!(state(10.0, &Managed::default()), InterestState::active);
+        assert_eq!(state(20.0, &Managed::default()), InterestState::lowered);
+        assert_eq!(state(41.0, &Managed::default()), InterestState::paused);
+        // leaving the pause for the lowered band, then the lowered band
+        assert_eq!(state(37.0, &paused()), InterestState::paused);
+        assert_eq!(state(30.0, &paused()), InterestState::lowered);
+        assert_eq!(state(14.0, &lowered()), InterestState::lowered);
+        assert_eq!(state(13.0, &lowered()), InterestState::active);
+    }
+
+    #[test]
+    fn test_video_without_layers_is_never_lowered() {
+        let interest = interest();
+        let state = target_state(
+            &interest,
+            MediaKind::Video,
+            false,
+            20.0,
+            &Managed::default(),
+        );
+        assert_eq!(state, InterestState::active);
+        let state = target_state(
+            &interest,
+            MediaKind::Video,
+            false,
+            41.0,
+            &Managed::default(),
+        );
+        assert_eq!(state, InterestState::paused);
+    }
+
+    #[test]
+    fn test_paused_consumer_stays_lowered() {
+        assert!(lowers(InterestState::paused, &lowered()));
+        assert!(!lowers(InterestState::paused, &Managed::default()));
+        assert!(lowers(InterestState::lowered, &Managed::default()));
+        // back close, the preferred layers are restored
+        assert!(!lowers(InterestState::active, &lowered()));
+    }
+
+    #[test]
+    fn test_client_paused_consumer_is_left_alone() {
+        assert!(pauses(InterestState::paused, &Managed::default(), false));
+        assert!(!pauses(InterestState::paused, &Managed::default(), true));
+        // paused by the interest manager, consumer.paused() is its own pause
+        assert!(pauses(InterestState::paused, &paused(), true));
+        assert!(!pauses(InterestState::active, &paused(), true));
+    }
+}
@@ -0,0 +1,50 @@
+#[cfg(test)]
+mod tests {
+    use crate::{
+        config::config::{Config, ConfigFile, Mode},
+        server::{metrics::render_metrics, models::MediaServer},
+        utils::arg::Args,
+    };
+
+    fn media_server() -> MediaServer {
+        let file = ConfigFile {
+            url: Some("127.0.0.1:1188".to_string()),
+            mode: Some(Mode::both),
+            listen_ip: Some("10.0.0.5".parse().unwrap()),
+            ..Default::default()
+        };
+        MediaServer::new(Config::from_sources(Args::default(), file).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_fresh_server_renders_zero_gauges() {
+        let out = render_metrics(&media_server()).await;
+        assert!(out.contains("# HELP frame_rooms Rooms with routers on this node\n"));
+        assert!(out.contains("# TYPE frame_rooms gauge\nframe_rooms 0\n"));
+        assert!(out.contains("frame_producers{kind=\"audio\"} 0\n"));
+        assert!(out.contains("frame_consumers{kind=\"video\"} 0\n"));
+        assert!(out.contains("frame_pipe_relays{state=\"connected\"} 0\n"));
+        assert!(out.contains("frame_signaling_queue_depth 0\n"));
+        assert!(out.contains("# TYPE frame_handler_requests_total counter\n"));
+        assert!(out.contains("# TYPE frame_handler_errors_total counter\n"));
+        assert!(out.contains("# TYPE frame_handler_duration_seconds summary\n"));
+        assert!(!out.contains("request=\""));
+    }
+
+    #[tokio::test]
+    async fn test_recorded_requests_are_labelled() {
+        let media_server = media_server();
+        media_server.metrics.observe("createWebRtcTransport", 0.5);
+        media_server.metrics.observe("createWebRtcTransport", 0.25);
+        media_server.metrics.error("createWebRtcTransport");
+        let out = render_metrics(&media_server).await;
+        assert!(out.contains("frame_handler_requests_total{request=\"createWebRtcTransport\"} 2\n"));
+        assert!(out.contains("frame_handler_errors_total{request=\"createWebRtcTransport\"} 1\n"));
+        assert!(out.contains(
+            "frame_handler_duration_seconds_sum{request=\"createWebRtcTransport\"} 0.75\n"
+        ));
+        assert!(out.contains(
+            "frame_handler_duration_seconds_count{request=\"createWebRtcTransport\"} 2\n"
+        ));
+    }
+}
@@ -0,0 +1,79 @@
+use std::{fs::File, io::BufReader, path::Path, sync::Arc};
+
+use tokio::net::TcpStream;
+use tokio_rustls::{
+    client::TlsStream,
+    rustls::{
+        crypto::ring::default_provider,
+        pki_types::{CertificateDer, PrivateKeyDer, ServerName},
+        ClientConfig, RootCertStore,
+    },
+    TlsConnector,
+};
+
+use crate::{config::config::TlsConfig, utils::utils::Error};
+
+// Connector for the signaling link, built once at startup so bad certificates
+// fail the node before it ever connects.
+#[derive(Clone)]
+pub struct SignalingTls {
+    connector: TlsConnector,
+    server_name: ServerName<'static>,
+}
+
+impl SignalingTls {
+    pub fn new(tls: &TlsConfig) -> Result<Self, String> {
+        let mut roots = RootCertStore::empty();
+        match &tls.ca {
+            Some(ca) => {
+                for cert in read_certs(ca)? {
+                    roots
+                        .add(cert)
+                        .map_err(|e| format!("invalid CA in {}: {}", ca.display(), e))?;
+                }
+            }
+            None => roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+        }
+        let builder = ClientConfig::builder_with_provider(Arc::new(default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(|e| e.to_string())?
+            .with_root_certificates(roots);
+        let client_config = match (&tls.cert, &tls.key) {
+            (Some(cert), Some(key)) => builder
+                .with_client_auth_cert(read_certs(cert)?, read_key(key)?)
+                .map_err(|e| format!("invalid client certificate: {}", e))?,
+            _ => builder.with_no_client_auth(),
+        };
+        let server_name = ServerName::try_from(tls.server_name.clone())
+            .map_err(|e| format!("invalid tls server name {}: {}", tls.server_name, e))?;
+        Ok(Self {
+            connector: TlsConnector::from(Arc::new(client_config)),
+            server_name,
+        })
+    }
+
+    pub async fn connect(&self, stream: TcpStream) -> Result<TlsStream<TcpStream>, Error> {
+        Ok(self
+            .connector
+            .connect(self.server_name.clone(), stream)
+            .await?)
+    }
+}
+
+fn read_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, String> {
+    let file = File::open(path).map_err(|e| format!("cannot open {}: {}", path.display(), e))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("cannot read certificates from {}: {}", path.display(), e))?;
+    if certs.is_empty() {
+        return Err(format!("no certificate found in {}", path.display()));
+    }
+    Ok(certs)
+}
+
+fn read_key(path: &Path) -> Result<PrivateKeyDer<'static>, String> {
+    let file = File::open(path).map_err(|e| format!("cannot open {}: {}", path.display(), e))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .map_err(|e| format!("cannot read private key from {}: {}", path.display(), e))?
+        .ok_or(format!("no private key found in {}", path.display()))
+}
@@ -1,22 +1,139 @@
+use std::{net::IpAddr, path::PathBuf};
+
 use clap::Parser;
 
-#[derive(Parser, Debug)]
+use crate::config::config::{LoadBalancing, Mode, RouterPlacement};
+
+// Every flag can also be set from its environment variable or in the config
+// file, in that order of precedence.
+#[derive(Parser, Debug, Default)]
 #[clap(author, version, about, long_about = None)]
 pub struct Args {
+    /// toml config file
+    #[clap(short, long, env = "FRAME_CONFIG")]
+    pub config: Option<PathBuf>,
+    /// signaling server address
+    #[clap(short, long, env = "FRAME_URL")]
+    pub url: Option<String>,
+    /// ingress, egress or both
+    #[clap(short, long, env = "FRAME_MODE")]
+    pub mode: Option<Mode>,
+    #[clap(short, long, env = "FRAME_TRAVERSE_NAT")]
+    pub traverse_nat: Option<bool>,
+    #[clap(short, long, env = "FRAME_ANNOUNCE_IP")]
+    pub announceip: Option<IpAddr>,
+    #[clap(long, env = "FRAME_LISTEN_IP")]
+    pub listen_ip: Option<IpAddr>,
+    /// same as --mode ingress, kept for existing launch scripts
     #[clap(short, long)]
-    pub url: String,
-    #[clap(short, long)]
-    pub traverse_nat: String,
-    #[clap(short, long)]
-    pub announceip: String,
-    #[clap(short, long)]
-    pub ingress: String,
-    #[clap(short, long)]
-    pub egress: String,
-    #[clap(short, long)]
-    pub workers: i32,
-    #[clap(short, long)]
-    pub region: String,
+    pub ingress: Option<bool>,
+    /// same as --mode egress, kept for existing launch scripts
     #[clap(short, long)]
-    pub port_transport: u16,
+    pub egress: Option<bool>,
+    #[clap(short, long, env = "FRAME_WORKERS")]
+    pub workers: Option<i32>,
+    #[clap(short, long, env = "FRAME_REGION")]
+    pub region: Option<String>,
+    /// first webrtc port, worker n listens on port + n
+    #[clap(short, long, env = "FRAME_WEBRTC_PORT")]
+    pub port_transport: Option<u16>,
+    /// round-robin (default), least-transports or least-cpu
+    #[clap(long, env = "FRAME_LOAD_BALANCING")]
+    pub load_balancing: Option<LoadBalancing>,
+    /// all-workers (default) or lazy
+    #[clap(long, env = "FRAME_ROUTER_PLACEMENT")]
+    pub router_placement: Option<RouterPlacement>,
+    /// consumers on every router of a lazy room before it gets another router
+    #[clap(long, env = "FRAME_ROUTER_CONSUMER_THRESHOLD")]
+    pub router_consumer_threshold: Option<u32>,
+    /// serve prometheus metrics over http on this port
+    #[clap(long, env = "FRAME_METRICS_PORT")]
+    pub metrics_port: Option<u16>,
+    /// seconds between serverLoad messages
+    #[clap(long, env = "FRAME_HEARTBEAT_INTERVAL")]
+    pub heartbeat_interval: Option<u64>,
+    /// largest signaling frame accepted or sent, in bytes
+    #[clap(long, env = "FRAME_MAX_FRAME_SIZE")]
+    pub max_frame_size: Option<usize>,
+    /// connect to signaling over tls
+    #[clap(long, env = "FRAME_TLS")]
+    pub tls: Option<bool>,
+    /// pem CA bundle used to verify signaling
+    #[clap(long, env = "FRAME_TLS_CA")]
+    pub tls_ca: Option<PathBuf>,
+    /// pem client certificate presented to signaling
+    #[clap(long, env = "FRAME_TLS_CERT")]
+    pub tls_cert: Option<PathBuf>,
+    #[clap(long, env = "FRAME_TLS_KEY")]
+    pub tls_key: Option<PathBuf>,
+    /// name in the signaling certificate, the url host by default
+    #[clap(long, env = "FRAME_TLS_SERVER_NAME")]
+    pub tls_server_name: Option<String>,
+    /// secret shared with signaling for the connection handshake
+    #[clap(long, env = "FRAME_AUTH_SECRET", hide_env_values = true)]
+    pub auth_secret: Option<String>,
+    /// seconds a draining node waits for its rooms to close
+    #[clap(long, env = "FRAME_DRAIN_TIMEOUT")]
+    pub drain_timeout: Option<u64>,
+    /// seconds a room without transports is kept before it is destroyed, unset or 0 never
+    #[clap(long, env = "FRAME_ROOM_IDLE_TIMEOUT")]
+    pub room_idle_timeout: Option<u64>,
+    /// rooms this node hosts before createRouterGroup is rejected
+    #[clap(long, env = "FRAME_MAX_ROOMS")]
+    pub max_rooms: Option<u32>,
+    /// peers with a transport in one room
+    #[clap(long, env = "FRAME_MAX_PEERS_PER_ROOM")]
+    pub max_peers_per_room: Option<u32>,
+    /// video producers in one room
+    #[clap(long, env = "FRAME_MAX_VIDEO_PRODUCERS_PER_ROOM")]
+    pub max_video_producers_per_room: Option<u32>,
+    /// consumers on one peer transport
+    #[clap(long, env = "FRAME_MAX_CONSUMERS_PER_TRANSPORT")]
+    pub max_consumers_per_transport: Option<u32>,
+    /// milliseconds between audioLevels messages of a room
+    #[clap(long, env = "FRAME_AUDIO_LEVEL_INTERVAL")]
+    pub audio_level_interval: Option<u16>,
+    /// pause audio consumers of peers farther than this distance
+    #[clap(long, env = "FRAME_INTEREST_AUDIO_DISTANCE")]
+    pub interest_audio_distance: Option<f32>,
+    /// pause video consumers of peers farther than this distance
+    #[clap(long, env = "FRAME_INTEREST_VIDEO_DISTANCE")]
+    pub interest_video_distance: Option<f32>,
+    /// lower video consumers of peers farther than this distance to their lowest layer
+    #[clap(long, env = "FRAME_INTEREST_VIDEO_LOW_DISTANCE")]
+    pub interest_video_low_distance: Option<f32>,
+    /// milliseconds between interest checks
+    #[clap(long, env = "FRAME_INTEREST_INTERVAL")]
+    pub interest_interval: Option<u64>,
+    /// drop malformed or oversized movement packets before fanning them out
+    #[clap(long, env = "FRAME_VALIDATE_MOVEMENT")]
+    pub validate_movement: Option<bool>,
+    /// send new movement consumers the last movement of every peer
+    #[clap(long, env = "FRAME_MOVEMENT_SNAPSHOT")]
+    pub movement_snapshot: Option<bool>,
+    /// messages a peer may send per second on one data channel
+    #[clap(long, env = "FRAME_DATA_MAX_MESSAGES_PER_SEC")]
+    pub data_max_messages_per_sec: Option<u32>,
+    /// bytes a peer may send per second on one data channel
+    #[clap(long, env = "FRAME_DATA_MAX_BYTES_PER_SEC")]
+    pub data_max_bytes_per_sec: Option<u64>,
+    /// largest data channel message forwarded, in bytes
+    #[clap(long, env = "FRAME_DATA_MAX_MESSAGE_SIZE")]
+    pub data_max_message_size: Option<usize>,
+    /// seconds in a row over the limits before a data channel is paused
+    #[clap(long, env = "FRAME_DATA_THROTTLE_SECONDS")]
+    pub data_throttle_seconds: Option<u32>,
+    /// seconds a paused data channel drops everything
+    #[clap(long, env = "FRAME_DATA_PAUSE_SECONDS")]
+    pub data_pause_seconds: Option<u64>,
+    /// directory startRecording writes to, recording is disabled without it
+    #[clap(long, env = "FRAME_RECORDING_DIR")]
+    pub recording_dir: Option<PathBuf>,
+    /// seconds between peerStats messages, not sent without it
+    #[clap(long, env = "FRAME_STATS_INTERVAL")]
+    pub stats_interval: Option<u64>,
+    #[clap(long, env = "FRAME_INITIAL_OUTGOING_BITRATE")]
+    pub initial_outgoing_bitrate: Option<u32>,
+    #[clap(long, env = "FRAME_MAX_OUTGOING_BITRATE")]
+    pub max_outgoing_bitrate: Option<u32>,
 }
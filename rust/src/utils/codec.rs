@@ -1,16 +1,23 @@
 #![allow(non_snake_case, non_camel_case_types)]
 use byteorder::{BigEndian, ByteOrder};
 use bytes::{Buf, BufMut, BytesMut};
+use log::error;
 use mediasoup::{
-    prelude::*, router::RouterId, sctp_parameters::SctpParameters, srtp_parameters::SrtpParameters,
+    consumer::ConsumerStats, data_structures::IceState, prelude::*, producer::ProducerStat,
+    router::RouterId, sctp_parameters::SctpParameters, srtp_parameters::SrtpParameters,
+    webrtc_transport::WebRtcTransportStat, worker::WorkerId,
 };
 use serde::{Deserialize, Serialize};
 use serde_json as json;
-use std::{collections::HashMap, io, net::IpAddr};
+use std::{collections::HashMap, io, net::IpAddr, sync::Arc};
 use tokio_util::codec::{Decoder, Encoder};
 use uuid::Uuid;
 
-use crate::models::message::{NewConsumerOptions, NewDataConsumerOptions};
+use crate::{
+    handlers::error::{ErrorCode, Limit},
+    models::message::{NewConsumerOptions, NewDataConsumerOptions, RoomSnapshot},
+    utils::utils::Mut,
+};
 
 // server message sent to client
 #[derive(Serialize, Deserialize, Debug)]
@@ -18,11 +25,15 @@ use crate::models::message::{NewConsumerOptions, NewDataConsumerOptions};
 pub enum RequestMessage {
     Incoming {
         wsid: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        requestId: Option<String>,
         message: MessageRequest,
     },
     IncomingServer {
         node: Option<Uuid>,
         wsid: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        requestId: Option<String>,
         message: MessageRequest,
     },
 }
@@ -71,6 +82,10 @@ pub enum MessageRequest {
     #[serde(rename_all = "camelCase")]
     consumerResume { data: ConsumerMuteData },
     #[serde(rename_all = "camelCase")]
+    setConsumerPreferredLayers { data: ConsumerPreferredLayersData },
+    #[serde(rename_all = "camelCase")]
+    setConsumerPriority { data: ConsumerPriorityData },
+    #[serde(rename_all = "camelCase")]
     producerPause { data: ProducerMuteData },
     #[serde(rename_all = "camelCase")]
     producerResume { data: ProducerMuteData },
@@ -78,6 +93,87 @@ pub enum MessageRequest {
     producerClose { data: ProducerCloseData },
     #[serde(rename_all = "camelCase")]
     restartIce { data: RestartIceData },
+    #[serde(rename_all = "camelCase")]
+    setEncoding { data: SetEncodingData },
+    #[serde(rename_all = "camelCase")]
+    authResponse { data: AuthResponseData },
+    #[serde(rename_all = "camelCase")]
+    createPlainEgress { data: CreatePlainEgressData },
+    #[serde(rename_all = "camelCase")]
+    createPlainIngress { data: CreatePlainIngressData },
+    #[serde(rename_all = "camelCase")]
+    closePlainTransport { data: ClosePlainTransportData },
+    #[serde(rename_all = "camelCase")]
+    startRecording { data: StartRecordingData },
+    #[serde(rename_all = "camelCase")]
+    stopRecording { data: StopRecordingData },
+    #[serde(rename_all = "camelCase")]
+    createBotPeer { data: CreateBotPeerData },
+    #[serde(rename_all = "camelCase")]
+    botSend { data: BotSendData },
+    #[serde(rename_all = "camelCase")]
+    botConsume { data: BotConsumeData },
+    #[serde(rename_all = "camelCase")]
+    closeBotPeer { data: CloseBotPeerData },
+    #[serde(rename_all = "camelCase")]
+    getTransportStats { data: TransportStatsRequestData },
+    #[serde(rename_all = "camelCase")]
+    getProducerStats { data: ProducerStatsRequestData },
+    #[serde(rename_all = "camelCase")]
+    getConsumerStats { data: ConsumerStatsRequestData },
+    #[serde(rename_all = "camelCase")]
+    drainNode {
+        #[serde(default)]
+        data: DrainNodeData,
+    },
+}
+
+impl MessageRequest {
+    // value of the type tag, used to label metrics
+    pub fn name(&self) -> &'static str {
+        match self {
+            MessageRequest::createRouterGroup { .. } => "createRouterGroup",
+            MessageRequest::consumeAudio { .. } => "consumeAudio",
+            MessageRequest::consumeVideo { .. } => "consumeVideo",
+            MessageRequest::consumeMovement { .. } => "consumeMovement",
+            MessageRequest::consumeEvents { .. } => "consumeEvents",
+            MessageRequest::createWebRTCIngress { .. } => "createWebRTCIngress",
+            MessageRequest::createWebRTCEgress { .. } => "createWebRTCEgress",
+            MessageRequest::connectWebRTCIngress { .. } => "connectWebRTCIngress",
+            MessageRequest::connectWebRTCEgress { .. } => "connectWebRTCEgress",
+            MessageRequest::createMediaProducer { .. } => "createMediaProducer",
+            MessageRequest::createDataProducer { .. } => "createDataProducer",
+            MessageRequest::createEventProducer { .. } => "createEventProducer",
+            MessageRequest::disconnectTransport { .. } => "disconnectTransport",
+            MessageRequest::destroyRouterGroup { .. } => "destroyRouterGroup",
+            MessageRequest::storePipeRelay { .. } => "storePipeRelay",
+            MessageRequest::createRelayProducer { .. } => "createRelayProducer",
+            MessageRequest::connectPipeRelay { .. } => "connectPipeRelay",
+            MessageRequest::consumerPause { .. } => "consumerPause",
+            MessageRequest::consumerResume { .. } => "consumerResume",
+            MessageRequest::setConsumerPreferredLayers { .. } => "setConsumerPreferredLayers",
+            MessageRequest::setConsumerPriority { .. } => "setConsumerPriority",
+            MessageRequest::producerPause { .. } => "producerPause",
+            MessageRequest::producerResume { .. } => "producerResume",
+            MessageRequest::producerClose { .. } => "producerClose",
+            MessageRequest::restartIce { .. } => "restartIce",
+            MessageRequest::setEncoding { .. } => "setEncoding",
+            MessageRequest::authResponse { .. } => "authResponse",
+            MessageRequest::createPlainEgress { .. } => "createPlainEgress",
+            MessageRequest::createPlainIngress { .. } => "createPlainIngress",
+            MessageRequest::closePlainTransport { .. } => "closePlainTransport",
+            MessageRequest::startRecording { .. } => "startRecording",
+            MessageRequest::stopRecording { .. } => "stopRecording",
+            MessageRequest::createBotPeer { .. } => "createBotPeer",
+            MessageRequest::botSend { .. } => "botSend",
+            MessageRequest::botConsume { .. } => "botConsume",
+            MessageRequest::closeBotPeer { .. } => "closeBotPeer",
+            MessageRequest::getTransportStats { .. } => "getTransportStats",
+            MessageRequest::getProducerStats { .. } => "getProducerStats",
+            MessageRequest::getConsumerStats { .. } => "getConsumerStats",
+            MessageRequest::drainNode { .. } => "drainNode",
+        }
+    }
 }
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ProducerMuteData {
@@ -92,6 +188,135 @@ pub struct ConsumerMuteData {
     pub consumerId: ConsumerId,
 }
 
+// highest simulcast/svc layers the consumer should receive
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ConsumerPreferredLayersData {
+    pub peerId: Uuid,
+    pub consumerId: ConsumerId,
+    pub spatialLayer: u8,
+    pub temporalLayer: Option<u8>,
+}
+
+// Send one producer of the room as plain RTP to a local process at ip:port,
+// RTCP goes to port + 1 without rtcpMux. With comedia the process has to send
+// first and ip/port are learnt from its packets.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CreatePlainEgressData {
+    pub room: String,
+    pub producerId: ProducerId,
+    pub ip: Option<IpAddr>,
+    pub port: Option<u16>,
+    pub rtcpMux: bool,
+    pub comedia: bool,
+}
+
+// Let a local process publish into the room as peerId, the producer is relayed
+// to egress like any other.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CreatePlainIngressData {
+    pub room: String,
+    pub peerId: Uuid,
+    pub egress: Uuid,
+    pub kind: MediaKind,
+    pub rtpParameters: RtpParameters,
+    #[serde(default)]
+    pub appData: appData,
+    pub ip: Option<IpAddr>,
+    pub port: Option<u16>,
+    pub rtcpMux: bool,
+    pub comedia: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ClosePlainTransportData {
+    pub transportId: TransportId,
+}
+
+// A peer played by the node in the room, announced to egress like a client
+// joining through it. position is the first movement frame it sends.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CreateBotPeerData {
+    pub room: String,
+    pub peerId: Uuid,
+    pub egress: Uuid,
+    pub position: Option<[f32; 3]>,
+}
+
+// event is sent on the events channel, position/rotation as a movement frame
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BotSendData {
+    pub peerId: Uuid,
+    pub event: Option<String>,
+    pub position: Option<[f32; 3]>,
+    pub rotation: Option<[f32; 4]>,
+}
+
+// a data producer of the room on the bot's router, events or movement of a peer
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BotConsumeData {
+    pub peerId: Uuid,
+    pub dataProducerId: DataProducerId,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CloseBotPeerData {
+    pub peerId: Uuid,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TransportStatsRequestData {
+    pub peerId: Uuid,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ProducerStatsRequestData {
+    pub producerId: ProducerId,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ConsumerStatsRequestData {
+    pub consumerId: ConsumerId,
+}
+
+// record the producers of the room, of every peer unless peers is set
+#[derive(Serialize, Deserialize, Debug)]
+pub struct StartRecordingData {
+    pub room: String,
+    pub peers: Option<Vec<Uuid>>,
+    pub kinds: Vec<MediaKind>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct StopRecordingData {
+    pub room: String,
+}
+
+// bandwidth share of the consumer among the consumers of its transport
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ConsumerPriorityData {
+    pub peerId: Uuid,
+    pub consumerId: ConsumerId,
+    pub priority: u8,
+}
+
+// layers actually forwarded, none while the consumer receives nothing
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ConsumerLayersChangedData {
+    pub peerId: Uuid,
+    pub consumerId: ConsumerId,
+    pub spatialLayer: Option<u8>,
+    pub temporalLayer: Option<u8>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ConsumerScoreData {
+    pub peerId: Uuid,
+    pub consumerId: ConsumerId,
+    pub score: u8,
+    pub producerScore: u8,
+    pub producerScores: Vec<u8>,
+}
+
 #[derive(Serialize, Clone, Deserialize, Debug)]
 pub struct ProducerCloseData {
     pub peerId: Uuid,
@@ -105,6 +330,25 @@ pub struct RestartIceData {
     pub transportId: TransportId,
 }
 
+// sent by signaling to pick the encoding of the frames this node writes
+#[derive(Serialize, Clone, Deserialize, Debug)]
+pub struct SetEncodingData {
+    pub encoding: Encoding,
+}
+
+// seconds to wait for rooms before exiting, the configured drain_timeout when missing
+#[derive(Serialize, Clone, Deserialize, Debug, Default)]
+pub struct DrainNodeData {
+    pub timeout: Option<u64>,
+}
+
+// signaling proves it knows the shared secret and challenges the node back
+#[derive(Serialize, Clone, Deserialize, Debug)]
+pub struct AuthResponseData {
+    pub nonce: String,
+    pub hmac: String,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ProducerReplyMuteData {
     pub peerId: Uuid,
@@ -267,19 +511,40 @@ pub struct DestroyRouterGroupData {
 pub enum ResponseMessage {
     Outgoing {
         ws: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        requestId: Option<String>,
         message: MessageResponse,
     },
     OutgoingCommunication {
         ws: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        requestId: Option<String>,
         communication: MessageResponse,
     },
     OutgoingServer {
         // wsid: Option<String>,
         node: Option<Uuid>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        requestId: Option<String>,
         message: MessageResponse,
     },
 }
 
+impl ResponseMessage {
+    // echo the id of the request this message answers, unless one is already set
+    pub fn set_request_id(&mut self, request_id: Option<String>) {
+        match self {
+            ResponseMessage::Outgoing { requestId, .. }
+            | ResponseMessage::OutgoingCommunication { requestId, .. }
+            | ResponseMessage::OutgoingServer { requestId, .. } => {
+                if requestId.is_none() {
+                    *requestId = request_id;
+                }
+            }
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(tag = "type")]
 pub enum MessageResponse {
@@ -288,12 +553,109 @@ pub enum MessageResponse {
     registerMediaServer {
         mode: String,
         region: String,
+        encodings: Vec<Encoding>,
+        draining: bool,
+    },
+    #[serde(rename_all = "camelCase")]
+    serverSnapshot {
+        rooms: Vec<RoomSnapshot>,
+    },
+    #[serde(rename_all = "camelCase")]
+    workerLost {
+        workerId: WorkerId,
+        rooms: Vec<String>,
+        peers: Vec<Uuid>,
+    },
+    #[serde(rename_all = "camelCase")]
+    roomCreated {
+        data: RoomCreatedData,
+    },
+    #[serde(rename_all = "camelCase")]
+    roomClosed {
+        data: RoomClosedData,
+    },
+    #[serde(rename_all = "camelCase")]
+    nodeDraining {
+        timeout: u64,
+        rooms: Vec<String>,
+    },
+    #[serde(rename_all = "camelCase")]
+    authChallenge {
+        nonce: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    authProof {
+        hmac: String,
     },
     #[serde(rename_all = "camelCase")]
     joinedRoom {
         data: JoinRoomData,
     },
     #[serde(rename_all = "camelCase")]
+    consumerLayersChanged {
+        data: ConsumerLayersChangedData,
+    },
+    #[serde(rename_all = "camelCase")]
+    consumerScore {
+        data: ConsumerScoreData,
+    },
+    #[serde(rename_all = "camelCase")]
+    audioLevels {
+        data: AudioLevelsData,
+    },
+    #[serde(rename_all = "camelCase")]
+    activeSpeaker {
+        data: ActiveSpeakerData,
+    },
+    #[serde(rename_all = "camelCase")]
+    interestChanged {
+        data: InterestChangedData,
+    },
+    #[serde(rename_all = "camelCase")]
+    dataProducerThrottled {
+        data: DataProducerThrottledData,
+    },
+    #[serde(rename_all = "camelCase")]
+    createdPlainEgress {
+        data: CreatedPlainTransportData,
+    },
+    #[serde(rename_all = "camelCase")]
+    createdPlainIngress {
+        data: CreatedPlainTransportData,
+    },
+    #[serde(rename_all = "camelCase")]
+    transportStats {
+        data: TransportStatsData,
+    },
+    #[serde(rename_all = "camelCase")]
+    producerStats {
+        data: ProducerStatsData,
+    },
+    #[serde(rename_all = "camelCase")]
+    consumerStats {
+        data: ConsumerStatsData,
+    },
+    #[serde(rename_all = "camelCase")]
+    peerStats {
+        data: PeerStatsData,
+    },
+    #[serde(rename_all = "camelCase")]
+    createdBotPeer {
+        data: CreatedBotPeerData,
+    },
+    #[serde(rename_all = "camelCase")]
+    botReceived {
+        data: BotReceivedData,
+    },
+    #[serde(rename_all = "camelCase")]
+    recordingStarted {
+        data: RecordingData,
+    },
+    #[serde(rename_all = "camelCase")]
+    recordingStopped {
+        data: RecordingData,
+    },
+    #[serde(rename_all = "camelCase")]
     createdIngressTransport {
         data: CreatedIngressTransportData,
     },
@@ -335,6 +697,11 @@ pub enum MessageResponse {
     movementAnnouncement {
         data: HashMap<Uuid, NewDataConsumerOptions>,
     },
+    // last movement packet of each announced peer, encoded as on the data channel
+    #[serde(rename_all = "camelCase")]
+    movementSnapshot {
+        data: HashMap<Uuid, Vec<u8>>,
+    },
     #[serde(rename_all = "camelCase")]
     eventAnnouncement {
         data: HashMap<Uuid, NewDataConsumerOptions>,
@@ -344,6 +711,8 @@ pub enum MessageResponse {
         mode: String,
         region: String,
         load: f32,
+        draining: bool,
+        capacity: NodeCapacity,
     },
     #[serde(rename_all = "camelCase")]
     createRelayProducer {
@@ -369,6 +738,14 @@ pub enum MessageResponse {
     restartedIce {
         data: RestartedIceData,
     },
+    #[serde(rename_all = "camelCase")]
+    requestFailed {
+        requestId: Option<String>,
+        code: ErrorCode,
+        reason: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        limit: Option<Limit>,
+    },
 }
 #[derive(Serialize, Deserialize, Debug)]
 pub struct CreatedRelayProducerData {
@@ -408,10 +785,200 @@ pub struct JoinRoomData {
     pub ingress: Option<Uuid>,
     pub egress: Option<Uuid>,
 }
+// loudest producers of a room over the last interval, empty once it is silent
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AudioLevelsData {
+    pub roomName: String,
+    pub levels: Vec<AudioLevel>,
+}
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AudioLevel {
+    pub peerId: Uuid,
+    pub producerId: ProducerId,
+    // dBov from -127 to 0
+    pub volume: i8,
+}
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ActiveSpeakerData {
+    pub roomName: String,
+    pub peerId: Uuid,
+    pub producerId: ProducerId,
+}
+// Consumers of a peer paused, lowered or restored by the interest manager.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct InterestChangedData {
+    pub peerId: Uuid,
+    pub changes: Vec<InterestChange>,
+}
+#[derive(Serialize, Deserialize, Debug)]
+pub struct InterestChange {
+    pub consumerId: ConsumerId,
+    pub producerPeerId: Uuid,
+    pub kind: MediaKind,
+    pub state: InterestState,
+}
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterestState {
+    active,
+    lowered,
+    paused,
+}
+// A data channel of a peer went over its limits, was paused or is forwarded
+// again. `dropped` counts the messages dropped since the last notification.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DataProducerThrottledData {
+    pub peerId: Uuid,
+    pub dataProducerId: DataProducerId,
+    pub label: String,
+    pub state: ThrottleState,
+    pub dropped: u64,
+}
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThrottleState {
+    active,
+    throttled,
+    paused,
+}
+// Where the local process sends to (ingress) or receives from (egress).
+// rtpParameters are those of the consumer for an egress, of the producer for an
+// ingress.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CreatedPlainTransportData {
+    pub transportId: TransportId,
+    pub ip: IpAddr,
+    pub port: u16,
+    pub rtcpPort: Option<u16>,
+    pub producerId: ProducerId,
+    pub consumerId: Option<ConsumerId>,
+    pub rtpParameters: RtpParameters,
+}
+// Reports of mediasoup's get_stats(), as the worker returns them.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TransportStatsData {
+    pub peerId: Uuid,
+    pub transportId: TransportId,
+    pub stats: Vec<WebRtcTransportStat>,
+}
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ProducerStatsData {
+    pub producerId: ProducerId,
+    pub stats: Vec<ProducerStat>,
+}
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ConsumerStatsData {
+    pub consumerId: ConsumerId,
+    pub stats: ConsumerStats,
+}
+// Periodic summary of the WebRTC transport of every peer on this node.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PeerStatsData {
+    pub peers: Vec<PeerStat>,
+}
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct PeerStat {
+    pub peerId: Uuid,
+    pub transportId: TransportId,
+    pub iceState: IceState,
+    // bits per second, as seen by this node
+    pub recvBitrate: u32,
+    pub sendBitrate: u32,
+    pub availableOutgoingBitrate: Option<u32>,
+    pub packetLossReceived: Option<f64>,
+    pub packetLossSent: Option<f64>,
+}
+// Data producers of a bot peer, consumed with consumeEvents/consumeMovement.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CreatedBotPeerData {
+    pub peerId: Uuid,
+    pub eventsProducerId: DataProducerId,
+    pub movementProducerId: DataProducerId,
+}
+// A message a bot read from a data producer it consumes, an event as sent or
+// the base position/rotation of a movement packet.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BotReceivedData {
+    pub peerId: Uuid,
+    pub dataProducerId: DataProducerId,
+    pub event: Option<String>,
+    pub position: Option<[f32; 3]>,
+    pub rotation: Option<[f32; 4]>,
+}
+// One file per recorded producer. `packets` is only set once the recording
+// stopped.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RecordingData {
+    pub room: String,
+    pub recordingId: Uuid,
+    pub files: Vec<RecordingFile>,
+}
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RecordingFile {
+    pub peerId: Uuid,
+    pub producerId: ProducerId,
+    pub kind: MediaKind,
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub packets: Option<u64>,
+}
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Room {
     pub room: String,
 }
+// What this node carries and has left, sampled in the background for
+// serverLoad so signaling can place rooms and peers.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct NodeCapacity {
+    // percent of all cores of the host in use
+    pub cpu: f32,
+    pub workers: Vec<WorkerCapacity>,
+    // resident memory of this process, free and total memory of the host, in bytes
+    pub memoryUsed: u64,
+    pub memoryFree: u64,
+    pub memoryTotal: u64,
+    // bytes per second over every interface but loopback
+    pub rxBytesPerSec: u64,
+    pub txBytesPerSec: u64,
+    pub rooms: u32,
+    pub transports: u32,
+    pub consumers: u32,
+    // ports left in the worker rtc port range for pipe and plain transports
+    pub freePorts: u32,
+    // 0 when the node is full, 100 when idle
+    pub remaining: f32,
+}
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WorkerCapacity {
+    pub workerId: WorkerId,
+    // percent of one core used by the worker thread
+    pub cpu: f32,
+    pub routers: u32,
+    pub transports: u32,
+    pub consumers: u32,
+}
+// A room got its routers on this node.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RoomCreatedData {
+    pub room: String,
+    pub routers: Vec<RouterId>,
+}
+// A room and its routers are gone from this node, with the peers it still had
+// and how many seconds it lived.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RoomClosedData {
+    pub room: String,
+    pub reason: RoomClosedReason,
+    pub peers: Vec<Uuid>,
+    pub lifetime: u64,
+}
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoomClosedReason {
+    // destroyRouterGroup
+    destroyed,
+    // no transport for room_idle_timeout
+    idle,
+    // the workers of all its routers died
+    workerLost,
+}
 #[derive(Serialize, Deserialize, Debug)]
 pub struct CreatedIngressTransportData {
     pub id: TransportId,
@@ -451,7 +1018,7 @@ pub struct ProduceEventData {
     pub label: String,
     pub appData: appData,
 }
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct appData(pub HashMap<String, String>);
 impl appData {
     pub fn _get(&self, source: String) -> std::option::Option<std::string::String> {
@@ -495,98 +1062,246 @@ pub struct RestartedIceData {
 }
 
 // end of structs and enums
-pub struct ClientCodec;
+// Frames bigger than this are rejected unless configured otherwise.
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 8 * 1024 * 1024;
 
-impl Decoder for ClientCodec {
-    type Item = RequestMessage;
-    type Error = io::Error;
+fn frame_too_large(size: usize, max_frame_size: usize) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!(
+            "frame of {} bytes exceeds max frame size of {} bytes",
+            size, max_frame_size
+        ),
+    )
+}
 
-    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-        let size = {
-            if src.len() < 2 {
-                return Ok(None);
-            }
-            BigEndian::read_u16(src.as_ref()) as usize
-        };
-
-        if src.len() >= size + 2 {
-            let _ = src.split_to(2);
-            let buf = src.split_to(size);
-            Ok(Some(json::from_slice::<RequestMessage>(&buf)?))
-        } else {
-            Ok(None)
+// Wire encoding of frame payloads. Every frame written before signaling
+// selects another encoding is json, decoders accept both.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    #[default]
+    json,
+    msgpack,
+}
+
+impl Encoding {
+    // encodings this node can read and write, announced in registerMediaServer
+    pub fn supported() -> Vec<Encoding> {
+        vec![Encoding::json, Encoding::msgpack]
+    }
+
+    // json frames are objects, msgpack frames are maps and never start with '{'
+    fn detect(payload: &[u8]) -> Self {
+        match payload.iter().find(|b| !b.is_ascii_whitespace()) {
+            Some(b'{') => Encoding::json,
+            _ => Encoding::msgpack,
         }
     }
 }
 
-impl Encoder<ResponseMessage> for ClientCodec {
-    type Error = io::Error;
-    fn encode(&mut self, msg: ResponseMessage, dst: &mut BytesMut) -> Result<(), Self::Error> {
-        let msg = json::to_string(&msg).unwrap();
-        let msg_ref: &[u8] = msg.as_ref();
-
-        dst.reserve(msg_ref.len() + 2);
-        dst.put_u16(msg_ref.len() as u16);
-        dst.put(msg_ref);
+fn decode_payload(payload: &[u8]) -> Result<RequestMessage, String> {
+    match Encoding::detect(payload) {
+        Encoding::json => json::from_slice(payload).map_err(|e| e.to_string()),
+        Encoding::msgpack => {
+            // human readable keeps uuids and ids as strings, like in json
+            let mut de = rmp_serde::Deserializer::new(payload).with_human_readable();
+            RequestMessage::deserialize(&mut de).map_err(|e| e.to_string())
+        }
+    }
+}
 
-        Ok(())
+// Write `msg` after a u32 length, undoing the write if it fails.
+fn encode_frame(
+    msg: &ResponseMessage,
+    dst: &mut BytesMut,
+    max_frame_size: usize,
+    header_in_length: bool,
+    encoding: Encoding,
+) -> io::Result<()> {
+    let base = dst.len();
+    dst.extend_from_slice(&[0; 4]);
+    let res = match encoding {
+        Encoding::json => json::to_writer(dst.writer(), msg).map_err(|e| e.to_string()),
+        Encoding::msgpack => {
+            let mut ser = rmp_serde::Serializer::new(dst.writer())
+                .with_struct_map()
+                .with_human_readable();
+            msg.serialize(&mut ser).map_err(|e| e.to_string())
+        }
+    };
+    if let Err(e) = res {
+        dst.truncate(base);
+        return Err(io::Error::new(io::ErrorKind::InvalidData, e));
+    }
+    let size = dst.len() - base - 4;
+    if size > max_frame_size || size > u32::MAX as usize - 4 {
+        dst.truncate(base);
+        return Err(frame_too_large(size, max_frame_size));
     }
+    let len = if header_in_length { size + 4 } else { size };
+    (&mut dst[base..]).put_u32(len as u32);
+    Ok(())
 }
 
+// Length prefixed json or msgpack frames. A frame that is too large or does not parse
+// is logged and dropped, the frames after it are still decoded: returning an
+// error would end the whole FramedRead.
 #[derive(Debug, Copy, Clone)]
-pub struct ServerCodec {
+struct FrameDecoder {
+    // payload size of the frame being read
     size: Option<usize>,
+    // bytes of a rejected frame still to be skipped
+    discard: usize,
+    max_frame_size: usize,
+    // whether the length prefix counts its own 4 bytes
+    header_in_length: bool,
 }
 
-impl Default for ServerCodec {
-    fn default() -> Self {
-        Self { size: None }
+impl FrameDecoder {
+    fn new(max_frame_size: usize, header_in_length: bool) -> Self {
+        Self {
+            size: None,
+            discard: 0,
+            max_frame_size,
+            header_in_length,
+        }
     }
-}
-
-impl Decoder for ServerCodec {
-    type Item = RequestMessage;
-    type Error = io::Error;
 
-    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+    fn decode(&mut self, src: &mut BytesMut) -> Option<RequestMessage> {
         loop {
+            if self.discard > 0 {
+                let skip = self.discard.min(src.len());
+                src.advance(skip);
+                self.discard -= skip;
+                if self.discard > 0 {
+                    return None;
+                }
+            }
             match self.size {
                 Some(size) => {
-                    if src.len() >= size {
-                        let msg = serde_json::from_slice(&src[..size])?;
-                        src.advance(size);
-                        self.size = None;
-                        return Ok(Some(msg));
-                    } else {
-                        return Ok(None);
+                    if src.len() < size {
+                        src.reserve(size - src.len());
+                        return None;
+                    }
+                    let frame = src.split_to(size);
+                    self.size = None;
+                    match decode_payload(&frame) {
+                        Ok(msg) => return Some(msg),
+                        Err(e) => error!("dropping malformed frame of {} bytes: {}", size, e),
                     }
                 }
                 None => {
                     if src.len() < 4 {
-                        return Ok(None);
+                        return None;
                     }
-                    self.size = Some(BigEndian::read_u32(&src[..4]) as usize - 4);
+                    let len = BigEndian::read_u32(&src[..4]) as usize;
                     src.advance(4);
+                    let size = if self.header_in_length {
+                        match len.checked_sub(4) {
+                            Some(size) => size,
+                            None => {
+                                error!("dropping frame with invalid length {}", len);
+                                continue;
+                            }
+                        }
+                    } else {
+                        len
+                    };
+                    if size > self.max_frame_size {
+                        error!(
+                            "{}, dropping it",
+                            frame_too_large(size, self.max_frame_size)
+                        );
+                        self.discard = size;
+                        continue;
+                    }
+                    self.size = Some(size);
                 }
             }
         }
     }
 }
 
-impl Encoder<ResponseMessage> for ServerCodec {
+// u32 length of the json payload, followed by the payload.
+#[derive(Debug, Copy, Clone)]
+pub struct ClientCodec {
+    frame: FrameDecoder,
+}
+
+impl ClientCodec {
+    pub fn new(max_frame_size: usize) -> Self {
+        Self {
+            frame: FrameDecoder::new(max_frame_size, false),
+        }
+    }
+}
+
+impl Default for ClientCodec {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_FRAME_SIZE)
+    }
+}
+
+impl Decoder for ClientCodec {
+    type Item = RequestMessage;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        Ok(self.frame.decode(src))
+    }
+}
+
+impl Encoder<ResponseMessage> for ClientCodec {
     type Error = io::Error;
 
     fn encode(&mut self, msg: ResponseMessage, dst: &mut BytesMut) -> Result<(), Self::Error> {
-        let base = dst.len();
+        encode_frame(&msg, dst, self.frame.max_frame_size, false, Encoding::json)
+    }
+}
 
-        dst.extend_from_slice(&[0; 4]);
+// u32 length of the whole frame, header included, followed by the payload.
+#[derive(Debug, Clone)]
+pub struct ServerCodec {
+    frame: FrameDecoder,
+    // encoding of the frames this codec writes
+    encoding: Arc<Mut<Encoding>>,
+}
 
-        serde_json::to_writer(dst.writer(), &msg).unwrap();
+impl ServerCodec {
+    pub fn new(max_frame_size: usize) -> Self {
+        Self {
+            frame: FrameDecoder::new(max_frame_size, true),
+            encoding: Arc::new(Mut::new(Encoding::json)),
+        }
+    }
 
-        let len = (dst.len() - base) as u32;
+    // handle to switch the encoding once the codec is owned by a FramedWrite
+    pub fn encoding(&self) -> Arc<Mut<Encoding>> {
+        self.encoding.clone()
+    }
+}
 
-        (&mut dst[base..]).put_u32(len);
+impl Default for ServerCodec {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_FRAME_SIZE)
+    }
+}
 
-        Ok(())
+impl Decoder for ServerCodec {
+    type Item = RequestMessage;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        Ok(self.frame.decode(src))
+    }
+}
+
+impl Encoder<ResponseMessage> for ServerCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, msg: ResponseMessage, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let encoding = self.encoding.with(|e| *e);
+        encode_frame(&msg, dst, self.frame.max_frame_size, true, encoding)
     }
 }
@@ -1,4 +1,6 @@
 pub mod arg;
 pub mod codec;
+pub mod test_codec;
+pub mod test_worker_load;
 pub mod utils;
 pub mod worker_load;
@@ -0,0 +1,308 @@
+#[cfg(test)]
+mod tests {
+    use bytes::{BufMut, BytesMut};
+    use serde::Serialize;
+    use tokio::sync::mpsc::channel;
+    use tokio_util::codec::{Decoder, Encoder};
+
+    use crate::handlers::error::{HandlerError, Limit};
+    use crate::utils::codec::{
+        BotReceivedData, ClientCodec, Encoding, MessageRequest, MessageResponse, RequestMessage,
+        ResponseMessage, ServerCodec,
+    };
+    use crate::utils::utils::Responder;
+
+    const REQUEST: &str =
+        r#"{"wsid":"ws1","message":{"type":"destroyRouterGroup","data":{"room":"lobby"}}}"#;
+
+    // frame as sent by signaling: u32 length including the header, then json
+    fn server_frame(payload: &[u8]) -> Vec<u8> {
+        let mut frame = ((payload.len() + 4) as u32).to_be_bytes().to_vec();
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    fn assert_request(msg: Option<RequestMessage>) {
+        match msg {
+            Some(RequestMessage::Incoming {
+                wsid,
+                message: MessageRequest::destroyRouterGroup { data },
+                ..
+            }) => {
+                assert_eq!(wsid, "ws1");
+                assert_eq!(data.room, "lobby");
+            }
+            other => panic!("unexpected message {:?}", other),
+        }
+    }
+
+    fn response(room: &str) -> ResponseMessage {
+        ResponseMessage::OutgoingServer {
+            node: None,
+            requestId: None,
+            message: MessageResponse::requestFailed {
+                requestId: None,
+                code: crate::handlers::error::ErrorCode::notFound,
+                reason: room.to_string(),
+                limit: None,
+            },
+        }
+    }
+
+    #[test]
+    fn test_partial_frame() {
+        let mut codec = ServerCodec::default();
+        let frame = server_frame(REQUEST.as_bytes());
+        let mut buf = BytesMut::new();
+
+        buf.extend_from_slice(&frame[..2]);
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+        buf.extend_from_slice(&frame[2..10]);
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+        buf.extend_from_slice(&frame[10..]);
+        assert_request(codec.decode(&mut buf).unwrap());
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_oversize_frame_is_skipped() {
+        let mut codec = ServerCodec::new(REQUEST.len());
+        let oversize = vec![b'x'; REQUEST.len() + 1];
+        let mut buf = BytesMut::new();
+
+        // the oversize payload arrives in pieces and is never buffered whole
+        buf.extend_from_slice(&server_frame(&oversize)[..20]);
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+        assert!(buf.is_empty());
+        buf.extend_from_slice(&server_frame(&oversize)[20..]);
+        buf.extend_from_slice(&server_frame(REQUEST.as_bytes()));
+        assert_request(codec.decode(&mut buf).unwrap());
+    }
+
+    #[test]
+    fn test_oversize_frame_is_not_encoded() {
+        let mut codec = ServerCodec::new(16);
+        let mut buf = BytesMut::new();
+        buf.put_u8(7);
+
+        assert!(codec.encode(response("lobby"), &mut buf).is_err());
+        // a rejected message leaves the buffer as it was
+        assert_eq!(&buf[..], &[7]);
+
+        let mut codec = ServerCodec::default();
+        codec.encode(response("lobby"), &mut buf).unwrap();
+        let len = u32::from_be_bytes(buf[1..5].try_into().unwrap()) as usize;
+        assert_eq!(len, buf.len() - 1);
+    }
+
+    #[test]
+    fn test_garbage_frames_are_skipped() {
+        let mut codec = ServerCodec::default();
+        let mut buf = BytesMut::new();
+
+        // length smaller than its own header
+        buf.extend_from_slice(&2u32.to_be_bytes());
+        // well framed but not a request
+        buf.extend_from_slice(&server_frame(b"\x00\xffnot json"));
+        buf.extend_from_slice(&server_frame(br#"{"wsid":"ws1"}"#));
+        buf.extend_from_slice(&server_frame(REQUEST.as_bytes()));
+
+        assert_request(codec.decode(&mut buf).unwrap());
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_client_codec_frames_over_64k() {
+        let mut codec = ClientCodec::default();
+        let mut buf = BytesMut::new();
+        let room = "r".repeat(70_000);
+
+        codec.encode(response(&room), &mut buf).unwrap();
+        let len = u32::from_be_bytes(buf[..4].try_into().unwrap()) as usize;
+        assert_eq!(len, buf.len() - 4);
+        assert!(len > u16::MAX as usize);
+    }
+
+    #[test]
+    fn test_msgpack_and_json_frames_are_both_decoded() {
+        let mut codec = ServerCodec::default();
+        let request: RequestMessage = serde_json::from_str(REQUEST).unwrap();
+        let mut payload = vec![];
+        let mut ser = rmp_serde::Serializer::new(&mut payload)
+            .with_struct_map()
+            .with_human_readable();
+        request.serialize(&mut ser).unwrap();
+        let mut buf = BytesMut::new();
+        buf.extend_from_slice(&server_frame(&payload));
+        buf.extend_from_slice(&server_frame(REQUEST.as_bytes()));
+
+        assert_request(codec.decode(&mut buf).unwrap());
+        assert_request(codec.decode(&mut buf).unwrap());
+    }
+
+    #[test]
+    fn test_encoding_switch_applies_to_next_frame() {
+        let mut codec = ServerCodec::default();
+        let mut buf = BytesMut::new();
+        codec.encode(response("lobby"), &mut buf).unwrap();
+        assert_eq!(buf[4], b'{');
+
+        codec.encoding().with(|e| *e = Encoding::msgpack);
+        let mut buf = BytesMut::new();
+        codec.encode(response("lobby"), &mut buf).unwrap();
+        let value: serde_json::Value = rmp_serde::from_slice(&buf[4..]).unwrap();
+        assert_eq!(value["message"]["type"], "requestFailed");
+        assert_eq!(value["message"]["reason"], "lobby");
+    }
+
+    #[test]
+    fn test_drain_node_without_data() {
+        let mut codec = ServerCodec::default();
+        let mut buf = BytesMut::new();
+        buf.extend_from_slice(&server_frame(br#"{"message":{"type":"drainNode"}}"#));
+
+        match codec.decode(&mut buf).unwrap() {
+            Some(RequestMessage::IncomingServer {
+                message: MessageRequest::drainNode { data },
+                ..
+            }) => assert!(data.timeout.is_none()),
+            other => panic!("unexpected message {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_plain_ingress_with_comedia() {
+        let mut codec = ServerCodec::default();
+        let mut buf = BytesMut::new();
+        let request = br#"{"wsid":"ws1","message":{"type":"createPlainIngress","data":{"room":"lobby","peerId":"1f0e5a4c-8a4e-4b1a-9a4e-2f7c9f3b6d10","egress":"6a1d2c3e-4b5f-4a7b-8c9d-0e1f2a3b4c5d","kind":"audio","rtpParameters":{"codecs":[{"mimeType":"audio/opus","payloadType":101,"clockRate":48000,"channels":2,"parameters":{},"rtcpFeedback":[]}],"headerExtensions":[],"encodings":[{"ssrc":11111111}],"rtcp":{"cname":"ffmpeg","reducedSize":true}},"rtcpMux":true,"comedia":true}}}"#;
+        buf.extend_from_slice(&server_frame(request));
+
+        match codec.decode(&mut buf).unwrap() {
+            Some(RequestMessage::Incoming {
+                message: MessageRequest::createPlainIngress { data },
+                ..
+            }) => {
+                assert_eq!(data.room, "lobby");
+                assert!(data.comedia && data.ip.is_none() && data.port.is_none());
+                assert!(data.appData.0.is_empty());
+                assert_eq!(data.rtpParameters.codecs.len(), 1);
+            }
+            other => panic!("unexpected message {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_bot_send_without_movement() {
+        let mut codec = ServerCodec::default();
+        let mut buf = BytesMut::new();
+        let request = br#"{"wsid":"ws1","message":{"type":"botSend","data":{"peerId":"1f0e5a4c-8a4e-4b1a-9a4e-2f7c9f3b6d10","event":"{\"round\":2}"}}}"#;
+        buf.extend_from_slice(&server_frame(request));
+
+        match codec.decode(&mut buf).unwrap() {
+            Some(RequestMessage::Incoming {
+                message: MessageRequest::botSend { data },
+                ..
+            }) => {
+                assert_eq!(data.event.as_deref(), Some("{\"round\":2}"));
+                assert!(data.position.is_none() && data.rotation.is_none());
+            }
+            other => panic!("unexpected message {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_bot_consume_and_received() {
+        let mut codec = ServerCodec::default();
+        let mut buf = BytesMut::new();
+        let request = br#"{"wsid":"ws1","message":{"type":"botConsume","data":{"peerId":"1f0e5a4c-8a4e-4b1a-9a4e-2f7c9f3b6d10","dataProducerId":"6a1d2c3e-4b5f-4a7b-8c9d-0e1f2a3b4c5d"}}}"#;
+        buf.extend_from_slice(&server_frame(request));
+
+        let data = match codec.decode(&mut buf).unwrap() {
+            Some(RequestMessage::Incoming {
+                message: MessageRequest::botConsume { data },
+                ..
+            }) => data,
+            other => panic!("unexpected message {:?}", other),
+        };
+        let received = MessageResponse::botReceived {
+            data: BotReceivedData {
+                peerId: data.peerId,
+                dataProducerId: data.dataProducerId,
+                event: None,
+                position: Some([1.0, 0.0, 2.0]),
+                rotation: None,
+            },
+        };
+        let value = serde_json::to_value(&received).unwrap();
+        assert_eq!(value["type"], "botReceived");
+        assert_eq!(
+            value["data"]["dataProducerId"],
+            "6a1d2c3e-4b5f-4a7b-8c9d-0e1f2a3b4c5d"
+        );
+        assert_eq!(
+            value["data"]["position"],
+            serde_json::json!([1.0, 0.0, 2.0])
+        );
+        assert!(value["data"]["event"].is_null());
+    }
+
+    #[tokio::test]
+    async fn test_request_id_is_echoed_on_replies() {
+        let mut codec = ServerCodec::default();
+        let mut buf = BytesMut::new();
+        let request = br#"{"wsid":"ws1","requestId":"42","message":{"type":"destroyRouterGroup","data":{"room":"lobby"}}}"#;
+        buf.extend_from_slice(&server_frame(request));
+        let request_id = match codec.decode(&mut buf).unwrap() {
+            Some(RequestMessage::Incoming { requestId, .. }) => requestId,
+            other => panic!("unexpected message {:?}", other),
+        };
+        assert_eq!(request_id.as_deref(), Some("42"));
+
+        let (tx, mut rx) = channel(4);
+        let responder = Responder::new(tx, request_id, None);
+        responder.send(response("lobby")).await.unwrap();
+        let value = serde_json::to_value(rx.recv().await.unwrap()).unwrap();
+        assert_eq!(value["requestId"], "42");
+
+        let error = HandlerError::NotFound("cannot find room lobby".to_string());
+        responder.fail(Some("ws1".to_string()), None, &error).await;
+        let value = serde_json::to_value(rx.recv().await.unwrap()).unwrap();
+        assert_eq!(value["ws"], "ws1");
+        assert_eq!(value["requestId"], "42");
+        assert_eq!(
+            value["communication"],
+            serde_json::json!({
+                "type": "requestFailed",
+                "requestId": "42",
+                "code": "notFound",
+                "reason": "cannot find room lobby",
+            })
+        );
+
+        // replies to requests without an id carry none
+        let (tx, mut rx) = channel(4);
+        Responder::new(tx, None, None)
+            .fail(None, None, &error)
+            .await;
+        let value = serde_json::to_value(rx.recv().await.unwrap()).unwrap();
+        assert!(value.get("requestId").is_none());
+        assert!(value["message"]["requestId"].is_null());
+    }
+
+    #[test]
+    fn test_limit_reached_names_the_limit() {
+        let error = HandlerError::LimitReached(Limit::roomPeers, "lobby is full".to_string());
+        let failed = MessageResponse::requestFailed {
+            requestId: Some("7".to_string()),
+            code: error.code(),
+            reason: error.reason().to_string(),
+            limit: error.limit(),
+        };
+        let value = serde_json::to_value(&failed).unwrap();
+        assert_eq!(value["code"], "limitReached");
+        assert_eq!(value["limit"], "roomPeers");
+
+        let value = serde_json::to_value(response("lobby")).unwrap();
+        assert!(value["message"].get("limit").is_none());
+    }
+}
@@ -0,0 +1,59 @@
+#[cfg(test)]
+mod tests {
+    use mediasoup::{router::RouterId, worker::WorkerId};
+    use uuid::Uuid;
+
+    use crate::{config::config::LoadBalancing, models::sfu::Loads};
+
+    fn router_on_new_worker() -> (RouterId, Option<WorkerId>) {
+        let router_id = serde_json::from_value(serde_json::json!(Uuid::new_v4())).unwrap();
+        let worker_id = serde_json::from_value(serde_json::json!(Uuid::new_v4())).unwrap();
+        (router_id, Some(worker_id))
+    }
+
+    #[test]
+    fn test_round_robin_cycles_routers() {
+        let routers = vec![router_on_new_worker(), router_on_new_worker()];
+        let mut loads = Loads::new(LoadBalancing::RoundRobin);
+        assert_eq!(loads.select(&routers), Some(routers[0].0));
+        assert_eq!(loads.select(&routers), Some(routers[1].0));
+        assert_eq!(loads.select(&routers), Some(routers[0].0));
+        assert_eq!(loads.select(&[]), None);
+    }
+
+    #[test]
+    fn test_least_transports_picks_emptiest_worker() {
+        let routers = vec![router_on_new_worker(), router_on_new_worker()];
+        let mut loads = Loads::new(LoadBalancing::LeastTransports);
+        loads.add(routers[0].1.unwrap(), routers[0].0);
+        assert_eq!(loads.select(&routers), Some(routers[1].0));
+        loads.add(routers[1].1.unwrap(), routers[1].0);
+        loads.add(routers[1].1.unwrap(), routers[1].0);
+        assert_eq!(loads.select(&routers), Some(routers[0].0));
+    }
+
+    #[test]
+    fn test_least_cpu_falls_back_to_transport_count() {
+        let routers = vec![router_on_new_worker(), router_on_new_worker()];
+        let mut loads = Loads::new(LoadBalancing::LeastCpu);
+        loads.add(routers[0].1.unwrap(), routers[0].0);
+        assert_eq!(loads.select(&routers), Some(routers[1].0));
+        loads.add(routers[1].1.unwrap(), routers[1].0);
+        loads.workers.get_mut(&routers[1].1.unwrap()).unwrap().cpu = 40.0;
+        assert_eq!(loads.select(&routers), Some(routers[0].0));
+    }
+
+    #[test]
+    fn test_select_worker_picks_least_busy_worker() {
+        let routers = vec![router_on_new_worker(), router_on_new_worker()];
+        let workers: Vec<WorkerId> = routers.iter().map(|(_, w)| w.unwrap()).collect();
+        let mut loads = Loads::new(LoadBalancing::LeastCpu);
+        loads.add(workers[0], routers[0].0);
+        assert_eq!(loads.select_worker(&workers), Some(workers[1]));
+        loads.add(workers[1], routers[1].0);
+        loads.workers.get_mut(&workers[1]).unwrap().cpu = 80.0;
+        assert_eq!(loads.select_worker(&workers), Some(workers[0]));
+        assert_eq!(loads.select_worker(&workers[1..]), Some(workers[1]));
+        assert_eq!(loads.select_worker(&[]), None);
+    }
+}
@@ -1,63 +1,29 @@
-use std::{
-    net::{self, IpAddr, ToSocketAddrs},
-    str::FromStr,
-    sync::Mutex,
-    time::{SystemTime, UNIX_EPOCH},
-};
+use std::sync::{Arc, Mutex};
 
 use clap::Parser;
+use log::error;
+use tokio::sync::mpsc::{error::SendError, Sender};
 use uuid::Uuid;
 
-use crate::config::config::Config;
+use crate::{
+    config::config::{Config, ConfigFile},
+    handlers::error::HandlerError,
+    server::metrics::RequestTimer,
+    utils::codec::{MessageResponse, ResponseMessage},
+};
 
 use super::arg::Args;
 
-pub fn get_now_ms() -> u128 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_millis()
-}
-
-pub fn init() -> Config {
+// build the node config from the command line, environment and config file
+pub fn init() -> Result<Config, String> {
     let args = Args::parse();
-    let server_address = args.url.to_socket_addrs().unwrap();
-    let traversenat = args.traverse_nat;
-    let region = args.region;
-    let webrtc_port = args.port_transport;
-    let mut addr = net::SocketAddr::from_str("0.0.0.0:1188").unwrap();
-    for ip in server_address.into_iter() {
-        if ip.is_ipv4() {
-            addr = ip
-        } else {
-            println!("looking for ipv4");
-        }
-    }
-
-    let announceip = IpAddr::from_str(args.announceip.as_str()).unwrap();
-    //let addr = net::SocketAddr::from(server_address.unwrap().as_str()).unwrap();
-    let ingress = check_server(args.ingress.clone());
-    let egress = check_server(args.egress.clone());
-    let workers = args.workers;
-    Config::new(
-        ingress,
-        egress,
-        announceip,
-        addr.clone(),
-        workers,
-        region,
-        traversenat,
-        webrtc_port,
-    )
+    let file = match &args.config {
+        Some(path) => ConfigFile::read(path)?,
+        None => ConfigFile::default(),
+    };
+    Config::from_sources(args, file)
 }
 
-pub fn check_server(istrue: String) -> Option<Uuid> {
-    if istrue == "true" {
-        Some(uuid::Uuid::new_v4())
-    } else {
-        None
-    }
-}
 pub fn get_nodeid(ingress: Option<Uuid>, egress: Option<Uuid>) -> Option<Uuid> {
     if ingress != None {
         ingress
@@ -81,3 +47,58 @@ impl<T> Mut<T> {
         func(&mut *guard)
     }
 }
+
+// Sender handed to request handlers, stamps every reply with the id of the request it answers
+#[derive(Debug, Clone)]
+pub struct Responder {
+    sender: Sender<ResponseMessage>,
+    request_id: Option<String>,
+    timer: Option<Arc<RequestTimer>>,
+}
+
+impl Responder {
+    pub fn new(
+        sender: Sender<ResponseMessage>,
+        request_id: Option<String>,
+        timer: Option<RequestTimer>,
+    ) -> Self {
+        Self {
+            sender,
+            request_id,
+            timer: timer.map(Arc::new),
+        }
+    }
+
+    pub async fn send(&self, mut msg: ResponseMessage) -> Result<(), SendError<ResponseMessage>> {
+        msg.set_request_id(self.request_id.clone());
+        self.sender.send(msg).await
+    }
+
+    // report a failed request back to the client (ws) or to the signaling server (node)
+    pub async fn fail(&self, ws: Option<String>, node: Option<Uuid>, error: &HandlerError) {
+        if let Some(timer) = &self.timer {
+            timer.error();
+        }
+        let failed = MessageResponse::requestFailed {
+            requestId: self.request_id.clone(),
+            code: error.code(),
+            reason: error.reason().to_string(),
+            limit: error.limit(),
+        };
+        let msg = match ws {
+            Some(ws) => ResponseMessage::OutgoingCommunication {
+                ws: Some(ws),
+                requestId: None,
+                communication: failed,
+            },
+            None => ResponseMessage::OutgoingServer {
+                node,
+                requestId: None,
+                message: failed,
+            },
+        };
+        if let Err(e) = self.send(msg).await {
+            error!("error sending requestFailed: {:?}", e);
+        }
+    }
+}
@@ -1,21 +1,29 @@
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc, time::Duration};
 
-use mediasoup::router::RouterId;
-use tokio::sync::Mutex;
+use log::debug;
+use mediasoup::{router::RouterId, worker::WorkerId};
+use tokio::sync::{Mutex, RwLock};
 
 use crate::{
-    models::sfu::{Loads, RoomRouters, Routers2Worker},
-    IDX_COUNT,
+    handlers::cpu_load::get_thread_cpu_ticks,
+    models::{
+        peer::Peers,
+        room::Rooms,
+        sfu::{Loads, Routers2Worker, Workers},
+    },
+    utils::utils::Mut,
 };
 
+// how often worker cpu usage and consumer counts are refreshed
+pub const LOAD_SAMPLE_INTERVAL: Duration = Duration::from_secs(2);
+
 pub async fn get_less_loaded_router(
     router_network: String,
-    room_routers: Arc<Mutex<RoomRouters>>,
+    room_routers: Arc<Mutex<Rooms>>,
     routers2workers: Arc<Mutex<Routers2Worker>>,
     loads: Arc<Mutex<Loads>>,
 ) -> Option<RouterId> {
-    let idx = unsafe { IDX_COUNT };
-    let get_router = room_routers.lock().await.get(router_network);
+    let get_router = room_routers.lock().await.routers(&router_network);
     if get_router.is_none() {
         return None;
     }
@@ -24,20 +32,54 @@ pub async fn get_less_loaded_router(
         println!("no router");
         return None;
     };
-    let i = idx % get_router.len();
-    println!("current {:?}", i);
-    let router = &get_router[i];
-    if let Some(w) = routers2workers.lock().await.get(router.clone().id()) {
-        // println!("worker loads: {:?}", &self.loads);
-        {
-            let mut loads = loads.lock().await;
-            loads.add(w, router.id().clone());
-        }
-    } else {
-        println!("cannot find router2worker")
+    let candidates: Vec<(RouterId, Option<WorkerId>)> = {
+        let routers2workers = routers2workers.lock().await;
+        get_router
+            .iter()
+            .map(|r| (r.id(), routers2workers.get(r.id())))
+            .collect()
+    };
+    let mut loads = loads.lock().await;
+    let router_id = loads.select(&candidates)?;
+    match candidates.iter().find(|(r, _)| *r == router_id) {
+        Some((_, Some(w))) => loads.add(*w, router_id),
+        _ => println!("cannot find router2worker"),
     }
-    unsafe {
-        IDX_COUNT = idx.wrapping_add(1);
+    Some(router_id)
+}
+
+// Refresh the cpu usage of every worker thread and the number of consumers
+// living on each worker, forever.
+pub async fn sample_worker_loads(
+    workers: Arc<RwLock<Workers>>,
+    peers: Arc<Mut<Peers>>,
+    loads: Arc<Mutex<Loads>>,
+) {
+    let mut interval = tokio::time::interval(LOAD_SAMPLE_INTERVAL);
+    loop {
+        interval.tick().await;
+        let mut consumer_counts: HashMap<WorkerId, u32> = HashMap::new();
+        peers.with(|p| {
+            for peer in p.iter() {
+                for consumer in peer.consumers.iter().chain(peer.relay_consumers.iter()) {
+                    let worker_id = consumer.transport().router().worker().id();
+                    *consumer_counts.entry(worker_id).or_default() += 1;
+                }
+            }
+        });
+        let worker_ids: Vec<WorkerId> = workers.read().await.0.iter().map(|w| w.id()).collect();
+        let mut loads = loads.lock().await;
+        for worker_id in worker_ids.into_iter() {
+            let thread_id = loads._get(worker_id).and_then(|l| l.thread_id);
+            let cpu_ticks = thread_id.and_then(|t| match get_thread_cpu_ticks(t) {
+                Ok(ticks) => Some(ticks),
+                Err(e) => {
+                    debug!("cannot sample worker {} cpu: {}", worker_id, e);
+                    None
+                }
+            });
+            let consumers = consumer_counts.get(&worker_id).copied().unwrap_or(0);
+            loads.sample(worker_id, cpu_ticks, consumers);
+        }
     }
-    return Some(router.id());
 }